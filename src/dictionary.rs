@@ -1,102 +1,665 @@
+use flate2::read::GzDecoder;
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashSet,
-    fs::File,
-    io::{self, BufRead},
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::SystemTime,
 };
+use thiserror::Error;
 
-use crate::{DICTIONARY_FILE, MAX_WORD_LEN};
+use crate::{grid::is_word_char, DICTIONARY_FILE};
+
+/// The wordlists chosen on the command line, if any, highest priority first
+static SOURCES: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+/// The word frequency list chosen on the command line, if any
+static FREQUENCIES: OnceLock<PathBuf> = OnceLock::new();
+
+/// Set when `DICTIONARY` should be left empty rather than loaded
+static DISABLED: OnceLock<()> = OnceLock::new();
+
+/// Set when the wordlists chosen are known to hold one lowercase word per line
+static CANONICAL: OnceLock<()> = OnceLock::new();
 
 lazy_static! {
+    /// The dictionary is only loaded the first time a command needs it
     pub static ref DICTIONARY: Dictionary = {
-        println!("Loading dictionary from {}", DICTIONARY_FILE);
-        let mut dictionary = Dictionary::new(MAX_WORD_LEN);
-        let file = File::open(DICTIONARY_FILE);
-        if let Ok(file) = file {
-            let lines = io::BufReader::new(file).lines();
-            for line in lines {
-                if let Ok(word) = line {
-                    dictionary.insert(word);
-                }
+        if DISABLED.get().is_some() {
+            return Dictionary::disabled();
+        }
+        let default = [PathBuf::from(DICTIONARY_FILE)];
+        let paths = match SOURCES.get() {
+            Some(paths) if !paths.is_empty() => paths.as_slice(),
+            _ => &default,
+        };
+        let names: Vec<String> = paths.iter().map(|path| path.display().to_string()).collect();
+        // Stdout may be carrying a puzzle to the next command in a pipe, so progress goes to stderr
+        eprintln!("Loading dictionary from {}", names.join(", "));
+        let mut dictionary = Dictionary::load(paths, CANONICAL.get().is_some());
+        if let Err(e) = dictionary.check_loaded() {
+            eprintln!("{}", e);
+        }
+        if let Some(path) = FREQUENCIES.get() {
+            match File::open(path) {
+                Ok(file) => dictionary.load_frequencies(BufReader::new(file)),
+                Err(e) => eprintln!("Unable to load word frequencies from {}: {}", path.display(), e),
             }
         }
         dictionary
     };
 }
 
-pub struct Dictionary(Vec<HashSet<String>>);
+/// Load `DICTIONARY` from these wordlists instead of the default one. Only takes effect if called before the
+/// dictionary is first used.
+pub fn set_sources(paths: Vec<PathBuf>) {
+    let _ = SOURCES.set(paths);
+}
+
+/// Leave `DICTIONARY` empty instead of reading any wordlist, for work on the black squares alone, so that anything
+/// needing words fails its `Dictionary::check_loaded`. Like `set_sources`, this only takes effect before the dictionary
+/// is first used.
+pub fn disable() {
+    let _ = DISABLED.set(());
+}
+
+/// Load `DICTIONARY` trusting its wordlists to be in the form words are stored in, as in `Dictionary::load`. Like
+/// `set_sources`, this only takes effect before the dictionary is first used.
+pub fn set_canonical() {
+    let _ = CANONICAL.set(());
+}
+
+/// Rank `DICTIONARY`'s words by the frequency list at path, as in `Dictionary::load_frequencies`. Like `set_sources`,
+/// this only takes effect before the dictionary is first used.
+pub fn set_frequencies(path: PathBuf) {
+    let _ = FREQUENCIES.set(path);
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum DictionaryError {
+    #[error("The dictionary couldn't be loaded, so words can't be checked or suggested: {0}")]
+    NotLoaded(String),
+    #[error("The dictionary is turned off, so words can't be checked or suggested")]
+    Disabled,
+}
+
+/// A snapshot of a parsed wordlist, along with the modification time of the file it was built from
+#[derive(Serialize, Deserialize)]
+struct DictionaryCache {
+    source_modified: SystemTime,
+    dictionary: Dictionary,
+}
+
+/// Words bucketed by length, each mapped to the priority of the wordlist it came from, where 0 is the highest
+#[derive(Serialize, Deserialize)]
+pub struct Dictionary {
+    words: Vec<HashMap<String, usize>>,
+    /// How common each word is in some corpus, 1 being the most common. Ranks come from a separate frequency list, so
+    /// they're left out of the cache built from a wordlist.
+    #[serde(skip)]
+    ranks: HashMap<String, usize>,
+    /// The spaced form of each phrase, keyed by its letters run together as it's stored in `words`
+    phrases: HashMap<String, String>,
+    /// Why the wordlists couldn't be read, for a dictionary built by `load`
+    #[serde(skip)]
+    load_error: Option<String>,
+    /// Whether this is the empty stand-in for a dictionary that was turned off with `disable`
+    #[serde(skip)]
+    disabled: bool,
+    /// Whether the wordlists were trusted to be lowercase already rather than checked and lowercased as they were read
+    #[serde(skip)]
+    canonical: bool,
+}
+
 impl Dictionary {
-    fn new(size: usize) -> Self {
-        let mut dictionary: Vec<HashSet<String>> = Vec::new();
-        for _ in 0..size {
-            dictionary.push(HashSet::new());
+    /// Load a wordlist with one word per line, skipping any words with characters that can't be placed in a grid.
+    /// Words are stored lowercase, and a phrase like "ice cream" fills a slot spelling out its letters without the
+    /// spaces. A wordlist ending in `.gz` is decompressed as it's read.
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        Dictionary::read_file(path, false)
+    }
+
+    /// Read a wordlist with `from_canonical_reader` if canonical is set, or `from_reader` otherwise
+    fn read_file(path: &Path, canonical: bool) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let reader: Box<dyn BufRead> =
+            if path.extension().is_some_and(|extension| extension == "gz") {
+                Box::new(BufReader::new(GzDecoder::new(file)))
+            } else {
+                Box::new(BufReader::new(file))
+            };
+        match canonical {
+            true => Ok(Dictionary::from_canonical_reader(reader)),
+            false => Ok(Dictionary::from_reader(reader)),
+        }
+    }
+
+    /// Load a wordlist with one word per line from any reader, as in `from_file`
+    pub fn from_reader(reader: impl BufRead) -> Self {
+        let mut dictionary = Dictionary::new();
+        for word in reader.lines().map_while(Result::ok) {
+            if word
+                .split_whitespace()
+                .flat_map(str::chars)
+                .all(is_word_char)
+            {
+                dictionary.insert(word.to_lowercase());
+            }
+        }
+        dictionary
+    }
+
+    /// Load a wordlist that's known to hold one lowercase word per line, as `is_valid_canonical` expects, trusting it
+    /// rather than checking and lowercasing each line. Lines with spaces are still treated as phrases.
+    pub fn from_canonical_reader(reader: impl BufRead) -> Self {
+        let mut dictionary = Dictionary::new();
+        for word in reader.lines().map_while(Result::ok) {
+            dictionary.insert(word);
+        }
+        dictionary.canonical = true;
+        dictionary
+    }
+
+    /// Load several wordlists, most preferred first. Suggestions favor words from earlier lists, and a word is valid
+    /// if any list contains it.
+    pub fn from_files(paths: &[PathBuf]) -> io::Result<Self> {
+        Dictionary::read_files(paths, false)
+    }
+
+    fn read_files(paths: &[PathBuf], canonical: bool) -> io::Result<Self> {
+        let mut dictionary = Dictionary::new();
+        for (priority, path) in paths.iter().enumerate() {
+            let words = Dictionary::read_cached(path, canonical)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            dictionary.merge(words, priority);
         }
-        Dictionary(dictionary)
+        dictionary.canonical = canonical;
+        Ok(dictionary)
     }
 
+    /// Like `from_files`, but a wordlist that can't be read leaves the dictionary empty rather than failing, and
+    /// `check_loaded` reports why. Anything that relies on the words should check first, since an empty dictionary
+    /// would otherwise call every word made up. With canonical set, each wordlist is trusted to hold one lowercase word
+    /// per line and read with `from_canonical_reader`, which skips the per-line checks on a cache miss.
+    pub fn load(paths: &[PathBuf], canonical: bool) -> Self {
+        Dictionary::read_files(paths, canonical).unwrap_or_else(|e| Dictionary {
+            load_error: Some(e.to_string()),
+            ..Dictionary::new()
+        })
+    }
+
+    /// Whether the wordlists were loaded trusting they were lowercase already, as `from_canonical_reader` does
+    pub fn is_canonical(&self) -> bool {
+        self.canonical
+    }
+
+    /// Fail if the wordlists couldn't be read when the dictionary was built, or it was turned off
+    pub fn check_loaded(&self) -> Result<(), DictionaryError> {
+        if self.disabled {
+            return Err(DictionaryError::Disabled);
+        }
+        match &self.load_error {
+            Some(e) => Err(DictionaryError::NotLoaded(e.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Load a wordlist from the cache saved next to it, as long as the wordlist hasn't been modified since the cache
+    /// was written. Otherwise parse the wordlist and save a new cache for next time.
+    pub fn load_or_build_cache(path: &Path) -> io::Result<Self> {
+        Dictionary::read_cached(path, false)
+    }
+
+    fn read_cached(path: &Path, canonical: bool) -> io::Result<Self> {
+        let source_modified = fs::metadata(path)?.modified()?;
+        let cache_path = Dictionary::cache_path(path);
+        if let Ok(file) = File::open(&cache_path) {
+            let cache: Result<DictionaryCache, _> = bincode::deserialize_from(BufReader::new(file));
+            if let Ok(cache) = cache {
+                if cache.source_modified == source_modified {
+                    return Ok(cache.dictionary);
+                }
+            }
+        }
+
+        let cache = DictionaryCache {
+            source_modified,
+            dictionary: Dictionary::read_file(path, canonical)?,
+        };
+        // Failing to write the cache only costs speed on the next run, so it isn't an error
+        if let Ok(file) = File::create(&cache_path) {
+            let _ = bincode::serialize_into(BufWriter::new(file), &cache);
+        }
+        Ok(cache.dictionary)
+    }
+
+    fn cache_path(path: &Path) -> PathBuf {
+        let mut cache_path = path.as_os_str().to_owned();
+        cache_path.push(".cache");
+        PathBuf::from(cache_path)
+    }
+
+    fn new() -> Self {
+        Dictionary {
+            words: Vec::new(),
+            ranks: HashMap::new(),
+            phrases: HashMap::new(),
+            load_error: None,
+            disabled: false,
+            canonical: false,
+        }
+    }
+
+    fn disabled() -> Self {
+        Dictionary {
+            disabled: true,
+            ..Dictionary::new()
+        }
+    }
+
+    /// Add a word from the highest priority wordlist. A phrase is stored with its spaces removed, so that it matches
+    /// a slot like any other word, and its spaced form is kept for `display`.
     fn insert(&mut self, word: String) -> bool {
-        if let Some(map) = self.get_mut(word.len()) {
-            return map.insert(word);
+        let parts: Vec<&str> = word.split_whitespace().collect();
+        let key = parts.concat();
+        if parts.len() > 1 {
+            self.phrases
+                .entry(key.clone())
+                .or_insert_with(|| parts.join(" "));
         }
-        false
+        self.insert_with_priority(key, 0)
     }
 
-    fn get(&self, index: usize) -> Option<&HashSet<String>> {
-        self.0.get(index)
+    /// Words are bucketed by length in characters, the cells they fill, adding buckets as needed to fit the longest word
+    /// seen so far. A word that's already present keeps the highest priority it's been given.
+    fn insert_with_priority(&mut self, word: String, priority: usize) -> bool {
+        let len = word.chars().count();
+        if self.words.len() <= len {
+            self.words.resize_with(len + 1, HashMap::new);
+        }
+        match self.words[len].get_mut(&word) {
+            Some(existing) => {
+                *existing = (*existing).min(priority);
+                false
+            }
+            None => {
+                self.words[len].insert(word, priority);
+                true
+            }
+        }
     }
 
-    fn get_mut(&mut self, index: usize) -> Option<&mut HashSet<String>> {
-        self.0.get_mut(index)
+    /// Add every word from another dictionary at the given priority
+    fn merge(&mut self, other: Dictionary, priority: usize) {
+        for (key, phrase) in other.phrases {
+            self.phrases.entry(key).or_insert(phrase);
+        }
+        for words in other.words {
+            for (word, _) in words {
+                self.insert_with_priority(word, priority);
+            }
+        }
     }
 
-    pub fn is_valid(&self, word: &str) -> bool {
-        if let Some(map) = self.get(word.len()) {
-            return map.get(word).is_some();
+    /// Read a frequency list with a `word,rank` pair on each line, 1 being the most common word. Lines that don't
+    /// parse are skipped, and a word listed twice keeps its best rank.
+    pub fn load_frequencies(&mut self, reader: impl BufRead) {
+        for line in reader.lines().map_while(Result::ok) {
+            let Some((word, rank)) = line.split_once(',') else {
+                continue;
+            };
+            let Ok(rank) = rank.trim().parse::<usize>() else {
+                continue;
+            };
+            let word = word.trim().to_lowercase();
+            let rank = self
+                .ranks
+                .get(&word)
+                .map_or(rank, |&existing| existing.min(rank));
+            self.ranks.insert(word, rank);
         }
-        false
     }
 
+    /// The total number of words across every length
+    pub fn word_count(&self) -> usize {
+        self.words.iter().map(HashMap::len).sum()
+    }
+
+    /// The length of the longest word, or 0 if the dictionary is empty
+    pub fn longest(&self) -> usize {
+        self.words
+            .iter()
+            .rposition(|words| !words.is_empty())
+            .unwrap_or(0)
+    }
+
+    /// The number of words of each length that has any, shortest first
+    pub fn counts_by_length(&self) -> Vec<(usize, usize)> {
+        self.words
+            .iter()
+            .enumerate()
+            .filter(|(_, words)| !words.is_empty())
+            .map(|(len, words)| (len, words.len()))
+            .collect()
+    }
+
+    fn get(&self, index: usize) -> Option<&HashMap<String, usize>> {
+        self.words.get(index)
+    }
+
+    /// How a word is best shown: with its spaces if it's a phrase, or as given otherwise
+    pub fn display(&self, word: &str) -> String {
+        self.phrases
+            .get(&word.to_lowercase())
+            .cloned()
+            .unwrap_or_else(|| word.to_string())
+    }
+
+    /// Whether the word is in the dictionary, ignoring case and any spaces
+    pub fn is_valid(&self, word: &str) -> bool {
+        let word: String = word.split_whitespace().collect::<String>().to_lowercase();
+        self.is_valid_canonical(&word)
+    }
+
+    /// Like `is_valid`, but for a word that's already lowercase with no spaces, the form words are stored in, so it can
+    /// be looked up without building a normalized copy. That saves an allocation per lookup, which adds up when
+    /// checking many candidate words; a word in any other form is never found.
+    pub fn is_valid_canonical(&self, word: &str) -> bool {
+        self.get(word.chars().count())
+            .is_some_and(|words| words.contains_key(word))
+    }
+
+    /// Whether every word is in the dictionary, ignoring case
+    pub fn all_valid<S: AsRef<str>>(&self, words: &[S]) -> bool {
+        words.iter().all(|word| self.is_valid(word.as_ref()))
+    }
+
+    /// The words that aren't in the dictionary, in the order given and as they were written
+    pub fn which_invalid<S: AsRef<str>>(&self, words: &[S]) -> Vec<String> {
+        words
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|word| !self.is_valid(word))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Up to count words that fit the partial word, with phrases shown spaced out. The other suggestions give phrases
+    /// run together, ready to be filled into the grid.
     pub fn suggest_words(&self, partial_word: SparseWord, count: usize) -> Vec<String> {
+        self.suggest_words_excluding(partial_word, count, &HashSet::new())
+            .iter()
+            .map(|word| self.display(word))
+            .collect()
+    }
+
+    /// How many words fit the partial word, without collecting them. A quick measure of how open a slot is.
+    pub fn count_matches(&self, partial_word: &SparseWord) -> usize {
+        self.get(partial_word.len()).map_or(0, |words| {
+            words
+                .keys()
+                .filter(|word| partial_word.matches(word))
+                .count()
+        })
+    }
+
+    /// Like `suggest_words`, but never suggesting any of the (lowercase) words in exclude
+    pub fn suggest_words_excluding(
+        &self,
+        partial_word: SparseWord,
+        count: usize,
+        exclude: &HashSet<String>,
+    ) -> Vec<String> {
+        match self.get(partial_word.len()) {
+            Some(words) => best_matches(
+                words,
+                |word| partial_word.matches(word) && !exclude.contains(word),
+                count,
+            ),
+            None => Vec::new(),
+        }
+    }
+
+    /// Like `suggest_words_excluding`, but favoring the most common words according to the loaded frequency list
+    /// rather than the wordlist they came from. Words without a rank come last, alphabetically.
+    pub fn suggest_words_by_frequency(
+        &self,
+        partial_word: SparseWord,
+        count: usize,
+        exclude: &HashSet<String>,
+    ) -> Vec<String> {
+        let Some(words) = self.get(partial_word.len()) else {
+            return Vec::new();
+        };
+        let mut found: Vec<(Option<usize>, &String)> = words
+            .keys()
+            .filter(|word| partial_word.matches(word) && !exclude.contains(*word))
+            .map(|word| (self.ranks.get(word).copied(), word))
+            .collect();
+        // Unranked words sort after every ranked one
+        found.sort_by_key(|&(rank, word)| (rank.is_none(), rank, word));
+        found
+            .into_iter()
+            .take(count)
+            .map(|(_, word)| word.clone())
+            .collect()
+    }
+
+    /// Suggest words of the right length that break at most max_mismatches of the partial word's constraints, fewest
+    /// mismatches first. Useful when no word fits exactly and a crossing letter might be wrong.
+    pub fn suggest_near(
+        &self,
+        partial_word: SparseWord,
+        count: usize,
+        max_mismatches: usize,
+    ) -> Vec<String> {
+        let Some(words) = self.get(partial_word.len()) else {
+            return Vec::new();
+        };
+        let mut found: Vec<(usize, usize, &String)> = words
+            .iter()
+            .filter_map(|(word, &priority)| {
+                let mismatches = partial_word.mismatches(word);
+                (mismatches <= max_mismatches).then_some((mismatches, priority, word))
+            })
+            .collect();
+        found.sort();
+        found
+            .into_iter()
+            .take(count)
+            .map(|(_, _, word)| word.clone())
+            .collect()
+    }
+
+    /// Suggest words of any length matching a pattern, where `?` stands for a single letter and `*` for any run of
+    /// letters, e.g. "qu*" for every word starting with "qu". Shorter words are suggested first, and phrases are shown
+    /// spaced out.
+    pub fn suggest_by_pattern(&self, pattern: &str, count: usize) -> Vec<String> {
+        let regex = pattern_regex(pattern);
         let mut suggestions = Vec::new();
-        let correct_len = self.get(partial_word.len());
-        if let Some(words) = correct_len {
-            for word in words {
-                if partial_word.matches(word) {
-                    suggestions.push(word.clone())
-                }
-                if suggestions.len() >= count {
-                    return suggestions;
+        for words in self.words.iter() {
+            if suggestions.len() >= count {
+                break;
+            }
+            let remaining = count - suggestions.len();
+            suggestions.extend(best_matches(words, |word| regex.is_match(word), remaining));
+        }
+        suggestions.iter().map(|word| self.display(word)).collect()
+    }
+
+    /// How many words match a pattern as in `suggest_by_pattern`, for each length that has any, shortest first. Like
+    /// `count_matches` for each possible slot length, it shows which lengths leave enough options.
+    pub fn pattern_counts_by_length(&self, pattern: &str) -> Vec<(usize, usize)> {
+        let regex = pattern_regex(pattern);
+        self.words
+            .iter()
+            .enumerate()
+            .map(|(len, words)| {
+                (
+                    len,
+                    words.keys().filter(|word| regex.is_match(word)).count(),
+                )
+            })
+            .filter(|&(_, count)| count > 0)
+            .collect()
+    }
+}
+
+/// Up to count of the words for which `matches` returns true, favoring higher priority words and then alphabetical
+/// order. The search stops early once there are enough matches from the highest priority wordlist, so asking for every
+/// match (a count of `usize::MAX`) always returns them in the same order.
+fn best_matches(
+    words: &HashMap<String, usize>,
+    matches: impl Fn(&str) -> bool,
+    count: usize,
+) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut top_priority = 0;
+    for (word, &priority) in words {
+        if matches(word) {
+            found.push((priority, word));
+            if priority == 0 {
+                top_priority += 1;
+                if top_priority >= count {
+                    break;
                 }
             }
         }
-        suggestions
+    }
+    found.sort();
+    found
+        .into_iter()
+        .take(count)
+        .map(|(_, word)| word.clone())
+        .collect()
+}
+
+/// Build a case-insensitive regex matching whole words against a pattern of letters and `?`/`*` wildcards, where a
+/// group of letters in brackets like `[aei]` matches any one of them
+fn pattern_regex(pattern: &str) -> Regex {
+    let mut body = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '?' => body.push_str(&Constraint::Any.regex()),
+            '*' => body.push_str(".*"),
+            '[' => {
+                let group: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                body.push_str(&Constraint::AnyOf(group.chars().collect()).regex());
+            }
+            c => body.push_str(&Constraint::Exact(c).regex()),
+        }
+    }
+    Regex::new(&format!("(?i)^{}$", body)).expect("Unable to build regex")
+}
+
+/// What a single position in a word may hold
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constraint {
+    Any,
+    Exact(char),
+    /// Any one of these letters, like the vowels when a crossing needs one
+    AnyOf(Vec<char>),
+}
+
+impl Constraint {
+    /// Whether the letter satisfies the constraint, ignoring case
+    fn allows(&self, letter: char) -> bool {
+        let same = |c: &char| c.to_lowercase().eq(letter.to_lowercase());
+        match self {
+            Constraint::Any => true,
+            Constraint::Exact(c) => same(c),
+            Constraint::AnyOf(chars) => chars.iter().any(same),
+        }
+    }
+
+    fn regex(&self) -> String {
+        match self {
+            Constraint::Any => ".".to_string(),
+            Constraint::Exact(c) => regex::escape(&c.to_string()),
+            // An empty class isn't valid regex syntax, so use one that can't match anything
+            Constraint::AnyOf(chars) if chars.is_empty() => r"[^\s\S]".to_string(),
+            Constraint::AnyOf(chars) => {
+                let class: String = chars
+                    .iter()
+                    .map(|c| regex::escape(&c.to_string()))
+                    .collect();
+                format!("[{}]", class)
+            }
+        }
+    }
+}
+
+impl From<Option<char>> for Constraint {
+    fn from(letter: Option<char>) -> Self {
+        letter.map_or(Constraint::Any, Constraint::Exact)
     }
 }
 
 #[derive(Debug)]
 pub struct SparseWord {
     regex: Regex,
+    constraints: Vec<Constraint>,
     len: usize,
 }
 impl SparseWord {
     pub fn new(vec: Vec<Option<char>>) -> Self {
-        let len = vec.len();
-        // Build a case-insensitive regex of the form "..a..cd.."
-        let regex = Regex::new(&vec.iter().fold("(?i)".to_string(), |acc, arg| {
-            format!("{}{}", acc, arg.map_or('.', |x| x))
-        }))
-        .expect("Unable to build regex");
-        SparseWord { regex, len }
+        SparseWord::from_constraints(vec.into_iter().map(Constraint::from).collect())
+    }
+
+    /// Build a case-insensitive regex of the form "..a[ei].cd..", escaping punctuation like hyphens
+    pub fn from_constraints(constraints: Vec<Constraint>) -> Self {
+        let len = constraints.len();
+        let regex = constraints
+            .iter()
+            .fold("(?i)".to_string(), |acc, constraint| {
+                acc + &constraint.regex()
+            });
+        let regex = Regex::new(&regex).expect("Unable to build regex");
+        SparseWord {
+            regex,
+            constraints,
+            len,
+        }
     }
 
-    fn len(&self) -> usize {
+    pub fn len(&self) -> usize {
         self.len
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The word spelled out by the constraints, if every position is an exact letter
+    pub fn as_complete(&self) -> Option<String> {
+        self.constraints
+            .iter()
+            .map(|constraint| match constraint {
+                Constraint::Exact(letter) => Some(*letter),
+                _ => None,
+            })
+            .collect()
+    }
+
     fn matches(&self, word: &str) -> bool {
         self.regex.is_match(word)
     }
+
+    /// The number of positions where the word breaks a constraint, counting any difference in length as mismatches
+    fn mismatches(&self, word: &str) -> usize {
+        let broken = self
+            .constraints
+            .iter()
+            .zip(word.chars())
+            .filter(|(constraint, letter)| !constraint.allows(*letter))
+            .count();
+        broken + self.len.abs_diff(word.chars().count())
+    }
 }
 
 impl PartialEq for SparseWord {
@@ -107,10 +670,314 @@ impl PartialEq for SparseWord {
 
 #[cfg(test)]
 mod tests {
-    use crate::dictionary::SparseWord;
+    use std::{
+        collections::HashSet,
+        fs::{self, File},
+        io::{BufReader, Write},
+        time::{Duration, SystemTime},
+    };
+
+    use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+    use crate::dictionary::{Constraint, Dictionary, DictionaryError, SparseWord};
 
     use super::DICTIONARY;
 
+    #[test]
+    fn canonical_lookup() {
+        let dictionary = Dictionary::from_canonical_reader("cat\nice cream\n".as_bytes());
+        assert!(dictionary.is_canonical());
+        assert!(dictionary.is_valid_canonical("cat"));
+        assert!(dictionary.is_valid_canonical("icecream"));
+        assert!(!dictionary.is_valid_canonical("CAT"));
+        assert!(!dictionary.is_valid_canonical("ice cream"));
+        assert!(dictionary.is_valid("CAT") && dictionary.is_valid("ice cream"));
+
+        // Loading canonically trusts the wordlist as it is, so a capitalized line is kept as written
+        let dir = std::env::temp_dir().join("crossword-builder-canonical-test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("words.txt");
+        fs::write(&path, "cat\nDog\n").unwrap();
+        let _ = fs::remove_file(dir.join("words.txt.cache"));
+        let dictionary = Dictionary::load(std::slice::from_ref(&path), true);
+        assert!(dictionary.is_canonical());
+        assert!(dictionary.is_valid_canonical("cat") && dictionary.is_valid_canonical("Dog"));
+        assert!(!dictionary.is_valid("dog"));
+        assert!(!Dictionary::from_file(&path).unwrap().is_canonical());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn phrases() {
+        let dictionary =
+            Dictionary::from_reader("ice cream\nicebergs\nhot  dog\nno way!\n".as_bytes());
+        assert_eq!(dictionary.counts_by_length(), vec![(6, 1), (8, 2)]);
+        assert!(dictionary.is_valid("ICECREAM") && dictionary.is_valid("Ice Cream"));
+        assert!(!dictionary.is_valid("noway"));
+
+        let slot = SparseWord::new(vec![
+            Some('I'),
+            Some('C'),
+            Some('E'),
+            Some('C'),
+            None,
+            None,
+            None,
+            None,
+        ]);
+        assert_eq!(dictionary.suggest_words(slot, 5), vec!["ice cream"]);
+        assert_eq!(dictionary.suggest_by_pattern("hot*", 5), vec!["hot dog"]);
+        assert_eq!(dictionary.display("HOTDOG"), "hot dog");
+        assert_eq!(dictionary.display("icebergs"), "icebergs");
+    }
+
+    #[test]
+    fn accented_words() {
+        let dictionary = Dictionary::from_reader("café\ncafe\ncat\nnaïve\n".as_bytes());
+        assert_eq!(dictionary.counts_by_length(), vec![(3, 1), (4, 2), (5, 1)]);
+        assert!(dictionary.is_valid("CAFÉ") && dictionary.is_valid_canonical("naïve"));
+
+        let slot = SparseWord::new(vec![Some('C'), Some('A'), Some('F'), None]);
+        assert_eq!(dictionary.count_matches(&slot), 2);
+        assert_eq!(dictionary.suggest_words(slot, 5), vec!["cafe", "café"]);
+        assert_eq!(
+            dictionary.suggest_by_pattern("*", 5),
+            vec!["cat", "cafe", "café", "naïve"]
+        );
+    }
+
+    #[test]
+    fn count_matches() {
+        for pattern in [
+            vec![Some('A'), None, Some('T')],
+            vec![None, None, None, None],
+            vec![Some('Q'), Some('X'), None],
+            vec![None; 40],
+        ] {
+            assert_eq!(
+                DICTIONARY.count_matches(&SparseWord::new(pattern.clone())),
+                DICTIONARY
+                    .suggest_words(SparseWord::new(pattern), usize::MAX)
+                    .len()
+            );
+        }
+    }
+
+    #[test]
+    fn suggest_by_frequency() {
+        let mut dictionary = Dictionary::from_reader("cat\ncot\ncut\nact\n".as_bytes());
+        dictionary.load_frequencies("cut,1\nCAT,20\nnot a rank\ncat,2\n".as_bytes());
+        let pattern = || SparseWord::new(vec![Some('c'), None, Some('t')]);
+        assert_eq!(
+            dictionary.suggest_words_by_frequency(pattern(), 5, &HashSet::new()),
+            vec!["cut", "cat", "cot"]
+        );
+        let exclude = HashSet::from(["cut".to_string()]);
+        assert_eq!(
+            dictionary.suggest_words_by_frequency(pattern(), 1, &exclude),
+            vec!["cat"]
+        );
+    }
+
+    #[test]
+    fn missing_wordlist() {
+        let path = std::env::temp_dir().join("crossword-builder-missing-wordlist.txt");
+        let _ = fs::remove_file(&path);
+        let dictionary = Dictionary::load(std::slice::from_ref(&path), false);
+        assert_eq!(dictionary.word_count(), 0);
+        match dictionary.check_loaded() {
+            Err(DictionaryError::NotLoaded(reason)) => {
+                assert!(reason.contains("crossword-builder-missing-wordlist.txt"))
+            }
+            other => panic!("expected NotLoaded, got {:?}", other),
+        }
+        assert_eq!(DICTIONARY.check_loaded(), Ok(()));
+    }
+
+    #[test]
+    fn disabled() {
+        let dictionary = Dictionary::disabled();
+        assert_eq!(dictionary.word_count(), 0);
+        assert_eq!(dictionary.check_loaded(), Err(DictionaryError::Disabled));
+        assert!(!dictionary.is_valid("cat"));
+    }
+
+    #[test]
+    fn load_from_cache() {
+        let dir = std::env::temp_dir().join("crossword-builder-cache-test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("words.txt");
+        let cache_path = dir.join("words.txt.cache");
+        let _ = fs::remove_file(&cache_path);
+
+        fs::write(&path, "cat\ndog\n").unwrap();
+        let dictionary = Dictionary::load_or_build_cache(&path).unwrap();
+        assert!(dictionary.is_valid("cat"));
+        assert!(cache_path.exists());
+
+        // The cache is used while the wordlist's modification time is unchanged
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        fs::write(&path, "cat\n").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let dictionary = Dictionary::load_or_build_cache(&path).unwrap();
+        assert!(dictionary.is_valid("dog"));
+
+        // And rebuilt once the wordlist changes
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        let dictionary = Dictionary::load_or_build_cache(&path).unwrap();
+        assert!(dictionary.is_valid("cat"));
+        assert!(!dictionary.is_valid("dog"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn gzipped_wordlist() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"cat\nDog\nr2d2\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let dictionary = Dictionary::from_reader(BufReader::new(GzDecoder::new(&compressed[..])));
+        assert!(dictionary.is_valid("cat"));
+        assert!(dictionary.is_valid("dog"));
+        assert!(!dictionary.is_valid("r2d2"));
+
+        let dir = std::env::temp_dir().join("crossword-builder-gzip-test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("words.txt.gz");
+        fs::write(&path, &compressed).unwrap();
+        let dictionary = Dictionary::from_file(&path).unwrap();
+        assert_eq!(dictionary.word_count(), 2);
+        assert!(dictionary.is_valid("dog"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hyphenated_words() {
+        let dir = std::env::temp_dir().join("crossword-builder-hyphen-test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("words.txt");
+        fs::write(&path, "well-being\no'er\nr2d2\ncat\n").unwrap();
+
+        let dictionary = Dictionary::from_file(&path).unwrap();
+        assert!(dictionary.is_valid("well-being"));
+        assert!(dictionary.is_valid("o'er"));
+        assert!(dictionary.is_valid("cat"));
+        assert!(!dictionary.is_valid("r2d2"));
+        assert!(!dictionary.is_valid("wellbeing"));
+
+        let suggestions = dictionary.suggest_words(
+            SparseWord::new(vec![
+                Some('W'),
+                None,
+                None,
+                None,
+                Some('-'),
+                None,
+                None,
+                None,
+                None,
+                None,
+            ]),
+            10,
+        );
+        assert_eq!(suggestions, vec!["well-being"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn counts() {
+        let mut dictionary = Dictionary::new();
+        assert_eq!(dictionary.word_count(), 0);
+        assert_eq!(dictionary.longest(), 0);
+        assert!(dictionary.counts_by_length().is_empty());
+
+        for word in ["cat", "dog", "ox", "elephant", "cat"] {
+            dictionary.insert(word.to_string());
+        }
+        assert_eq!(dictionary.word_count(), 4);
+        assert_eq!(dictionary.longest(), 8);
+        assert_eq!(dictionary.counts_by_length(), vec![(2, 1), (3, 2), (8, 1)]);
+    }
+
+    #[test]
+    fn priority() {
+        let dir = std::env::temp_dir().join("crossword-builder-priority-test");
+        fs::create_dir_all(&dir).unwrap();
+        let curated = dir.join("curated.txt");
+        let fallback = dir.join("fallback.txt");
+        fs::write(&curated, "zap\nzip\n").unwrap();
+        fs::write(&fallback, "zag\nzap\nzed\nzit\nzoo\n").unwrap();
+
+        let dictionary = Dictionary::from_files(&[curated, fallback]).unwrap();
+        assert!(dictionary.is_valid("zip"));
+        assert!(dictionary.is_valid("zoo"));
+        assert!(!dictionary.is_valid("zen"));
+
+        for _ in 0..10 {
+            let mut suggestions =
+                dictionary.suggest_words(SparseWord::new(vec![Some('Z'), None, None]), 3);
+            let fallback_word = suggestions.pop().unwrap();
+            suggestions.sort();
+            assert_eq!(suggestions, vec!["zap", "zip"]);
+            assert!(["zag", "zed", "zit", "zoo"].contains(&fallback_word.as_str()));
+        }
+        assert_eq!(dictionary.suggest_by_pattern("zi?", 1), vec!["zip"]);
+        assert_eq!(dictionary.suggest_by_pattern("*", 7).len(), 6);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn long_words() {
+        let mut dictionary = Dictionary::new();
+        let long = "pneumonoultramicroscopicsilicovolcanoconiosis";
+        let thirty_one = "abcdefghijklmnopqrstuvwxyzabcde";
+        assert!(dictionary.insert(long.to_string()));
+        assert!(dictionary.insert(thirty_one.to_string()));
+        assert!(dictionary.insert("cat".to_string()));
+        assert!(dictionary.is_valid(long));
+        assert!(dictionary.is_valid(thirty_one));
+        assert!(dictionary.is_valid("cat"));
+        assert!(!dictionary.is_valid("dog"));
+
+        let mut pattern = vec![None; 31];
+        pattern[0] = Some('A');
+        assert_eq!(
+            dictionary.suggest_words(SparseWord::new(pattern), 5),
+            vec![thirty_one]
+        );
+    }
+
+    #[test]
+    fn batch_validity() {
+        let words: Vec<String> = DICTIONARY.words[5]
+            .keys()
+            .take(10_000)
+            .map(|word| word.to_uppercase())
+            .collect();
+        assert_eq!(words.len(), 10_000);
+        assert!(DICTIONARY.all_valid(&words));
+        assert!(DICTIONARY.which_invalid(&words).is_empty());
+
+        let mixed = ["Cat", "QXZV", "dog", "zzzq"];
+        assert!(!DICTIONARY.all_valid(&mixed));
+        assert_eq!(DICTIONARY.which_invalid(&mixed), vec!["QXZV", "zzzq"]);
+        assert!(DICTIONARY.all_valid::<&str>(&[]));
+    }
+
     #[test]
     fn suggest_one() {
         let suggestions =
@@ -121,6 +988,82 @@ mod tests {
         assert_eq!(suggestions, vec!["act"]);
     }
 
+    #[test]
+    fn suggest_excluding() {
+        let mut dictionary = Dictionary::new();
+        for word in ["zap", "zip", "zit"] {
+            dictionary.insert(word.to_string());
+        }
+        let exclude = HashSet::from(["zap".to_string(), "zit".to_string()]);
+        let suggestions = dictionary.suggest_words_excluding(
+            SparseWord::new(vec![Some('Z'), None, None]),
+            5,
+            &exclude,
+        );
+        assert_eq!(suggestions, vec!["zip"]);
+    }
+
+    #[test]
+    fn constraints() {
+        let mut dictionary = Dictionary::new();
+        for word in ["bat", "bet", "bit", "but", "b-t"] {
+            dictionary.insert(word.to_string());
+        }
+        let vowel = SparseWord::from_constraints(vec![
+            Constraint::Exact('B'),
+            Constraint::AnyOf(vec!['a', 'E', 'i']),
+            Constraint::Any,
+        ]);
+        let mut suggestions = dictionary.suggest_words(vowel, 10);
+        suggestions.sort();
+        assert_eq!(suggestions, vec!["bat", "bet", "bit"]);
+
+        let hyphen = SparseWord::from_constraints(vec![
+            Constraint::Any,
+            Constraint::AnyOf(vec!['-', 'u']),
+            Constraint::Exact('t'),
+        ]);
+        let mut suggestions = dictionary.suggest_words(hyphen, 10);
+        suggestions.sort();
+        assert_eq!(suggestions, vec!["b-t", "but"]);
+
+        let nothing = SparseWord::from_constraints(vec![
+            Constraint::Any,
+            Constraint::AnyOf(vec![]),
+            Constraint::Any,
+        ]);
+        assert!(dictionary.suggest_words(nothing, 10).is_empty());
+
+        let mut suggestions = dictionary.suggest_by_pattern("b[ie]?", 10);
+        suggestions.sort();
+        assert_eq!(suggestions, vec!["bet", "bit"]);
+    }
+
+    #[test]
+    fn suggest_near() {
+        let mut dictionary = Dictionary::new();
+        for word in ["cat", "cot", "cog", "dog", "ace"] {
+            dictionary.insert(word.to_string());
+        }
+        let word = || SparseWord::new(vec![Some('C'), Some('U'), Some('T')]);
+        assert!(dictionary.suggest_words(word(), 5).is_empty());
+        assert!(dictionary.suggest_near(word(), 5, 0).is_empty());
+        assert_eq!(dictionary.suggest_near(word(), 5, 1), vec!["cat", "cot"]);
+        assert_eq!(
+            dictionary.suggest_near(word(), 5, 2),
+            vec!["cat", "cot", "cog"]
+        );
+        assert_eq!(dictionary.suggest_near(word(), 3, 3).len(), 3);
+        assert_eq!(dictionary.suggest_near(word(), 10, 3).len(), 5);
+
+        let vowel = SparseWord::from_constraints(vec![
+            Constraint::Exact('d'),
+            Constraint::AnyOf(vec!['A', 'E']),
+            Constraint::Any,
+        ]);
+        assert_eq!(dictionary.suggest_near(vowel, 5, 1), vec!["cat", "dog"]);
+    }
+
     #[test]
     fn suggest_ten() {
         let suggestions = DICTIONARY.suggest_words(
@@ -130,6 +1073,15 @@ mod tests {
         assert_eq!(suggestions.len(), 10);
     }
 
+    #[test]
+    fn suggest_all() {
+        let partial = || SparseWord::new(vec![Some('A'), None, None, None, Some('T')]);
+        let suggestions = DICTIONARY.suggest_words(partial(), usize::MAX);
+        assert!(suggestions.len() > 10);
+        assert!(suggestions.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(DICTIONARY.suggest_words(partial(), usize::MAX), suggestions);
+    }
+
     #[test]
     fn suggest_impossible() {
         let suggestions = DICTIONARY.suggest_words(
@@ -139,6 +1091,39 @@ mod tests {
         assert_eq!(suggestions.len(), 0);
     }
 
+    #[test]
+    fn suggest_by_pattern() {
+        let suggestions = DICTIONARY.suggest_by_pattern("qu*", 1000);
+        assert!(suggestions.len() > 100);
+        assert!(suggestions.iter().all(|word| word.starts_with("qu")));
+        let lengths: Vec<usize> = suggestions.iter().map(|word| word.len()).collect();
+        assert!(lengths.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(lengths.contains(&5) && lengths.contains(&9));
+
+        let mut suggestions = DICTIONARY.suggest_by_pattern("Z?PPY", 10);
+        suggestions.sort();
+        assert_eq!(suggestions, vec!["zappy", "zippy"]);
+
+        let suggestions = DICTIONARY.suggest_by_pattern("*xzq*", 10);
+        assert!(suggestions.is_empty());
+    }
+
+    #[test]
+    fn pattern_counts_by_length() {
+        let counts = DICTIONARY.pattern_counts_by_length("qu*");
+        let total: usize = counts.iter().map(|&(_, count)| count).sum();
+        assert_eq!(
+            total,
+            DICTIONARY.suggest_by_pattern("qu*", usize::MAX).len()
+        );
+        assert!(counts.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        let five = SparseWord::new(vec![Some('q'), Some('u'), None, None, None]);
+        assert!(counts.contains(&(5, DICTIONARY.count_matches(&five))));
+
+        assert_eq!(DICTIONARY.pattern_counts_by_length("Z?PPY"), vec![(5, 2)]);
+        assert!(DICTIONARY.pattern_counts_by_length("*xzq*").is_empty());
+    }
+
     #[test]
     fn suggest_z_words() {
         let mut suggestions = DICTIONARY.suggest_words(
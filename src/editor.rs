@@ -0,0 +1,167 @@
+use std::io::{self, Write};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+
+use crate::{
+    grid::{canonical_letter, Cell},
+    puzzle::Puzzle,
+};
+
+const HELP: &str = "arrows: move  letter: type  space: toggle black  backspace: clear  ctrl-z/ctrl-y: undo/redo  ctrl-s: save  esc: quit";
+
+/// An interactive terminal editor for a puzzle. The rendering here is deliberately separate from `Grid`'s `Display`
+/// impl, which is also the on-disk format.
+pub struct Editor {
+    puzzle: Puzzle,
+    cursor: (usize, usize),
+    message: String,
+}
+
+impl Editor {
+    pub fn new(puzzle: Puzzle) -> Self {
+        Editor {
+            puzzle,
+            cursor: (0, 0),
+            message: String::new(),
+        }
+    }
+
+    /// Take over the terminal until the user quits, restoring it afterwards even if drawing fails. A puzzle with no
+    /// cells has nowhere to put the cursor, so it's refused before the terminal is touched.
+    pub fn run(mut self) -> io::Result<()> {
+        if self.puzzle.size() == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The puzzle has no cells to edit, so it needs to be resized first",
+            ));
+        }
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        let result = self.event_loop(&mut out);
+        execute!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+        terminal::disable_raw_mode()?;
+        result
+    }
+
+    fn event_loop(&mut self, out: &mut impl Write) -> io::Result<()> {
+        loop {
+            self.draw(out)?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle_key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Apply a single key press, returning false when the editor should exit
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let size = self.puzzle.size();
+        let (x, y) = self.cursor;
+        let white = self.puzzle.get(x, y).is_white();
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return false,
+            KeyCode::Char('s') if ctrl => {
+                self.message = match self.puzzle.save_to_file() {
+                    Ok(_) => "Saved".to_string(),
+                    Err(e) => e.to_string(),
+                };
+            }
+            KeyCode::Char('z') if ctrl => {
+                self.message = match self.puzzle.undo() {
+                    true => String::new(),
+                    false => "Nothing to undo".to_string(),
+                };
+            }
+            KeyCode::Char('y') if ctrl => {
+                self.message = match self.puzzle.redo() {
+                    true => String::new(),
+                    false => "Nothing to redo".to_string(),
+                };
+            }
+            KeyCode::Up => self.cursor = (x, y.saturating_sub(1)),
+            KeyCode::Down => self.cursor = (x, (y + 1).min(size - 1)),
+            KeyCode::Left => self.cursor = (x.saturating_sub(1), y),
+            KeyCode::Right => self.cursor = ((x + 1).min(size - 1), y),
+            KeyCode::Char(' ') => {
+                let before = self.puzzle.clone();
+                let value = if white { Cell::Black } else { Cell::Empty };
+                self.puzzle.set_symmetric((x, y), value);
+                let renumbering = self.puzzle.renumbering(&before);
+                self.message = match (renumbering.removed.len(), renumbering.moved.len()) {
+                    (0, 0) => String::new(),
+                    (0, moved) => format!("{} clue(s) renumbered", moved),
+                    (removed, moved) => {
+                        format!("{} clue(s) removed, {} renumbered", removed, moved)
+                    }
+                };
+            }
+            KeyCode::Backspace | KeyCode::Delete if white => self.puzzle.set(x, y, Cell::Empty),
+            KeyCode::Char(c) if white && !ctrl && c.is_alphabetic() => {
+                self.puzzle.set(x, y, Cell::Letter(canonical_letter(c)));
+                let problems: Vec<String> = self
+                    .puzzle
+                    .validate_cell_edit(x, y)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                self.message = problems.join("; ");
+                self.cursor = ((x + 1).min(size - 1), y);
+            }
+            _ => (),
+        }
+        true
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        queue!(
+            out,
+            terminal::Clear(ClearType::All),
+            cursor::MoveTo(0, 0),
+            Print(format!(
+                "Editing {} ({} symmetry)",
+                self.puzzle.name(),
+                self.puzzle.symmetry()
+            ))
+        )?;
+        for y in 0..self.puzzle.size() {
+            queue!(out, cursor::MoveTo(0, y as u16 + 2))?;
+            for x in 0..self.puzzle.size() {
+                let cell = self.puzzle.get(x, y);
+                if (x, y) == self.cursor {
+                    queue!(
+                        out,
+                        SetAttribute(Attribute::Reverse),
+                        Print(cell),
+                        SetAttribute(Attribute::Reset)
+                    )?;
+                } else {
+                    queue!(out, Print(cell))?;
+                }
+            }
+        }
+        let status = match self.puzzle.validate_base() {
+            Ok(_) => "Puzzle base is valid".to_string(),
+            Err(e) => format!("Puzzle base is invalid: {}", e),
+        };
+        let bottom = self.puzzle.size() as u16 + 3;
+        queue!(
+            out,
+            cursor::MoveTo(0, bottom),
+            Print(status),
+            cursor::MoveTo(0, bottom + 1),
+            Print(&self.message),
+            cursor::MoveTo(0, bottom + 2),
+            Print(HELP)
+        )?;
+        out.flush()
+    }
+}
@@ -0,0 +1,355 @@
+use std::{fmt::Write, io::Cursor};
+
+use image::{GrayImage, ImageFormat, Luma};
+
+use crate::{
+    grid::Cell,
+    puzzle::{Direction, Puzzle},
+};
+
+/// The width and height of a cell in exported images, in pixels
+pub const CELL_SIZE: usize = 36;
+
+/// How thick the bars of a barred puzzle are drawn, in pixels
+const BAR_WIDTH: usize = 3;
+
+/// A 3x5 pixel font for the clue numbers and letters drawn into PNG exports, a row of three bits per line
+const FONT: [(char, [u8; 5]); 36] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+];
+
+const BLACK: Luma<u8> = Luma([0]);
+const WHITE: Luma<u8> = Luma([255]);
+
+/// Where everything in one cell goes in an exported image, worked out once so the SVG and PNG exports lay the grid
+/// out the same way
+struct CellPlan {
+    left: usize,
+    top: usize,
+    black: bool,
+    /// Whether the cell has a bar on its right and bottom edges, drawn as thick grid lines
+    bars: (bool, bool),
+    /// The clue number and the position of its bottom left corner
+    number: Option<(usize, (usize, usize))>,
+    /// The center and radius of the circle around a circled white cell
+    circle: Option<((usize, usize), usize)>,
+    /// The cell's letters and the point they're centered on
+    letters: Option<(String, (usize, usize))>,
+}
+
+impl Puzzle {
+    /// Lay out each cell of the grid for an image with square cells of the given size, left to right and top to
+    /// bottom. Clue numbers go in the top left of the cells that start words and letters in the middle.
+    fn render_plan(&self, cell_size: usize) -> Vec<CellPlan> {
+        let numbers = self.clue_numbers();
+        let mut plan = Vec::new();
+        for y in 0..self.size() {
+            for x in 0..self.size() {
+                let (left, top) = (x * cell_size, y * cell_size);
+                let center = (left + cell_size / 2, top + cell_size / 2);
+                let cell = self.get(x, y);
+                let letters = match cell {
+                    Cell::Letter(letter) => Some(letter.to_string()),
+                    Cell::Rebus(letters) => Some(letters.clone()),
+                    _ => None,
+                };
+                plan.push(CellPlan {
+                    left,
+                    top,
+                    black: cell.is_black(),
+                    bars: (
+                        self.has_bar(x, y, Direction::Across),
+                        self.has_bar(x, y, Direction::Down),
+                    ),
+                    number: numbers
+                        .get(&(x, y))
+                        .map(|&number| (number, (left + 2, top + cell_size / 4 + 1))),
+                    circle: (self.is_circled(x, y) && cell.is_white())
+                        .then_some((center, (cell_size / 2).saturating_sub(1))),
+                    letters: letters.map(|letters| (letters, (center.0, top + cell_size * 3 / 5))),
+                });
+            }
+        }
+        plan
+    }
+
+    /// Render the puzzle as an SVG image, with clue numbers in the top left of the cells that start words and any
+    /// letters centered in their cells
+    pub fn to_svg(&self) -> String {
+        let side = self.size() * CELL_SIZE + 2;
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{side}" height="{side}" viewBox="-1 -1 {side} {side}">"#
+        )
+        .unwrap();
+        for cell in self.render_plan(CELL_SIZE) {
+            let (left, top) = (cell.left, cell.top);
+            let fill = if cell.black { "black" } else { "white" };
+            writeln!(
+                svg,
+                r#"  <rect x="{left}" y="{top}" width="{CELL_SIZE}" height="{CELL_SIZE}" fill="{fill}" stroke="black" stroke-width="1"/>"#
+            )
+            .unwrap();
+            let (right, bottom) = (left + CELL_SIZE, top + CELL_SIZE);
+            if cell.bars.0 {
+                writeln!(
+                    svg,
+                    r#"  <line x1="{right}" y1="{top}" x2="{right}" y2="{bottom}" stroke="black" stroke-width="{BAR_WIDTH}"/>"#
+                )
+                .unwrap();
+            }
+            if cell.bars.1 {
+                writeln!(
+                    svg,
+                    r#"  <line x1="{left}" y1="{bottom}" x2="{right}" y2="{bottom}" stroke="black" stroke-width="{BAR_WIDTH}"/>"#
+                )
+                .unwrap();
+            }
+            if let Some((number, (x, y))) = cell.number {
+                writeln!(
+                    svg,
+                    r#"  <text x="{x}" y="{y}" font-family="sans-serif" font-size="{}">{number}</text>"#,
+                    CELL_SIZE / 4
+                )
+                .unwrap();
+            }
+            if let Some(((cx, cy), r)) = cell.circle {
+                writeln!(
+                    svg,
+                    r#"  <circle cx="{cx}" cy="{cy}" r="{r}" fill="none" stroke="black" stroke-width="1"/>"#
+                )
+                .unwrap();
+            }
+            let Some((letters, (x, y))) = cell.letters else {
+                continue;
+            };
+            // Shrink rebus entries so they still fit in the cell
+            let font_size = CELL_SIZE / 2 / letters.chars().count().clamp(1, 3);
+            writeln!(
+                svg,
+                r#"  <text x="{x}" y="{y}" font-family="sans-serif" font-size="{font_size}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                letters.chars().map(escape_xml).collect::<String>()
+            )
+            .unwrap();
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// Render the puzzle as a grayscale PNG with cells cell_px pixels wide, laid out as in `to_svg`. The image is one
+    /// pixel wider and taller than the cells so the grid line closes on the right and bottom. Text is drawn in a
+    /// small built-in font covering digits and the letters A to Z; other characters are left out.
+    pub fn to_png(&self, cell_px: u32) -> Vec<u8> {
+        let cell_size = cell_px.max(1) as usize;
+        let side = (self.size() * cell_size + 1) as u32;
+        let mut image = GrayImage::from_pixel(side, side, WHITE);
+        for cell in self.render_plan(cell_size) {
+            let (left, top) = (cell.left as u32, cell.top as u32);
+            for y in top..=top + cell_size as u32 {
+                for x in left..=left + cell_size as u32 {
+                    let edge = x == left
+                        || y == top
+                        || x == left + cell_size as u32
+                        || y == top + cell_size as u32;
+                    if edge || cell.black {
+                        image.put_pixel(x, y, BLACK);
+                    }
+                }
+            }
+            let (right, bottom) = (left + cell_size as u32, top + cell_size as u32);
+            let half = BAR_WIDTH as u32 / 2;
+            if cell.bars.0 {
+                for y in top..=bottom {
+                    for x in right.saturating_sub(half)..=right + half {
+                        image.put_pixel(x, y, BLACK);
+                    }
+                }
+            }
+            if cell.bars.1 {
+                for y in bottom.saturating_sub(half)..=bottom + half {
+                    for x in left..=right {
+                        image.put_pixel(x, y, BLACK);
+                    }
+                }
+            }
+            if let Some((number, (x, bottom))) = cell.number {
+                let scale = (cell_size / 4 / 5).max(1);
+                draw_text(
+                    &mut image,
+                    &number.to_string(),
+                    (x, bottom.saturating_sub(5 * scale)),
+                    scale,
+                );
+            }
+            if let Some(((cx, cy), r)) = cell.circle {
+                draw_circle(&mut image, (cx, cy), r);
+            }
+            if let Some((letters, (cx, cy))) = cell.letters {
+                let count = letters.chars().count();
+                let scale = (cell_size / 2 / count.clamp(1, 3) / 5).max(1);
+                let width = count * 4 * scale - scale;
+                let origin = (
+                    cx.saturating_sub(width / 2),
+                    cy.saturating_sub(5 * scale / 2),
+                );
+                draw_text(&mut image, &letters, origin, scale);
+            }
+        }
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .expect("Unable to encode PNG");
+        png
+    }
+}
+
+/// Draw text in the built-in font with its top left corner at origin, each font pixel a square of scale pixels.
+/// Characters without a glyph are skipped, leaving a gap.
+fn draw_text(image: &mut GrayImage, text: &str, (left, top): (usize, usize), scale: usize) {
+    for (i, c) in text.chars().enumerate() {
+        let Some((_, rows)) = FONT.iter().find(|(glyph, _)| *glyph == c) else {
+            continue;
+        };
+        let glyph_left = left + i * 4 * scale;
+        for (row, bits) in rows.iter().enumerate() {
+            for col in 0..3 {
+                if bits & (0b100 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let (x, y) = (glyph_left + col * scale + dx, top + row * scale + dy);
+                        if x < image.width() as usize && y < image.height() as usize {
+                            image.put_pixel(x as u32, y as u32, BLACK);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draw the outline of a circle a pixel wide
+fn draw_circle(image: &mut GrayImage, (cx, cy): (usize, usize), r: usize) {
+    let (cx, cy, r) = (cx as f64, cy as f64, r as f64);
+    let (from_x, from_y) = (
+        (cx - r - 1.0).max(0.0) as u32,
+        (cy - r - 1.0).max(0.0) as u32,
+    );
+    let to_x = ((cx + r + 1.0) as u32).min(image.width() - 1);
+    let to_y = ((cy + r + 1.0) as u32).min(image.height() - 1);
+    for y in from_y..=to_y {
+        for x in from_x..=to_x {
+            let distance = (x as f64 - cx).hypot(y as f64 - cy);
+            if (distance - r).abs() < 0.5 {
+                image.put_pixel(x, y, BLACK);
+            }
+        }
+    }
+}
+
+fn escape_xml(c: char) -> String {
+    match c {
+        '&' => "&amp;".to_string(),
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        '\'' => "&apos;".to_string(),
+        '"' => "&quot;".to_string(),
+        c => c.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageFormat, Luma};
+
+    use crate::{grid::Cell, puzzle::Puzzle};
+
+    #[test]
+    fn svg() {
+        let mut puzzle = Puzzle::new("x".to_string(), 5);
+        puzzle.set_symmetric((0, 0), Cell::Black);
+        puzzle.fill_across(1, "CAT").unwrap();
+        let svg = puzzle.to_svg();
+
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect ").count(), 25);
+        assert_eq!(svg.matches(r#"fill="black""#).count(), 4);
+        // Eight clue numbers and three letters
+        assert_eq!(svg.matches("<text ").count(), 11);
+        assert!(svg.contains(r#"<rect x="36" y="0" width="36" height="36" fill="white""#));
+        assert!(svg.contains(">8</text>"));
+        assert!(svg.contains(">C</text>"));
+        assert!(!svg.contains("<circle "));
+
+        puzzle.circle_cell(1, 0).unwrap();
+        puzzle.circle_cell(0, 0).unwrap();
+        let svg = puzzle.to_svg();
+        assert_eq!(svg.matches("<circle ").count(), 1);
+        assert!(svg.contains(r#"<circle cx="54" cy="18""#));
+    }
+
+    #[test]
+    fn png() {
+        let mut puzzle = Puzzle::new("x".to_string(), 5);
+        puzzle.set_symmetric((0, 0), Cell::Black);
+        puzzle.fill_across(1, "CAT").unwrap();
+        puzzle.circle_cell(2, 2).unwrap();
+        let png = puzzle.to_png(40);
+
+        assert_eq!(image::guess_format(&png).unwrap(), ImageFormat::Png);
+        let image = image::load_from_memory(&png).unwrap().to_luma8();
+        assert_eq!(image.dimensions(), (201, 201));
+        // Inside the black corner, on a grid line, and in the empty middle of a white cell
+        assert_eq!(image.get_pixel(20, 20), &Luma([0]));
+        assert_eq!(image.get_pixel(40, 100), &Luma([0]));
+        assert_eq!(image.get_pixel(100, 140), &Luma([255]));
+        // The letters and the circle add dark pixels to otherwise white cells
+        let dark = |left: u32, top: u32| {
+            (left + 1..left + 40)
+                .flat_map(|x| (top + 1..top + 40).map(move |y| (x, y)))
+                .filter(|&(x, y)| image.get_pixel(x, y) == &Luma([0]))
+                .count()
+        };
+        assert!(dark(40, 0) > 0);
+        assert!(dark(80, 80) > 0);
+        assert_eq!(dark(120, 160), 0);
+    }
+}
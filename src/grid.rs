@@ -1,32 +1,224 @@
-use std::{fmt, str::Utf8Error};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    hash::{DefaultHasher, Hash, Hasher},
+    str::FromStr,
+    str::Utf8Error,
+};
 use thiserror::Error;
 
-use crate::{puzzle::PuzzleError, PERCENT_BLACK};
+use crate::{puzzle::PuzzleError, EXTRA_WORD_CHARS};
+
+/// Whether a character can be part of a word, either in a grid cell or in the dictionary
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphabetic() || EXTRA_WORD_CHARS.contains(&c)
+}
+
+/// The form a letter is stored in a `Cell`: uppercase, unless it has no single-character uppercase form. Dictionary
+/// lookups use lowercase instead, so grids and wordlists agree whatever case either was written in.
+pub fn canonical_letter(c: char) -> char {
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(u), None) => u,
+        _ => c,
+    }
+}
 
 #[derive(Error, Debug, PartialEq)]
 pub enum GridError {
-    #[error("Invalid puzzle file format")]
-    InvalidPuzzleFormat,
+    #[error("Invalid puzzle file format on line {line}: unexpected \"{token}\"")]
+    InvalidPuzzleFormat { line: usize, token: String },
+    #[error("Invalid coordinates \"{0}\", expected x,y")]
+    InvalidCoords(String),
     #[error("Puzzle file not in utf8: {0}")]
     NonUtf8(Utf8Error),
+    #[error("Unknown symmetry \"{0}\", expected rotational, horizontal, vertical or diagonal")]
+    UnknownSymmetry(String),
+    #[error("Unknown axis \"{0}\", expected horizontal or vertical")]
+    UnknownAxis(String),
+    #[error("Unknown template \"{0}\", expected mini, daily or sunday")]
+    UnknownTemplate(String),
+    #[error("Row {row} has {found} cells, but the rows above it have {expected}")]
+    RaggedGrid {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("The grid has {rows} rows of {columns} cells, but must be square")]
+    NotSquare { rows: usize, columns: usize },
+    #[error("({x}, {y}) is outside the grid")]
+    OutOfBounds { x: usize, y: usize },
+}
+
+/// The ways the pattern of black squares in a grid can be symmetric
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Symmetry {
+    /// The pattern looks the same when the grid is turned upside-down
+    #[default]
+    Rotational,
+    /// The pattern looks the same when the grid is flipped left to right
+    Horizontal,
+    /// The pattern looks the same when the grid is flipped top to bottom
+    Vertical,
+    /// The pattern looks the same when the grid is reflected across the diagonal from the top left to the bottom right
+    Diagonal,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl Symmetry {
+    /// All the cells whose color must match the cell at (x, y) in a grid of the given size, including (x, y) itself
+    pub fn partners(&self, (x, y): (usize, usize), size: usize) -> Vec<(usize, usize)> {
+        let mut cells = self.images((x, y), size);
+        cells.sort();
+        cells.dedup();
+        cells
+    }
+
+    /// Where each of the symmetry's transformations takes (x, y), starting with (x, y) itself. Unlike `partners`,
+    /// the order is the same for every cell, so the nth images of two cells are related by the same transformation.
+    pub fn images(&self, (x, y): (usize, usize), size: usize) -> Vec<(usize, usize)> {
+        match self {
+            Symmetry::Rotational => vec![
+                (x, y),
+                (size - (y + 1), x),
+                (size - (x + 1), size - (y + 1)),
+                (y, size - (x + 1)),
+            ],
+            Symmetry::Horizontal => vec![(x, y), (size - (x + 1), y)],
+            Symmetry::Vertical => vec![(x, y), (x, size - (y + 1))],
+            Symmetry::Diagonal => vec![(x, y), (y, x)],
+        }
+    }
+
+    /// The single cell that mirrors (x, y) for placing theme entries: the 180 degree rotation for rotational
+    /// symmetry, or the reflection for the others
+    pub fn counterpart(&self, (x, y): (usize, usize), size: usize) -> (usize, usize) {
+        match self {
+            Symmetry::Rotational => (size - (x + 1), size - (y + 1)),
+            Symmetry::Horizontal => (size - (x + 1), y),
+            Symmetry::Vertical => (x, size - (y + 1)),
+            Symmetry::Diagonal => (y, x),
+        }
+    }
+}
+
+impl fmt::Display for Symmetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Symmetry::Rotational => "rotational",
+            Symmetry::Horizontal => "horizontal",
+            Symmetry::Vertical => "vertical",
+            Symmetry::Diagonal => "diagonal",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Symmetry {
+    type Err = GridError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "rotational" => Ok(Symmetry::Rotational),
+            "horizontal" => Ok(Symmetry::Horizontal),
+            "vertical" => Ok(Symmetry::Vertical),
+            "diagonal" => Ok(Symmetry::Diagonal),
+            other => Err(GridError::UnknownSymmetry(other.to_string())),
+        }
+    }
+}
+
+/// The direction a grid is flipped in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    /// Left to right, reversing each row
+    Horizontal,
+    /// Top to bottom, reversing the order of the rows
+    Vertical,
+}
+
+impl fmt::Display for Axis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Axis::Horizontal => "horizontal",
+            Axis::Vertical => "vertical",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Axis {
+    type Err = GridError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "horizontal" => Ok(Axis::Horizontal),
+            "vertical" => Ok(Axis::Vertical),
+            other => Err(GridError::UnknownAxis(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct Grid(pub Vec<Vec<Cell>>);
 
+/// Only the shape of the grid and its pattern of black squares are hashed, so grids with the same base hash the same
+/// whatever letters they hold. Equal grids still always hash the same.
+impl Hash for Grid {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.len().hash(state);
+        for row in &self.0 {
+            row.len().hash(state);
+            for cell in row {
+                cell.is_black().hash(state);
+            }
+        }
+    }
+}
+
 impl fmt::Display for Grid {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for row in &self.0 {
             for cell in row {
                 write!(f, "{}", cell)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
 }
 
 impl Grid {
+    /// The grid as `Display` writes it, but using `Cell::ascii_token`
+    pub fn render_ascii(&self) -> String {
+        let mut rendered = String::new();
+        for row in &self.0 {
+            for cell in row {
+                rendered.push_str(&cell.ascii_token());
+                rendered.push(' ');
+            }
+            rendered.push('\n');
+        }
+        rendered
+    }
+
+    /// The grid with each cell written as a single character and nothing between them, a row per line. A rebus keeps
+    /// its brackets, so it still reads back as one cell. Uses `Cell::ascii_token` if ascii is set.
+    pub fn render_compact(&self, ascii: bool) -> String {
+        let mut rendered = String::new();
+        for row in &self.0 {
+            for cell in row {
+                rendered.push_str(&if ascii {
+                    cell.ascii_token()
+                } else {
+                    cell.token()
+                });
+            }
+            rendered.push('\n');
+        }
+        rendered
+    }
+
     pub fn new(size: usize) -> Self {
         let mut grid = Vec::new();
         for _n in 0..size {
@@ -39,29 +231,100 @@ impl Grid {
         Grid(grid)
     }
 
-    pub fn pretty_print(&self) {
-        let row_len = self.0.len();
-        for (idx, row) in self.0.iter().enumerate() {
-            print!(" {:3}: ", (idx * row_len));
-            for cell in row {
-                print!("{}", cell);
+    /// Parse a grid written by the `Display` impl or `render_compact`. A row with spaces in it is read a token at a
+    /// time and one without a character (or bracketed rebus) at a time. Rows may end in `\r\n` as well as `\n`, and
+    /// blank lines, such as a trailing one at the end of the file, are skipped.
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, GridError> {
+        let mut cells = Vec::new();
+        for (index, row) in buf.split(|x| *x == b'\n').enumerate() {
+            let row = row.strip_suffix(b"\r").unwrap_or(row);
+            let row_str = std::str::from_utf8(row).map_err(GridError::NonUtf8)?;
+            if row_str.trim().is_empty() {
+                continue;
+            }
+            let tokens = match row_str.trim().contains(char::is_whitespace) {
+                true => row_str.split_whitespace().collect(),
+                false => Grid::compact_tokens(row_str.trim()),
+            };
+            let row_cells: Result<Vec<Cell>, _> = tokens
+                .into_iter()
+                .map(|token| {
+                    Cell::from_str(token).map_err(|token| GridError::InvalidPuzzleFormat {
+                        line: index + 1,
+                        token,
+                    })
+                })
+                .collect();
+            let row_cells = row_cells?;
+            if let Some(first) = cells.first().map(Vec::len) {
+                if row_cells.len() != first {
+                    return Err(GridError::RaggedGrid {
+                        row: cells.len(),
+                        expected: first,
+                        found: row_cells.len(),
+                    });
+                }
             }
-            println!();
+            cells.push(row_cells)
+        }
+        Ok(Grid(cells))
+    }
+
+    /// Split a row written by `render_compact` into a token per cell. An unclosed bracket takes the rest of the row, so
+    /// it's reported as a bad token.
+    fn compact_tokens(row: &str) -> Vec<&str> {
+        let mut tokens = Vec::new();
+        let mut rest = row;
+        while let Some(c) = rest.chars().next() {
+            let len = match c {
+                '[' => rest.find(']').map_or(rest.len(), |end| end + 1),
+                c => c.len_utf8(),
+            };
+            tokens.push(&rest[..len]);
+            rest = &rest[len..];
         }
+        tokens
     }
 
-    pub fn from_bytes(buf: &Vec<u8>) -> Result<Self, GridError> {
-        let mut cells = Vec::new();
-        for row in buf.split(|x| *x == '\n' as u8) {
-            if row.len() > 0 {
-                let row_str = std::str::from_utf8(row).map_err(|e| GridError::NonUtf8(e))?;
-                let row_cells: Result<Vec<Cell>, _> = row_str
-                    .split_ascii_whitespace()
-                    .map(|s| Cell::from_str(s))
-                    .collect();
-                let row_cells = row_cells?;
-                cells.push(row_cells)
+    /// Parse a compact grid with a character per cell: `.` for an empty cell, `#` for a black square and letters for
+    /// themselves. Blank lines and whitespace around rows are ignored, but the rows must form a square.
+    pub fn from_template(template: &str) -> Result<Self, GridError> {
+        let mut cells: Vec<Vec<Cell>> = Vec::new();
+        for (index, line) in template
+            .lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty())
+        {
+            let row: Vec<Cell> = line
+                .chars()
+                .map(|c| match c {
+                    '.' => Ok(Cell::Empty),
+                    '#' => Ok(Cell::Black),
+                    c if is_word_char(c) => Ok(Cell::Letter(canonical_letter(c))),
+                    _ => Err(GridError::InvalidPuzzleFormat {
+                        line: index + 1,
+                        token: c.to_string(),
+                    }),
+                })
+                .collect::<Result<_, _>>()?;
+            if let Some(first) = cells.first() {
+                if row.len() != first.len() {
+                    return Err(GridError::RaggedGrid {
+                        row: cells.len(),
+                        expected: first.len(),
+                        found: row.len(),
+                    });
+                }
             }
+            cells.push(row);
+        }
+        let columns = cells.first().map_or(0, Vec::len);
+        if columns != cells.len() {
+            return Err(GridError::NotSquare {
+                rows: cells.len(),
+                columns,
+            });
         }
         Ok(Grid(cells))
     }
@@ -70,10 +333,15 @@ impl Grid {
         self.0.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Swap rows and columns. A grid with no rows transposes to another empty grid.
     pub fn transpose(&self) -> Self {
-        assert!(!self.0.is_empty());
+        let width = self.0.first().map_or(0, Vec::len);
         Grid(
-            (0..self.0[0].len())
+            (0..width)
                 .map(|i| {
                     self.0
                         .iter()
@@ -88,6 +356,14 @@ impl Grid {
         self.0.iter()
     }
 
+    /// Every cell along with its coordinates, row by row from the top left
+    pub fn iter_coords(&self) -> impl Iterator<Item = (usize, usize, &Cell)> {
+        self.0
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| row.iter().enumerate().map(move |(x, cell)| (x, y, cell)))
+    }
+
     fn cells_row_major_iter(&self) -> impl Iterator<Item = &Cell> {
         let cells: Vec<&Cell> = self.0.iter().flatten().collect();
         cells.into_iter()
@@ -99,149 +375,797 @@ impl Grid {
         cells.into_iter()
     }
 
+    /// Set the cell at (x, y), panicking if it's outside the grid. Use `try_set` for coordinates that come from the user.
     pub fn set(&mut self, x: usize, y: usize, value: Cell) {
-        let row = self.0.get_mut(y).unwrap();
-        let cell = row.get_mut(x).unwrap();
-        *cell = value.clone();
+        self.try_set(x, y, value).unwrap()
     }
 
+    /// Get the cell at (x, y), panicking if it's outside the grid. Use `try_get` for coordinates that come from the user.
     pub fn get(&self, x: usize, y: usize) -> &Cell {
-        self.0.get(y).unwrap().get(x).unwrap()
+        self.try_get(x, y).unwrap()
+    }
+
+    /// Like `get`, but returns an error instead of panicking when (x, y) is outside the grid
+    pub fn try_get(&self, x: usize, y: usize) -> Result<&Cell, GridError> {
+        self.0
+            .get(y)
+            .and_then(|row| row.get(x))
+            .ok_or(GridError::OutOfBounds { x, y })
+    }
+
+    /// Like `set`, but returns an error instead of panicking when (x, y) is outside the grid
+    pub fn try_set(&mut self, x: usize, y: usize, value: Cell) -> Result<(), GridError> {
+        let cell = self
+            .0
+            .get_mut(y)
+            .and_then(|row| row.get_mut(x))
+            .ok_or(GridError::OutOfBounds { x, y })?;
+        *cell = value;
+        Ok(())
     }
 
     pub fn get_row(&self, row: usize) -> &Vec<Cell> {
         self.0.get(row).unwrap()
     }
 
+    /// A copy of the cells in column col, top to bottom. Unlike `get_row`, an out of range column is an error rather
+    /// than a panic.
+    pub fn get_column(&self, col: usize) -> Result<Vec<Cell>, GridError> {
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(y, row)| {
+                row.get(col)
+                    .cloned()
+                    .ok_or(GridError::OutOfBounds { x: col, y })
+            })
+            .collect()
+    }
+
     #[allow(dead_code)]
     fn get_row_mut(&mut self, row: usize) -> &mut Vec<Cell> {
         self.0.get_mut(row).unwrap()
     }
 
-    /// Rotate the puzzle 180 degrees by reversing the order of the rows and the contents of the rows
-    fn rotate_180(&mut self) {
-        self.0.reverse();
-        for row in self.0.iter_mut() {
-            row.reverse();
+    /// Rotate the grid clockwise by 90 degrees for each quarter turn. Like the rest of `Grid`, this assumes the grid
+    /// is square; a rectangular grid would also need its width and height swapped.
+    pub fn rotate(&mut self, quarter_turns: u8) {
+        match quarter_turns % 4 {
+            0 => (),
+            // Reversing the order of the rows and the contents of the rows
+            2 => {
+                self.flip(Axis::Vertical);
+                self.flip(Axis::Horizontal);
+            }
+            turns => {
+                *self = self.transpose();
+                self.flip(Axis::Horizontal);
+                if turns == 3 {
+                    self.rotate(2);
+                }
+            }
         }
     }
 
+    #[allow(dead_code)]
     pub fn get_mut(&mut self, x: usize, y: usize) -> &mut Cell {
         self.0.get_mut(y).unwrap().get_mut(x).unwrap()
     }
 
+    /// Check that every row is as long as the first and that there are as many rows as columns
     pub fn is_square(&self) -> Result<(), PuzzleError> {
-        let size = self.len();
-        for row in &self.0 {
-            if row.len() != size {
-                return Err(PuzzleError::NotSymmetric);
+        let columns = self.0.first().map_or(0, Vec::len);
+        for (row, cells) in self.0.iter().enumerate() {
+            if cells.len() != columns {
+                return Err(GridError::RaggedGrid {
+                    row,
+                    expected: columns,
+                    found: cells.len(),
+                }
+                .into());
+            }
+        }
+        if self.len() != columns {
+            return Err(GridError::NotSquare {
+                rows: self.len(),
+                columns,
             }
+            .into());
         }
-        return Ok(());
+        Ok(())
     }
 
-    /// Verify that the black sqaures in both puzzles are in the same locations
-    fn black_squares_match(&self, other: Self) -> bool {
-        for y in 0..self.len() {
-            for x in 0..self.len() {
-                let left = self.get(x, y);
-                let right = other.get(x, y);
-                if (left == &Cell::Black && right != &Cell::Black)
-                    || (right == &Cell::Black && left != &Cell::Black)
-                {
-                    return false;
+    pub fn flip(&mut self, axis: Axis) {
+        match axis {
+            Axis::Horizontal => {
+                for row in self.0.iter_mut() {
+                    row.reverse();
                 }
             }
+            Axis::Vertical => self.0.reverse(),
         }
-        return true;
     }
 
     /// "Generally this rule means that if you turn the grid upside-down, the pattern will look the same as it
-    /// does right-side-up. "
-    pub fn is_symmetric(&self) -> Result<(), PuzzleError> {
-        let mut flipped_grid = self.clone();
-        flipped_grid.rotate_180();
-        if self.black_squares_match(flipped_grid) {
+    /// does right-side-up. " Mirror and diagonal symmetry are checked the same way, with the matching transformation.
+    pub fn is_symmetric(&self, symmetry: Symmetry) -> Result<(), PuzzleError> {
+        self.is_nearly_symmetric(symmetry, 0)
+    }
+
+    /// Like `is_symmetric`, but allowing up to tolerance black squares whose partners are white, such as a few
+    /// cheater squares in a themed grid. Any more and every asymmetric cell is reported, as `is_symmetric` would.
+    pub fn is_nearly_symmetric(
+        &self,
+        symmetry: Symmetry,
+        tolerance: usize,
+    ) -> Result<(), PuzzleError> {
+        let asymmetric = self.asymmetry_cells(symmetry);
+        let black = asymmetric
+            .iter()
+            .filter(|&&(x, y)| self.get(x, y).is_black())
+            .count();
+        if black <= tolerance {
             Ok(())
         } else {
-            Err(PuzzleError::NotSymmetric)
+            Err(PuzzleError::NotSymmetric(asymmetric))
         }
     }
 
-    /// Check that the black squares account for no more than 16 percent of the total grid
-    pub fn acceptable_black_square_count(&self) -> Result<(), PuzzleError> {
-        let size = self.len();
-        let total = size * size;
-        let mut black = 0;
-        for cell in self.cells_row_major_iter() {
-            if let Cell::Black = cell {
-                black += 1;
-            }
+    /// The cells, in row order, whose color doesn't match their mirror image under the symmetry: black squares
+    /// whose partner is white, and white squares whose partner is black. Fixing a grid that's nearly symmetric
+    /// means changing one cell of each mismatched pair.
+    pub fn asymmetry_cells(&self, symmetry: Symmetry) -> Vec<(usize, usize)> {
+        let mut flipped_grid = self.clone();
+        match symmetry {
+            Symmetry::Rotational => flipped_grid.rotate(2),
+            Symmetry::Horizontal => flipped_grid.flip(Axis::Horizontal),
+            Symmetry::Vertical => flipped_grid.flip(Axis::Vertical),
+            Symmetry::Diagonal => flipped_grid = self.transpose(),
         }
-        if ((black * 100) / total) <= PERCENT_BLACK {
+        self.iter_coords()
+            .filter(|&(x, y, cell)| cell.is_black() != flipped_grid.get(x, y).is_black())
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    /// Count the cells for which `matches` returns true
+    pub fn count_cells(&self, matches: impl Fn(&Cell) -> bool) -> usize {
+        self.cells_row_major_iter()
+            .filter(|cell| matches(cell))
+            .count()
+    }
+
+    /// Every cell that differs between the two grids, as its coordinates, the cell in self and the cell in other.
+    /// Only the cells the grids share are compared if they're different sizes.
+    pub fn diff(&self, other: &Grid) -> Vec<((usize, usize), Cell, Cell)> {
+        self.iter_coords()
+            .filter_map(|(x, y, cell)| {
+                let other_cell = other.try_get(x, y).ok()?;
+                (cell != other_cell).then(|| ((x, y), cell.clone(), other_cell.clone()))
+            })
+            .collect()
+    }
+
+    /// A hash of the black square pattern, the same for any two grids with the same base, for spotting duplicates
+    /// among generated grids. It's stable within a build but may change between Rust versions, so it shouldn't be
+    /// saved.
+    pub fn base_fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Whether the two grids have the same shape and black squares, whatever letters they hold. Grids with the same
+    /// base also have the same `base_fingerprint`.
+    pub fn same_base(&self, other: &Grid) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().zip(&other.0).all(|(row, other_row)| {
+                row.len() == other_row.len()
+                    && row
+                        .iter()
+                        .zip(other_row)
+                        .all(|(cell, other_cell)| cell.is_black() == other_cell.is_black())
+            })
+    }
+
+    pub fn black_square_count(&self) -> usize {
+        self.count_cells(Cell::is_black)
+    }
+
+    /// The share of the grid that's black squares, from 0 to 1, or 0 for an empty grid
+    pub fn black_square_fraction(&self) -> f64 {
+        let total = self.len() * self.len();
+        if total == 0 {
+            return 0.0;
+        }
+        self.black_square_count() as f64 / total as f64
+    }
+
+    /// Check that the black squares account for no more than `max_percent` percent of the total grid. The comparison
+    /// is made on the exact fraction, so a grid that's 16.9% black doesn't pass a 16% limit.
+    pub fn acceptable_black_square_count(&self, max_percent: usize) -> Result<(), PuzzleError> {
+        // Both sides are the nearest float to a ratio of integers, so a grid exactly at the limit compares equal
+        if self.black_square_fraction() <= max_percent as f64 / 100.0 {
             Ok(())
         } else {
-            Err(PuzzleError::TooManyBlackSquares(PERCENT_BLACK))
+            Err(PuzzleError::TooManyBlackSquares(max_percent))
         }
     }
 
-    /// Check that the the distance to the end of a slice or to the first black Cell is either 0 or greater than or equal to 3.
-    pub fn ok_dist_to_black_or_edge(row: &[Cell]) -> bool {
+    /// Check that the the distance to the end of a slice or to the first black Cell is either 0 or at least min_word_len.
+    pub fn ok_dist_to_black_or_edge(row: &[Cell], min_word_len: usize) -> bool {
         let mut dist = 0;
         for x in row.iter() {
-            if matches!(x, Cell::Black) {
+            if x.is_black() {
                 break;
             }
             dist += 1;
         }
-        return dist == 0 || dist >= 3;
+        dist == 0 || dist >= min_word_len
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Hash, Eq)]
+/// In JSON a cell is tagged with its kind, like `{"type": "black"}` or `{"type": "letter", "letter": "A"}`
+#[derive(Debug, Clone, PartialEq, Hash, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "letter", rename_all = "lowercase")]
 pub enum Cell {
     Black,
     Empty,
+    /// Always holds a letter in the form given by `canonical_letter`
     Letter(char),
+    /// Several letters squeezed into one cell, which all count towards both words through it. Written in puzzle files
+    /// in brackets, like `[CAT]`.
+    Rebus(String),
 }
 
 impl fmt::Display for Cell {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let cell = match self {
-            Cell::Black => '▩',
-            Cell::Empty => '▢',
-            Cell::Letter(letter) => *letter,
-        };
-        write!(f, "{} ", cell)
+        write!(f, "{} ", self.token())
     }
 }
 
 impl Cell {
-    fn letter(&self) -> &char {
+    pub fn is_black(&self) -> bool {
+        matches!(self, Cell::Black)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Cell::Empty)
+    }
+
+    /// Whether the cell holds a letter, counting a rebus as one
+    pub fn is_letter(&self) -> bool {
+        matches!(self, Cell::Letter(_) | Cell::Rebus(_))
+    }
+
+    /// Whether the cell is part of a word, filled in or not
+    pub fn is_white(&self) -> bool {
+        !self.is_black()
+    }
+
+    /// How a cell is written in puzzle files
+    pub fn token(&self) -> String {
+        match self {
+            Cell::Black => '▩'.to_string(),
+            Cell::Empty => '▢'.to_string(),
+            Cell::Letter(letter) => letter.to_string(),
+            Cell::Rebus(letters) => format!("[{}]", letters),
+        }
+    }
+
+    /// Like `token`, but with `#` for a black square and `.` for an empty cell, for terminals and tools that don't
+    /// handle the usual glyphs. Puzzle files may use either set.
+    pub fn ascii_token(&self) -> String {
+        match self {
+            Cell::Black => '#'.to_string(),
+            Cell::Empty => '.'.to_string(),
+            _ => self.token(),
+        }
+    }
+
+    /// How a circled cell is shown on screen: an empty circle, an enclosed letter like Ⓐ, or anything else in
+    /// parentheses. Black squares can't be circled.
+    pub fn circled_token(&self) -> String {
+        match self {
+            Cell::Black => self.token(),
+            Cell::Empty => '◯'.to_string(),
+            Cell::Letter(letter @ 'A'..='Z') => {
+                char::from_u32('Ⓐ' as u32 + (*letter as u32 - 'A' as u32))
+                    .unwrap()
+                    .to_string()
+            }
+            Cell::Letter(letter) => format!("({})", letter),
+            Cell::Rebus(letters) => format!("({})", letters),
+        }
+    }
+
+    /// The letters a cell contributes to a word, with `_` standing in for an empty cell
+    fn letters(&self) -> String {
         match self {
             Cell::Black => panic!("Not a letter"),
-            Cell::Empty => &'_',
-            Cell::Letter(l) => l,
+            Cell::Empty => '_'.to_string(),
+            Cell::Letter(l) => l.to_string(),
+            Cell::Rebus(letters) => letters.clone(),
         }
     }
 
-    fn from_str(s: &str) -> Result<Self, GridError> {
-        let c = s.trim();
-        let c = c.chars().next().unwrap();
-        match c {
-            '▩' => Ok(Cell::Black),
-            '▢' => Ok(Cell::Empty),
-            l => {
-                if l.is_alphabetic() {
-                    Ok(Cell::Letter(l))
-                } else {
-                    Err(GridError::InvalidPuzzleFormat)
-                }
+    /// Parse a single token from a puzzle file. On failure, gives back the part that couldn't be parsed: the first
+    /// character that can't be in a word, or the whole token if it's the wrong shape for a cell.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let token = s.trim();
+        if let Some(letters) = token.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+            if let Some(bad) = letters.chars().find(|&c| !is_word_char(c)) {
+                return Err(bad.to_string());
             }
+            let letters: String = letters.chars().map(canonical_letter).collect();
+            let mut chars = letters.chars();
+            return match (chars.next(), chars.next()) {
+                (None, _) => Err(token.to_string()),
+                (Some(letter), None) => Ok(Cell::Letter(letter)),
+                _ => Ok(Cell::Rebus(letters)),
+            };
+        }
+        let mut chars = token.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            return Err(token.to_string());
+        };
+        match c {
+            '▩' | '#' => Ok(Cell::Black),
+            '▢' | '.' => Ok(Cell::Empty),
+            l if is_word_char(l) => Ok(Cell::Letter(canonical_letter(l))),
+            l => Err(l.to_string()),
         }
     }
 
     pub fn as_string(cells: &[Cell]) -> String {
-        cells.iter().map(|x| x.letter()).collect()
+        cells.iter().map(|x| x.letters()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        grid::{canonical_letter, is_word_char, Axis, Cell, Grid, GridError, Symmetry},
+        puzzle::PuzzleError,
+    };
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn extra_word_chars_round_trip() {
+        let grid =
+            Grid::from_bytes("W E L L - B E I N G \nO ' E R ▩ ▢ ▢ ▢ ▢ ▢ \n".as_bytes()).unwrap();
+        assert_eq!(grid.get(4, 0), &Cell::Letter('-'));
+        assert_eq!(grid.get(1, 1), &Cell::Letter('\''));
+        assert_eq!(Cell::as_string(grid.get_row(0)), "WELL-BEING");
+        assert_eq!(Grid::from_bytes(format!("{}", grid).as_bytes()), Ok(grid));
+
+        assert!(Grid::from_bytes("A B ! \n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn random_grids_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let letter = |rng: &mut StdRng| loop {
+            let c = char::from_u32(rng.gen_range(0..0x30000)).filter(|&c| is_word_char(c));
+            if let Some(c) = c {
+                return canonical_letter(c);
+            }
+        };
+        for _ in 0..200 {
+            let size = rng.gen_range(0..8);
+            let cells = (0..size)
+                .map(|_| {
+                    (0..size)
+                        .map(|_| match rng.gen_range(0..4) {
+                            0 => Cell::Black,
+                            1 => Cell::Empty,
+                            2 => Cell::Letter(letter(&mut rng)),
+                            _ => Cell::Rebus(
+                                (0..rng.gen_range(2..5)).map(|_| letter(&mut rng)).collect(),
+                            ),
+                        })
+                        .collect()
+                })
+                .collect();
+            let grid = Grid(cells);
+            assert_eq!(Grid::from_bytes(grid.to_string().as_bytes()), Ok(grid));
+        }
+    }
+
+    #[test]
+    fn parse_error_location() {
+        let error = |text: &str| Grid::from_bytes(text.as_bytes()).unwrap_err();
+        assert_eq!(
+            error("A B C \n\nD 4 F \n"),
+            GridError::InvalidPuzzleFormat {
+                line: 3,
+                token: "4".to_string()
+            }
+        );
+        assert_eq!(
+            error("A B \n[C!T] D \n"),
+            GridError::InvalidPuzzleFormat {
+                line: 2,
+                token: "!".to_string()
+            }
+        );
+        assert_eq!(
+            error("A B \n[] D \n"),
+            GridError::InvalidPuzzleFormat {
+                line: 2,
+                token: "[]".to_string()
+            }
+        );
+        assert_eq!(
+            error("AB ▢ \n").to_string(),
+            "Invalid puzzle file format on line 1: unexpected \"AB\""
+        );
+    }
+
+    #[test]
+    fn ragged_rows() {
+        assert_eq!(
+            Grid::from_bytes("A B C \nD E \nF G H \n".as_bytes()),
+            Err(GridError::RaggedGrid {
+                row: 1,
+                expected: 3,
+                found: 2
+            })
+        );
+        let ragged = Grid(vec![vec![Cell::Empty; 2], vec![Cell::Empty; 1]]);
+        assert_eq!(
+            ragged.is_square(),
+            Err(PuzzleError::Grid(GridError::RaggedGrid {
+                row: 1,
+                expected: 2,
+                found: 1
+            }))
+        );
+        let wide = Grid(vec![vec![Cell::Empty; 3]; 2]);
+        assert_eq!(
+            wide.is_square(),
+            Err(PuzzleError::Grid(GridError::NotSquare {
+                rows: 2,
+                columns: 3
+            }))
+        );
+        assert_eq!(Grid::new(3).is_square(), Ok(()));
+    }
+
+    #[test]
+    fn malformed_tokens() {
+        // Tokens are whole cells, so extra characters and odd whitespace are errors rather than being dropped
+        assert!(Grid::from_bytes("AB ▢ \n".as_bytes()).is_err());
+        assert!(Grid::from_bytes("▩▢ ▢ \n".as_bytes()).is_err());
+        assert_eq!(
+            Grid::from_bytes("A\u{3000}B\u{a0}▢\n".as_bytes()).map(|grid| grid.get_row(0).len()),
+            Ok(3)
+        );
+    }
+
+    #[test]
+    fn cell_predicates() {
+        let cells = [
+            Cell::Black,
+            Cell::Empty,
+            Cell::Letter('A'),
+            Cell::Rebus("CAT".to_string()),
+        ];
+        let flags = |cell: &Cell| {
+            (
+                cell.is_black(),
+                cell.is_empty(),
+                cell.is_letter(),
+                cell.is_white(),
+            )
+        };
+        assert_eq!(
+            cells.iter().map(flags).collect::<Vec<_>>(),
+            vec![
+                (true, false, false, false),
+                (false, true, false, true),
+                (false, false, true, true),
+                (false, false, true, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_base() {
+        let empty = Grid::from_template("#..\n...\n..#").unwrap();
+        let filled = Grid::from_template("#AB\nCDE\nFG#").unwrap();
+        let other = Grid::from_template("..#\n...\n#..").unwrap();
+        assert!(empty.same_base(&filled));
+        assert!(filled.same_base(&empty));
+        assert!(empty.same_base(&empty));
+        assert!(!empty.same_base(&other));
+        assert!(!Grid::new(3).same_base(&Grid::new(4)));
+        assert!(!Grid::new(3).same_base(&Grid(vec![vec![Cell::Empty; 3], vec![Cell::Empty; 2]])));
+    }
+
+    #[test]
+    fn base_fingerprint() {
+        let grid = |text: &str| Grid::from_bytes(text.as_bytes()).unwrap();
+        let empty = grid("▩ ▢ ▢ \n▢ ▢ ▢ \n▢ ▢ ▩ \n");
+        let filled = grid("▩ A B \nC D E \nF G ▩ \n");
+        let other = grid("▢ ▢ ▩ \n▢ ▢ ▢ \n▩ ▢ ▢ \n");
+        assert_eq!(empty.base_fingerprint(), filled.base_fingerprint());
+        assert_ne!(empty.base_fingerprint(), other.base_fingerprint());
+        assert_ne!(
+            Grid::new(3).base_fingerprint(),
+            Grid::new(4).base_fingerprint()
+        );
+    }
+
+    #[test]
+    fn get_column() {
+        let grid = Grid::from_bytes("A B ▩ \nC ▢ D \n[EF] G H \n".as_bytes()).unwrap();
+        let transpose = grid.transpose();
+        for col in 0..3 {
+            assert_eq!(grid.get_column(col).as_ref(), Ok(transpose.get_row(col)));
+        }
+        assert_eq!(
+            grid.get_column(3),
+            Err(GridError::OutOfBounds { x: 3, y: 0 })
+        );
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let grid = Grid::from_bytes("A ▩ [BC] \n▢ D ▩ \n▩ ▢ E \n".as_bytes()).unwrap();
+        let ascii = grid.render_ascii();
+        assert_eq!(ascii, "A # [BC] \n. D # \n# . E \n");
+        assert_eq!(Grid::from_bytes(ascii.as_bytes()), Ok(grid));
+    }
+
+    #[test]
+    fn compact_round_trip() {
+        let grid = Grid::from_bytes("A ▩ [BC] \n▢ D ▩ \n▩ ▢ E \n".as_bytes()).unwrap();
+        let compact = grid.render_compact(false);
+        assert_eq!(compact, "A▩[BC]\n▢D▩\n▩▢E\n");
+        assert_eq!(Grid::from_bytes(compact.as_bytes()), Ok(grid.clone()));
+        let ascii = grid.render_compact(true);
+        assert_eq!(ascii, "A#[BC]\n.D#\n#.E\n");
+        assert_eq!(Grid::from_bytes(ascii.as_bytes()), Ok(grid.clone()));
+        // The spaced layout still reads the same, and a grid a single cell wide is the same either way
+        assert_eq!(Grid::from_bytes(grid.to_string().as_bytes()), Ok(grid));
+        assert_eq!(Grid::from_bytes(b"A\n"), Grid::from_bytes(b"A \n"));
+
+        assert_eq!(
+            Grid::from_bytes("AB\nC!\n".as_bytes()),
+            Err(GridError::InvalidPuzzleFormat {
+                line: 2,
+                token: "!".to_string()
+            })
+        );
+        assert_eq!(
+            Grid::from_bytes("A[BC\n".as_bytes()),
+            Err(GridError::InvalidPuzzleFormat {
+                line: 1,
+                token: "[BC".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn asymmetry_cells() {
+        let grid = Grid::from_template("#....\n.....\n.....\n...#.\n....#").unwrap();
+        assert_eq!(
+            grid.asymmetry_cells(Symmetry::Rotational),
+            vec![(1, 1), (3, 3)]
+        );
+        assert_eq!(
+            grid.is_symmetric(Symmetry::Rotational),
+            Err(PuzzleError::NotSymmetric(vec![(1, 1), (3, 3)]))
+        );
+        assert_eq!(
+            PuzzleError::NotSymmetric(vec![(1, 1), (3, 3)]).to_string(),
+            "The black squares are not placed symmetrically at (1, 1), (3, 3)"
+        );
+        assert!(grid.asymmetry_cells(Symmetry::Diagonal).is_empty());
+    }
+
+    #[test]
+    fn symmetry_tolerance() {
+        let symmetric = Grid::from_template("#...\n....\n....\n...#").unwrap();
+        let one_off = Grid::from_template("#..#\n....\n....\n...#").unwrap();
+        let two_off = Grid::from_template("#..#\n....\n.#..\n...#").unwrap();
+        for (tolerance, passing) in [(0, 1), (1, 2), (2, 3)] {
+            for (mismatches, grid) in [&symmetric, &one_off, &two_off].into_iter().enumerate() {
+                assert_eq!(
+                    grid.is_nearly_symmetric(Symmetry::Rotational, tolerance)
+                        .is_ok(),
+                    mismatches < passing,
+                    "{} mismatches at tolerance {}",
+                    mismatches,
+                    tolerance
+                );
+            }
+        }
+        assert_eq!(
+            two_off.is_nearly_symmetric(Symmetry::Rotational, 1),
+            Err(PuzzleError::NotSymmetric(vec![
+                (3, 0),
+                (2, 1),
+                (1, 2),
+                (0, 3)
+            ]))
+        );
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let mut grid = Grid::new(3);
+        assert_eq!(grid.try_get(2, 2), Ok(&Cell::Empty));
+        assert_eq!(
+            grid.try_get(3, 0),
+            Err(GridError::OutOfBounds { x: 3, y: 0 })
+        );
+        assert_eq!(
+            grid.try_get(0, 3),
+            Err(GridError::OutOfBounds { x: 0, y: 3 })
+        );
+
+        assert_eq!(grid.try_set(2, 0, Cell::Black), Ok(()));
+        assert_eq!(grid.get(2, 0), &Cell::Black);
+        assert_eq!(
+            grid.try_set(0, 3, Cell::Black),
+            Err(GridError::OutOfBounds { x: 0, y: 3 })
+        );
+        assert_eq!(grid.count_cells(|cell| matches!(cell, Cell::Black)), 1);
+    }
+
+    #[test]
+    fn letters_are_uppercased() {
+        let mixed = Grid::from_bytes("c A t \nö ' - \n".as_bytes()).unwrap();
+        let upper = Grid::from_bytes("C A T \nÖ ' - \n".as_bytes()).unwrap();
+        assert_eq!(mixed, upper);
+        assert_eq!(mixed.get(0, 1), &Cell::Letter('Ö'));
+        assert_eq!(canonical_letter('ß'), 'ß');
+    }
+
+    #[test]
+    fn diff() {
+        let old = Grid::from_bytes("A B ▩ \n▢ C D \n".as_bytes()).unwrap();
+        let new = Grid::from_bytes("A X ▩ \n▩ C D \n".as_bytes()).unwrap();
+        assert_eq!(old.diff(&old), vec![]);
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                ((1, 0), Cell::Letter('B'), Cell::Letter('X')),
+                ((0, 1), Cell::Empty, Cell::Black)
+            ]
+        );
+        assert_eq!(
+            old.diff(&Grid::new(1)),
+            vec![((0, 0), Cell::Letter('A'), Cell::Empty)]
+        );
+    }
+
+    #[test]
+    fn rebus_cells() {
+        let grid = Grid::from_bytes("S [cat] T E R \n[A] ▩ ▢ ▢ ▢ \n".as_bytes()).unwrap();
+        assert_eq!(grid.get(1, 0), &Cell::Rebus("CAT".to_string()));
+        assert_eq!(grid.get(0, 1), &Cell::Letter('A'));
+        assert_eq!(Cell::as_string(grid.get_row(0)), "SCATTER");
+        assert_eq!(format!("{}", grid.get(1, 0)), "[CAT] ");
+        assert_eq!(Grid::from_bytes(format!("{}", grid).as_bytes()), Ok(grid));
+
+        assert!(Grid::from_bytes("[] A \n".as_bytes()).is_err());
+        assert!(Grid::from_bytes("[C!T] A \n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn from_template() {
+        let grid = Grid::from_template("\n  #ab\n  ...\n  o'#\n").unwrap();
+        assert_eq!(
+            grid,
+            Grid::from_bytes("▩ A B \n▢ ▢ ▢ \nO ' ▩ \n".as_bytes()).unwrap()
+        );
+        assert_eq!(
+            Grid::from_template("#..\n..\n..#"),
+            Err(GridError::RaggedGrid {
+                row: 1,
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            Grid::from_template("#..\n..."),
+            Err(GridError::NotSquare {
+                rows: 2,
+                columns: 3
+            })
+        );
+        assert_eq!(
+            Grid::from_template("#.\n.2"),
+            Err(GridError::InvalidPuzzleFormat {
+                line: 2,
+                token: "2".to_string()
+            })
+        );
+        assert_eq!(Grid::from_template(""), Ok(Grid::default()));
+    }
+
+    #[test]
+    fn black_square_threshold() {
+        // 4 of 25 is exactly 16%
+        let at_limit = Grid::from_template("#...#\n.....\n.....\n.....\n#...#").unwrap();
+        assert_eq!(at_limit.black_square_count(), 4);
+        assert_eq!(at_limit.black_square_fraction(), 0.16);
+        assert_eq!(at_limit.acceptable_black_square_count(16), Ok(()));
+        assert!(at_limit.acceptable_black_square_count(15).is_err());
+
+        // 8 of 49 is 16.3%, which rounding down to a whole percent would have let through
+        let over =
+            Grid::from_template("#.....#\n#.....#\n.......\n.......\n.......\n#.....#\n#.....#")
+                .unwrap();
+        assert_eq!(over.black_square_count(), 8);
+        assert_eq!(
+            over.acceptable_black_square_count(16),
+            Err(PuzzleError::TooManyBlackSquares(16))
+        );
+        assert_eq!(over.acceptable_black_square_count(17), Ok(()));
+
+        assert_eq!(Grid::default().black_square_fraction(), 0.0);
+        assert_eq!(Grid::default().acceptable_black_square_count(0), Ok(()));
+    }
+
+    #[test]
+    fn rotate_and_flip() {
+        let original = Grid::from_bytes("A B \nC ▩ \n".as_bytes()).unwrap();
+        let mut grid = original.clone();
+        grid.rotate(1);
+        assert_eq!(grid, Grid::from_bytes("C A \n▩ B \n".as_bytes()).unwrap());
+        grid.rotate(1);
+        assert_eq!(grid, Grid::from_bytes("▩ C \nB A \n".as_bytes()).unwrap());
+        grid.rotate(2);
+        assert_eq!(grid, original);
+        grid.rotate(3);
+        assert_eq!(grid, Grid::from_bytes("B ▩ \nA C \n".as_bytes()).unwrap());
+        grid.rotate(5);
+        assert_eq!(grid, original);
+
+        grid.flip(Axis::Horizontal);
+        assert_eq!(grid, Grid::from_bytes("B A \n▩ C \n".as_bytes()).unwrap());
+        grid.flip(Axis::Vertical);
+        assert_eq!(grid, Grid::from_bytes("▩ C \nB A \n".as_bytes()).unwrap());
+        assert_eq!("Vertical".parse(), Ok(Axis::Vertical));
+        assert!("diagonal".parse::<Axis>().is_err());
+    }
+
+    #[test]
+    fn iter_coords() {
+        let grid = Grid::from_bytes("A B \nC ▩ \n".as_bytes()).unwrap();
+        let cells: Vec<(usize, usize, &Cell)> = grid.iter_coords().collect();
+        assert_eq!(
+            cells,
+            vec![
+                (0, 0, &Cell::Letter('A')),
+                (1, 0, &Cell::Letter('B')),
+                (0, 1, &Cell::Letter('C')),
+                (1, 1, &Cell::Black)
+            ]
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        let unix = Grid::from_bytes("A B ▩ \n▢ C D \n▩ E F \n".as_bytes()).unwrap();
+        let windows = Grid::from_bytes("A B ▩ \r\n▢ C D \r\n▩ E F \r\n".as_bytes()).unwrap();
+        assert_eq!(windows, unix);
+        assert_eq!(windows.get(2, 1), &Cell::Letter('D'));
+
+        let no_trailing_space = Grid::from_bytes("A B ▩\r\n▢ C D\r\n▩ E F".as_bytes()).unwrap();
+        assert_eq!(no_trailing_space, unix);
+    }
+
+    #[test]
+    fn trailing_blank_lines() {
+        let grid = Grid::from_bytes("A B \nC D \n\n".as_bytes()).unwrap();
+        assert_eq!(grid.len(), 2);
+        let grid = Grid::from_bytes("A B \r\nC D \r\n  \r\n\r\n".as_bytes()).unwrap();
+        assert_eq!(grid.len(), 2);
+        assert_eq!(grid.get_row(1), &vec![Cell::Letter('C'), Cell::Letter('D')]);
     }
 }
@@ -0,0 +1,231 @@
+use std::{fmt, path::Path, str::FromStr};
+
+use crate::{
+    puz::PuzFile,
+    puzzle::{Puzzle, PuzzleError},
+};
+
+/// The file formats a puzzle can be imported from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// The format puzzles are saved in, with a header and a token per cell
+    Text,
+    /// A grid with a character per cell: . for empty, # for black and letters for themselves
+    Template,
+    Json,
+    /// An Across Lite file, read for its solution grid, title and circles
+    Puz,
+}
+
+impl ImportFormat {
+    pub const ALL: [ImportFormat; 4] = [
+        ImportFormat::Text,
+        ImportFormat::Template,
+        ImportFormat::Json,
+        ImportFormat::Puz,
+    ];
+
+    /// The file extension that marks a file as being in this format, if there is one. Templates are plain text too,
+    /// so they have to be asked for by name.
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            ImportFormat::Text => Some("txt"),
+            ImportFormat::Template => None,
+            ImportFormat::Json => Some("json"),
+            ImportFormat::Puz => Some("puz"),
+        }
+    }
+
+    /// Work out a file's format from its extension, ignoring case
+    pub fn from_path(path: &Path) -> Result<Self, PuzzleError> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+            .ok_or_else(|| PuzzleError::NoExtension(path.display().to_string()))?;
+        ImportFormat::ALL
+            .into_iter()
+            .find(|format| format.extension() == Some(extension.as_str()))
+            .ok_or(PuzzleError::UnknownFormat(format!(".{}", extension)))
+    }
+}
+
+impl fmt::Display for ImportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ImportFormat::Text => "text",
+            ImportFormat::Template => "template",
+            ImportFormat::Json => "json",
+            ImportFormat::Puz => "puz",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A format can be given by its name or its extension
+impl FromStr for ImportFormat {
+    type Err = PuzzleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().trim_start_matches('.').to_ascii_lowercase();
+        ImportFormat::ALL
+            .into_iter()
+            .find(|format| format.to_string() == s || format.extension() == Some(s.as_str()))
+            .ok_or(PuzzleError::UnknownFormat(s))
+    }
+}
+
+/// How the formats are listed in errors and help, each with its extension if it has one
+pub fn format_list() -> String {
+    ImportFormat::ALL
+        .map(|format| match format.extension() {
+            Some(extension) => format!("{} (.{})", format, extension),
+            None => format.to_string(),
+        })
+        .join(", ")
+}
+
+/// A puzzle read by `Puzzle::import`, with what the file said about itself besides the grid
+#[derive(Debug, Clone, PartialEq)]
+pub struct Imported {
+    pub puzzle: Puzzle,
+    /// The title stored in a `.puz` file
+    pub title: Option<String>,
+    /// False if a `.puz` file's checksums didn't match its contents, so it may be damaged
+    pub checksums_match: bool,
+}
+
+impl Puzzle {
+    /// Read a puzzle from the contents of a file in the given format, naming it name. A `.puz` file's own title is
+    /// returned alongside it rather than used as the name, since the name says where the puzzle is saved.
+    pub fn import(
+        name: String,
+        contents: &[u8],
+        format: ImportFormat,
+    ) -> Result<Imported, PuzzleError> {
+        let text = || {
+            std::str::from_utf8(contents)
+                .map_err(|e| PuzzleError::FileOpenError(format!("{}: {}", name, e)))
+        };
+        let puzzle = match format {
+            ImportFormat::Text => Puzzle::from_bytes(name, contents)?,
+            ImportFormat::Template => Puzzle::from_template(name.clone(), text()?)?,
+            ImportFormat::Json => Puzzle::from_json(text()?)?.with_name(name),
+            ImportFormat::Puz => {
+                let PuzFile {
+                    puzzle,
+                    checksums_match,
+                } = Puzzle::from_puz(contents)?;
+                return Ok(Imported {
+                    title: Some(puzzle.name().to_string()),
+                    puzzle: puzzle.with_name(name),
+                    checksums_match,
+                });
+            }
+        };
+        Ok(Imported {
+            puzzle,
+            title: None,
+            checksums_match: true,
+        })
+    }
+
+    /// Read a puzzle from the file at path, in the format its extension says unless format is given
+    pub fn import_file(
+        name: String,
+        path: &Path,
+        format: Option<ImportFormat>,
+    ) -> Result<Imported, PuzzleError> {
+        let format = match format {
+            Some(format) => format,
+            None => ImportFormat::from_path(path)?,
+        };
+        let contents = std::fs::read(path)
+            .map_err(|_e| PuzzleError::FileOpenError(path.display().to_string()))?;
+        Puzzle::import(name, &contents, format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        grid::Cell,
+        import::ImportFormat,
+        puzzle::{Puzzle, PuzzleError},
+    };
+
+    #[test]
+    fn format_from_path() {
+        let format = |path: &str| ImportFormat::from_path(Path::new(path));
+        assert_eq!(format("puzzles/mini.txt"), Ok(ImportFormat::Text));
+        assert_eq!(format("mini.JSON"), Ok(ImportFormat::Json));
+        assert_eq!(format("mini.puz"), Ok(ImportFormat::Puz));
+        assert_eq!(
+            format("mini"),
+            Err(PuzzleError::NoExtension("mini".to_string()))
+        );
+        assert!(format("mini").unwrap_err().to_string().contains("--format"));
+        let message = format("mini.svg").unwrap_err().to_string();
+        assert!(message.contains(".svg"));
+        assert!(message.contains("text (.txt), template, json (.json), puz (.puz)"));
+
+        assert_eq!("template".parse(), Ok(ImportFormat::Template));
+        assert_eq!(".json".parse(), Ok(ImportFormat::Json));
+        assert_eq!("TXT".parse(), Ok(ImportFormat::Text));
+        assert!("across lite".parse::<ImportFormat>().is_err());
+    }
+
+    #[test]
+    fn import_each_format() {
+        let mut puzzle = Puzzle::from_template("x".to_string(), "CA#\n...\n#..").unwrap();
+        puzzle.circle_cell(1, 1).unwrap();
+
+        let text = Puzzle::import(
+            "y".to_string(),
+            puzzle.save_text().as_bytes(),
+            ImportFormat::Text,
+        )
+        .unwrap()
+        .puzzle;
+        assert_eq!(text.cells(), puzzle.cells());
+        assert!(text.is_circled(1, 1));
+
+        let json = Puzzle::import(
+            "y".to_string(),
+            puzzle.to_json().as_bytes(),
+            ImportFormat::Json,
+        )
+        .unwrap()
+        .puzzle;
+        assert_eq!(json.name(), "y");
+        assert_eq!(json.cells(), puzzle.cells());
+
+        let template = Puzzle::import("y".to_string(), b"CA#\n...\n#..", ImportFormat::Template)
+            .unwrap()
+            .puzzle;
+        assert_eq!(template.get(0, 0), &Cell::Letter('C'));
+        assert_eq!(template.cells(), puzzle.cells());
+
+        // A .puz file's title is kept apart from the name the puzzle is saved under
+        let puz = Puzzle::import(
+            "y".to_string(),
+            &puzzle.to_puz().unwrap(),
+            ImportFormat::Puz,
+        )
+        .unwrap();
+        assert_eq!(puz.puzzle.name(), "y");
+        assert_eq!(puz.title.as_deref(), Some("x"));
+        assert!(puz.checksums_match);
+        assert_eq!(puz.puzzle.cells(), puzzle.cells());
+
+        // Each parser rejects the others' formats
+        assert!(Puzzle::import("y".to_string(), b"CA#\n...\n#..", ImportFormat::Json).is_err());
+        assert!(Puzzle::import(
+            "y".to_string(),
+            puzzle.to_json().as_bytes(),
+            ImportFormat::Text
+        )
+        .is_err());
+    }
+}
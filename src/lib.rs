@@ -0,0 +1,31 @@
+//! Build and validate NYT-style crossword puzzles. The `crossword-builder` binary is a command line front end to
+//! this library; everything it does is available here too, and `prelude` gathers the types most code needs.
+
+pub mod dictionary;
+pub mod editor;
+pub mod export;
+pub mod grid;
+pub mod import;
+pub mod puz;
+pub mod puzzle;
+pub mod template;
+
+/// The wordlist loaded when no other is chosen with `dictionary::set_sources`
+pub static DICTIONARY_FILE: &str = "./english3.txt";
+/// Where puzzles are saved and opened by name
+pub static PUZZLE_DIR: &str = "puzzles";
+/// The default for `Rules::max_black_percent`
+pub static PERCENT_BLACK: usize = 16;
+/// The default for `Rules::min_word_len`
+pub static MIN_WORD_LEN: usize = 3;
+/// Characters besides letters that can appear in answers, like the apostrophe in "O'ER" or the hyphen in "WELL-BEING"
+pub static EXTRA_WORD_CHARS: &[char] = &['\'', '-'];
+
+/// The types needed to open, edit, check and fill a puzzle
+pub mod prelude {
+    pub use crate::{
+        dictionary::{Dictionary, DictionaryError, SparseWord, DICTIONARY},
+        grid::{Cell, Grid, GridError, Symmetry},
+        puzzle::{Direction, Puzzle, PuzzleError, Rule, Rules},
+    };
+}
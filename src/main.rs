@@ -1,16 +1,27 @@
-use clap::{Args, Parser, Subcommand};
-use dictionary::DICTIONARY;
-use puzzle::Puzzle;
-use std::fs::{self};
-
-mod dictionary;
-mod grid;
-mod puzzle;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use crossword_builder::{
+    dictionary::{self, DictionaryError, DICTIONARY},
+    editor::Editor,
+    export::CELL_SIZE,
+    grid::{Axis, GridError, Symmetry},
+    import::{format_list, ImportFormat},
+    puzzle::{Anchor, Direction, Puzzle, PuzzleError, Renumbering, Rule, Rules, SolveOutcome},
+    template::Template,
+    MIN_WORD_LEN, PERCENT_BLACK, PUZZLE_DIR,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{
+    fs::{self},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
 /*
 
 Improvements:
-    + display for puzzle - print name & size
-    + Prettier error handling and display
     + Better file format
 */
 
@@ -19,119 +30,1307 @@ Improvements:
 #[command(propagate_version = true)]
 /// A command line utility to help build crossword puzzles
 struct Cli {
-    name: String,
+    /// The puzzle to work on, or - to read it from stdin and write any changes to stdout. Every command but
+    /// `completion` needs one.
+    name: Option<String>,
+    /// The largest percentage of the grid that may be black squares
+    #[arg(long, global = true, default_value_t = PERCENT_BLACK,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(0..=100))]
+    max_black_percent: usize,
+    /// The fewest letters a word may have
+    #[arg(long, global = true, default_value_t = MIN_WORD_LEN,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    min_word_len: usize,
+    /// How many black squares may break the grid's symmetry before the base is invalid
+    #[arg(long, global = true, default_value_t = 0)]
+    symmetry_tolerance: usize,
+    /// Seed the random number generator so generated grids and letters can be reproduced
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+    /// A wordlist to use instead of the default one. Repeat to combine several, most preferred first.
+    #[arg(long = "dictionary", global = true)]
+    dictionaries: Vec<PathBuf>,
+    /// A word frequency list with a word,rank pair on each line, for `suggest --by-frequency`
+    #[arg(long, global = true)]
+    frequencies: Option<PathBuf>,
+    /// Don't load any wordlist, for quick work on the black squares. Commands that need words fail instead.
+    #[arg(long, global = true, conflicts_with_all = ["dictionaries", "frequencies"])]
+    no_dictionary: bool,
+    /// Trust the wordlists to hold one lowercase word per line, skipping the checks on each line when they're loaded
+    #[arg(long, global = true, conflicts_with = "no_dictionary")]
+    canonical_dictionary: bool,
+    /// Show and save grids with # for black squares and . for empty cells instead of ▩ and ▢
+    #[arg(long, global = true)]
+    ascii: bool,
+    /// Save grids with a character per cell and no spaces between them, which is easier to edit by hand and diff.
+    /// Either layout can be opened.
+    #[arg(long, global = true)]
+    compact: bool,
+    /// Show what a command would produce without writing any files
+    #[arg(long, global = true)]
+    dry_run: bool,
     #[command(subcommand)]
     command: Commands,
 }
 
+/// The puzzle a command operates on, along with the settings from the global flags
+struct Context {
+    name: String,
+    rules: Rules,
+    seed: Option<u64>,
+    ascii: bool,
+    compact: bool,
+    dry_run: bool,
+}
+
+impl Context {
+    fn new_puzzle(&self, size: usize) -> Puzzle {
+        Puzzle::new(self.name.clone(), size)
+            .with_rules(self.rules)
+            .with_ascii(self.ascii)
+            .with_compact(self.compact)
+    }
+
+    /// A random number generator seeded from `--seed` if it was given, or from the OS otherwise
+    fn rng(&self) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        }
+    }
+
+    /// Show the updated grid and save it, unless this is a dry run. A puzzle piped through stdout is only written
+    /// once, as the saved puzzle.
+    fn save(&self, puzzle: &Puzzle) -> Result<(), AppError> {
+        if self.dry_run {
+            println!("{}", puzzle.grid_text());
+            eprintln!("Dry run, so nothing was saved");
+            return Ok(());
+        }
+        if !puzzle.is_stdio() {
+            println!("{}", puzzle.grid_text());
+        }
+        puzzle.save_to_file()?;
+        Ok(())
+    }
+
+    /// Save a puzzle whose black squares may have moved, then say on stderr how its clue numbers changed from before,
+    /// so clues written elsewhere can be brought up to date
+    fn save_renumbered(&self, before: &Puzzle, puzzle: &Puzzle) -> Result<(), AppError> {
+        self.save(puzzle)?;
+        if before.size() != puzzle.size() {
+            eprintln!(
+                "The grid is now {}x{}, so every clue number should be checked",
+                puzzle.size(),
+                puzzle.size()
+            );
+            return Ok(());
+        }
+        let renumbering = puzzle.renumbering(before);
+        if !renumbering.is_empty() {
+            for line in renumbering_lines(&renumbering) {
+                eprintln!("{}", line);
+            }
+        }
+        Ok(())
+    }
+
+    fn open(&self) -> Result<Puzzle, AppError> {
+        Ok(Puzzle::open_from_file(self.name.clone())?
+            .with_rules(self.rules)
+            .with_ascii(self.ascii)
+            .with_compact(self.compact))
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Generate a new, blank crossword puzzle.
     New(New),
     /// Fill a puzzle with random letters.
-    RandomFill,
+    RandomFill(RandomFill),
+    /// Erase every letter, keeping the black squares
+    ClearAll,
+    /// Replace an existing puzzle's grid with a new random pattern of black squares, erasing its letters
+    Shuffle,
     /// Validate the base grid of a puzzle
-    CheckBase,
+    CheckBase(Check),
     /// Validate the puzzle's words
-    CheckWords,
+    CheckWords(Check),
+    /// Run every base and word check, reporting all the problems found
+    Validate,
+    /// Find partially filled slots that no dictionary word fits
+    CheckFill,
+    /// Succeed only if every white square has a letter
+    CheckComplete,
     /// Display the puzzle
     Display,
+    /// List every answer with its clue number and starting (x, y), like a solution key
+    Words,
+    /// Explain a cell index: its row and column and the across and down words through it
+    Describe(Describe),
+    /// Show which cells a black square at an index would fill symmetrically, and whether the placement is valid
+    Preview(Preview),
+    /// Report word counts, black square density and fill progress
+    Stats,
+    /// Shade each empty cell by how many words fit its most constrained slot, darkest where fill is hardest
+    Heatmap(Heatmap),
+    /// Interactively edit the puzzle in the terminal
+    Edit,
+    /// Save the puzzle in another format alongside the puzzle file
+    Export(Export),
+    #[command(about = format!("Create the puzzle from a file in one of these formats: {}", format_list()))]
+    Import(Import),
+    /// Grow or shrink the puzzle to a new size
+    Resize(Resize),
+    /// Remove rows and columns of black squares from the edges of the grid
+    Trim,
+    /// Save a copy of the puzzle under a new name
+    Copy(CopyTo),
+    /// List the cells that differ between this puzzle and another
+    Diff(Diff),
+    /// Show how clue numbers changed since an earlier version of the puzzle
+    Renumber(Renumber),
+    /// Rotate or flip the whole puzzle
+    Transform(Transform),
 
     Suggest(Suggest),
+    /// Suggest words for both the across and down slots through a cell, side by side
+    Cross(Cross),
+    /// Write a word into the slot starting at an index, or every word listed in a file
+    Fill(Fill),
+    /// Fill the slot starting at an index with the word that leaves its crossings the most options
+    Autofill(Autofill),
+    /// Fill every empty cell so that each slot holds a dictionary word
+    Solve(Solve),
+    /// Circle a cell, or remove its circle
+    Circle(Circle),
+    /// Lock a cell so filling and clearing leave it as it is, or unlock it
+    Lock(Lock),
+    /// Put a bar after a cell that ends words in a direction, as in a barred puzzle, or remove it
+    Bar(Bar),
+    /// Place a theme entry, reserving a slot of the same length in the symmetric position
+    Theme(Entry),
+    /// Search the dictionary for words of any length matching a pattern, using ? and * as wildcards and [abc] for any
+    /// one of a group of letters
+    Search(Search),
+    /// Report how many words the dictionary holds, by length
+    DictInfo,
+    /// Print a script that sets up tab completion in a shell. No puzzle name is needed.
+    Completion(Completion),
 }
 
 #[derive(Args)]
 struct Suggest {
     index: usize,
-    direction: String,
+    direction: Direction,
+    /// How many words to list, or 0 for every word that fits, which can be a long list for an empty slot
+    #[arg(default_value_t = 5)]
+    count: usize,
+    /// Only suggest words that leave every crossing slot with at least one word that fits
+    #[arg(long)]
+    constrained: bool,
+    /// Suggest the most common words first, using the list given with --frequencies, instead of favoring earlier
+    /// wordlists
+    #[arg(long, conflicts_with = "constrained")]
+    by_frequency: bool,
+    /// Drop words that would give the slot a fill quality below this, from 0 to 1, where 1 means every letter is as
+    /// common as E. Letters the slot shares with crossing slots count twice.
+    #[arg(long)]
+    min_quality: Option<f64>,
+}
+
+#[derive(Args)]
+struct Cross {
+    x: usize,
+    y: usize,
+    /// How many words to list for each slot, or 0 for every word that fits
+    #[arg(default_value_t = 5)]
+    count: usize,
+}
+
+#[derive(Args)]
+struct Completion {
+    shell: Shell,
+}
+
+#[derive(Args)]
+struct Solve {
+    /// Stop after this many seconds and save the fullest fill found so far
+    #[arg(long)]
+    timeout: Option<f64>,
+}
+
+#[derive(Args)]
+struct Autofill {
+    index: usize,
+    direction: Direction,
+    /// Prefer words that use letters the grid is still missing, and report whether it now uses all 26
+    #[arg(long)]
+    pangram: bool,
+}
+
+#[derive(Args)]
+struct RandomFill {
+    /// Use every letter the grid is missing before picking the rest at random, and report whether it's a pangram
+    #[arg(long)]
+    pangram: bool,
+}
+
+#[derive(Args)]
+struct Fill {
+    #[arg(required_unless_present = "from_file")]
+    index: Option<usize>,
+    #[arg(required_unless_present = "from_file")]
+    direction: Option<Direction>,
+    #[arg(required_unless_present = "from_file")]
+    word: Option<String>,
+    /// Apply a file of `index direction word` lines in order, reporting the lines that can't be filled
+    #[arg(long, conflicts_with_all = ["index", "direction", "word"])]
+    from_file: Option<PathBuf>,
+    /// Give up without saving at the first line that can't be filled, rather than applying the rest
+    #[arg(long, requires = "from_file")]
+    stop_on_error: bool,
+}
+
+#[derive(Args)]
+struct Entry {
+    index: usize,
+    direction: Direction,
+    word: String,
+}
+
+#[derive(Args)]
+struct Circle {
+    x: usize,
+    y: usize,
+    /// Remove the circle instead of adding one
+    #[arg(long)]
+    remove: bool,
+}
+
+#[derive(Args)]
+struct Lock {
+    x: usize,
+    y: usize,
+    /// Unlock the cell instead of locking it
+    #[arg(long)]
+    remove: bool,
+}
+
+#[derive(Args)]
+struct Bar {
+    x: usize,
+    y: usize,
+    /// across for a bar on the cell's right edge, down for one on its bottom edge
+    direction: Direction,
+    /// Remove the bar instead of adding one
+    #[arg(long)]
+    remove: bool,
+}
+
+#[derive(Args)]
+struct Search {
+    pattern: String,
+    /// How many words to list, or 0 for every match
     #[arg(default_value_t = 5)]
     count: usize,
+    /// Report how many words match at each length instead of listing them, to help choose a slot length
+    #[arg(long, conflicts_with = "count")]
+    count_per_length: bool,
+}
+
+#[derive(Args)]
+struct Describe {
+    /// The cell, numbered left to right and top to bottom from 0 in the top left
+    index: usize,
+}
+
+#[derive(Args)]
+struct Check {
+    /// Run only one rule, by number or name: 1 symmetry, 2 black-count, 3 unkeyed, 4 min-length, 5 interlock,
+    /// 7 repeats or 8 dictionary
+    #[arg(long)]
+    rule: Option<Rule>,
+}
+
+#[derive(Args)]
+struct Preview {
+    /// The cell, numbered left to right and top to bottom from 0 in the top left
+    index: usize,
+}
+
+#[derive(Args)]
+struct Heatmap {
+    /// Show the number of words that fit instead of a shade
+    #[arg(long)]
+    numbers: bool,
+}
+
+#[derive(Args)]
+struct Export {
+    #[arg(long, value_enum)]
+    format: Format,
+    /// The width and height of each cell in a PNG, in pixels
+    #[arg(long, default_value_t = CELL_SIZE as u32)]
+    cell_size: u32,
+    /// Where to write the export instead of the puzzle directory, or - for stdout
+    #[arg(long)]
+    output: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Svg,
+    Png,
+    Json,
+    /// An Across Lite file with the grid and blank clues
+    Puz,
+}
+
+#[derive(Args)]
+struct Import {
+    /// The file to read. The puzzle is saved under the name given before the command, even if the file has a title.
+    file: PathBuf,
+    #[arg(
+        long,
+        conflicts_with = "template",
+        help = format!("The file's format, if its extension doesn't give it away: {}", format_list())
+    )]
+    format: Option<ImportFormat>,
+    /// Read the file as a grid with a character per cell: . for empty, # for black and letters for themselves. The
+    /// same as --format template.
+    #[arg(long)]
+    template: bool,
+    /// Where to save the imported puzzle instead of the puzzle directory, or - for stdout
+    #[arg(long)]
+    output: Option<String>,
+}
+
+#[derive(Args)]
+struct Resize {
+    #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    size: usize,
+    /// Keep the existing grid in the middle rather than the top left
+    #[arg(long)]
+    center: bool,
+    /// Shrink even if letters or black squares would be cut off
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args)]
+struct Renumber {
+    /// The name of the earlier version to compare against
+    before: String,
+}
+
+#[derive(Args)]
+struct CopyTo {
+    /// The name to save the copy under
+    new_name: String,
+    /// Replace a puzzle already saved under the new name
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args)]
+struct Diff {
+    /// The name of the puzzle to compare against
+    other: String,
+}
+
+#[derive(Args)]
+struct Transform {
+    /// Degrees to rotate clockwise: 90, 180 or 270
+    #[arg(long, value_parser = parse_quarter_turns)]
+    rotate: Option<u8>,
+    /// Mirror the puzzle: horizontal or vertical. Applied after any rotation.
+    #[arg(long)]
+    flip: Option<Axis>,
+}
+
+fn parse_quarter_turns(degrees: &str) -> Result<u8, String> {
+    match degrees {
+        "90" => Ok(1),
+        "180" => Ok(2),
+        "270" => Ok(3),
+        other => Err(format!(
+            "can't rotate by {} degrees, expected 90, 180 or 270",
+            other
+        )),
+    }
 }
 
 #[derive(Args)]
 struct New {
     #[arg(default_value_t = 3)]
     size: usize,
+    /// How the black squares are arranged: rotational, horizontal, vertical or diagonal
+    #[arg(long, default_value_t = Symmetry::Rotational)]
+    symmetry: Symmetry,
+    /// Keep generating until the base passes every check, rather than stopping at the first attempt
+    #[arg(long)]
+    valid: bool,
+    /// Start from a standard black square pattern instead of a random one: mini (5x5), daily (15x15) or sunday
+    /// (21x21). Overrides the size and symmetry.
+    #[arg(long, conflicts_with = "valid")]
+    template: Option<Template>,
+    /// Report each attempt at a valid base on stderr
+    #[arg(long, requires = "valid")]
+    verbose: bool,
+    /// Place exactly this many black squares, counting every symmetric partner
+    #[arg(long, conflicts_with_all = ["valid", "template"])]
+    blacks: Option<usize>,
+}
+
+/// The width in characters of the longest bar in the `dict-info` histogram
+static HISTOGRAM_WIDTH: usize = 40;
+/// Heatmap shades from the fewest words fitting a cell to the most, each step covering ten times as many words
+static HEATMAP_SHADES: [char; 4] = ['█', '▓', '▒', '░'];
+/// The heatmap shades used with `--ascii`
+static HEATMAP_ASCII_SHADES: [char; 4] = ['@', '%', '+', '-'];
+
+/// Every way a command can fail, so that handlers can return errors and leave printing to `main`
+#[derive(Error, Debug)]
+enum AppError {
+    #[error(transparent)]
+    Puzzle(#[from] PuzzleError),
+    #[error(transparent)]
+    Grid(#[from] GridError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Dictionary(#[from] DictionaryError),
+    #[error("Error creating dir {0}: {1}")]
+    CreateDir(&'static str, io::Error),
+    #[error("Puzzle base is invalid: {0}")]
+    InvalidBase(PuzzleError),
+    #[error("Puzzle words are invalid: {0}")]
+    InvalidWords(PuzzleError),
+    #[error("{0} slot(s) can't be filled")]
+    Unfillable(usize),
+    #[error("No fill of dictionary words fits the letters already in the grid")]
+    Unsolvable,
+    #[error("The timeout must be a number of seconds from 0 up, got {0}")]
+    InvalidTimeout(f64),
+    #[error("Found {0} problem(s)")]
+    Violations(usize),
+    #[error("Puzzle is incomplete: {0} empty cell(s)")]
+    Incomplete(usize),
+    #[error("A puzzle named '{0}' already exists, use --force to replace it")]
+    AlreadyExists(String),
+    #[error("Line {0}: {1}")]
+    FillLine(usize, String),
+    #[error("{0} line(s) couldn't be filled")]
+    FailedFills(usize),
+    #[error("Can't compare puzzles of different sizes: {0}x{0} and {1}x{1}")]
+    DifferentSizes(usize, usize),
+    #[error("A puzzle name is needed before the command, or - for stdin and stdout")]
+    MissingName,
 }
 
-static DICTIONARY_FILE: &str = "./english3.txt";
-static PUZZLE_DIR: &str = "puzzles";
-static PERCENT_BLACK: usize = 16;
-static MAX_WORD_LEN: usize = 30;
-fn main() {
-    if let Err(e) = fs::create_dir_all(PUZZLE_DIR) {
-        println!("Error creating dir {}: {}", PUZZLE_DIR, e);
-        return;
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
     }
-    let cli = Cli::parse();
-    let name = cli.name;
+}
+
+fn run(cli: Cli) -> Result<(), AppError> {
+    // Completion doesn't touch any puzzle, so it shouldn't need a name or create the puzzle directory
+    if let Commands::Completion(completion) = &cli.command {
+        return print_completion(completion);
+    }
+    let name = cli.name.ok_or(AppError::MissingName)?;
+    if cli.no_dictionary {
+        dictionary::disable();
+    }
+    if cli.canonical_dictionary {
+        dictionary::set_canonical();
+    }
+    dictionary::set_sources(cli.dictionaries);
+    if let Some(frequencies) = cli.frequencies {
+        dictionary::set_frequencies(frequencies);
+    }
+    fs::create_dir_all(PUZZLE_DIR).map_err(|e| AppError::CreateDir(PUZZLE_DIR, e))?;
+    let ctx = Context {
+        name,
+        rules: Rules {
+            max_black_percent: cli.max_black_percent,
+            min_word_len: cli.min_word_len,
+            symmetry_tolerance: cli.symmetry_tolerance,
+        },
+        seed: cli.seed,
+        ascii: cli.ascii,
+        compact: cli.compact,
+        dry_run: cli.dry_run,
+    };
 
     match &cli.command {
-        Commands::New(new) => {
-            if new.size % 2 != 0 {
-                println!("Warning: program only generates valid puzzle bases of an even size.")
-            }
+        Commands::New(new) => new_puzzle(&ctx, new),
+        Commands::RandomFill(random) => random_fill(&ctx, random),
+        Commands::ClearAll => clear_all(&ctx),
+        Commands::Shuffle => shuffle(&ctx),
+        Commands::CheckBase(check) => check_base(&ctx, check),
+        Commands::CheckWords(check) => check_words(&ctx, check),
+        Commands::Validate => validate(&ctx),
+        Commands::CheckFill => check_fill(&ctx),
+        Commands::CheckComplete => check_complete(&ctx),
+        Commands::Display => display(&ctx),
+        Commands::Words => list_words(&ctx),
+        Commands::Describe(describe) => describe_cell(&ctx, describe),
+        Commands::Preview(preview) => preview_black(&ctx, preview),
+        Commands::Stats => stats(&ctx),
+        Commands::Edit => edit(&ctx),
+        Commands::Heatmap(heatmap) => show_heatmap(&ctx, heatmap),
+        Commands::Export(export) => export_puzzle(&ctx, export),
+        Commands::Import(import) => import_puzzle(&ctx, import),
+        Commands::Resize(resize) => resize_puzzle(&ctx, resize),
+        Commands::Trim => trim_puzzle(&ctx),
+        Commands::Copy(copy) => copy_puzzle(&ctx, copy),
+        Commands::Diff(diff) => diff_puzzles(&ctx, diff),
+        Commands::Renumber(renumber) => renumber_clues(&ctx, renumber),
+        Commands::Transform(transform) => transform_puzzle(&ctx, transform),
+        Commands::Suggest(suggest) => suggest_words(&ctx, suggest),
+        Commands::Cross(cross) => cross_words(&ctx, cross),
+        Commands::Fill(fill) => fill_word(&ctx, fill),
+        Commands::Autofill(autofill) => autofill_slot(&ctx, autofill),
+        Commands::Solve(solve) => solve_puzzle(&ctx, solve),
+        Commands::Circle(circle) => circle_cell(&ctx, circle),
+        Commands::Lock(lock) => lock_cell(&ctx, lock),
+        Commands::Bar(bar) => place_bar(&ctx, bar),
+        Commands::Theme(theme) => place_theme(&ctx, theme),
+        Commands::Search(search) => search_words(search),
+        Commands::DictInfo => dict_info(),
+        Commands::Completion(_) => {
+            unreachable!("completion is handled before the puzzle is set up")
+        }
+    }
+}
+
+fn new_puzzle(ctx: &Context, new: &New) -> Result<(), AppError> {
+    // A new puzzle replaces any saved under the same name, so its clue numbers are compared against that one
+    let replaced = match Puzzle::exists(&ctx.name) {
+        true => ctx.open().ok(),
+        false => None,
+    };
+    let save = |puzzle: &Puzzle| match &replaced {
+        Some(before) => ctx.save_renumbered(before, puzzle),
+        None => ctx.save(puzzle),
+    };
+    if let Some(template) = new.template {
+        let puzzle = Puzzle::from_preset(ctx.name.clone(), template)?
+            .with_rules(ctx.rules)
+            .with_ascii(ctx.ascii)
+            .with_compact(ctx.compact);
+        return save(&puzzle);
+    }
+    if !new.size.is_multiple_of(2) {
+        eprintln!("Warning: program only generates valid puzzle bases of an even size.")
+    }
 
-            let mut puzzle = Puzzle::new(name, new.size);
-            puzzle.random_black();
-            //let puzzle = Puzzle::random_valid_grid(name, new.size);
-            println!("{}", puzzle.cells());
-            match puzzle.save_to_file() {
-                Ok(_) => (),
-                Err(e) => println!("{}", e),
+    let mut puzzle = ctx.new_puzzle(new.size).with_symmetry(new.symmetry);
+    let mut rng = ctx.rng();
+    if new.valid {
+        let verbose = new.verbose;
+        let result = puzzle.random_valid_base(&mut rng, |attempt, max| {
+            if verbose {
+                eprint!("\rTrying base {}/{}", attempt, max);
             }
+        });
+        if verbose {
+            eprintln!();
+        }
+        result?;
+    } else if let Some(blacks) = new.blacks {
+        puzzle.random_black_count(&mut rng, blacks)?;
+    } else {
+        puzzle.random_black(&mut rng);
+    }
+    save(&puzzle)
+}
+
+fn random_fill(ctx: &Context, random: &RandomFill) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    puzzle.random_letters(&mut ctx.rng(), random.pangram);
+    ctx.save(&puzzle)?;
+    if random.pangram {
+        report_pangram(&puzzle);
+    }
+    Ok(())
+}
+
+/// Say on stderr whether the grid uses every letter, so the report stays out of a piped puzzle
+fn report_pangram(puzzle: &Puzzle) {
+    if puzzle.is_pangram() {
+        eprintln!("The grid is a pangram");
+    } else {
+        let missing: String = puzzle.missing_letters().into_iter().collect();
+        eprintln!("The grid is not a pangram, missing {}", missing);
+    }
+}
+
+fn shuffle(ctx: &Context) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    let before = puzzle.clone();
+    puzzle
+        .shuffle_black(&mut ctx.rng())
+        .map_err(AppError::InvalidBase)?;
+    ctx.save_renumbered(&before, &puzzle)
+}
+
+fn clear_all(ctx: &Context) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    puzzle.clear_letters();
+    ctx.save(&puzzle)?;
+    Ok(())
+}
+
+fn check_base(ctx: &Context, check: &Check) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    if let Some(rule) = check.rule {
+        return check_rule(&puzzle, rule, AppError::InvalidBase);
+    }
+    puzzle.validate_base().map_err(AppError::InvalidBase)?;
+    println!("Puzzle base is valid");
+    Ok(())
+}
+
+fn check_words(ctx: &Context, check: &Check) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    if let Some(rule) = check.rule {
+        return check_rule(&puzzle, rule, AppError::InvalidWords);
+    }
+    puzzle.validate_words().map_err(AppError::InvalidWords)?;
+    println!("Puzzle words are valid");
+    Ok(())
+}
+
+fn check_rule(
+    puzzle: &Puzzle,
+    rule: Rule,
+    invalid: fn(PuzzleError) -> AppError,
+) -> Result<(), AppError> {
+    puzzle.check_rule(rule).map_err(invalid)?;
+    println!("Puzzle passes rule {} ({})", rule.number(), rule);
+    Ok(())
+}
+
+fn validate(ctx: &Context) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    let mut problems = 0;
+    for (category, violations) in [
+        ("Base", puzzle.base_violations()),
+        ("Words", puzzle.word_violations()),
+    ] {
+        println!("{}:", category);
+        if violations.is_empty() {
+            println!("  ok");
+        }
+        for violation in violations.iter() {
+            println!("  - {}", violation);
+        }
+        problems += violations.len();
+    }
+    let fixes = puzzle.suggest_keying_fixes();
+    if !fixes.is_empty() {
+        let fixes: Vec<String> = fixes.iter().map(|coords| format!("{:?}", coords)).collect();
+        println!(
+            "Removing one of the black squares at {} along with its partners would key an unkeyed letter",
+            fixes.join(", ")
+        );
+    }
+    if problems > 0 {
+        return Err(AppError::Violations(problems));
+    }
+    Ok(())
+}
+
+fn check_fill(ctx: &Context) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    DICTIONARY.check_loaded()?;
+    let slots = puzzle.unfillable_slots();
+    if slots.is_empty() {
+        println!("Every slot can be filled");
+        return Ok(());
+    }
+    for (index, direction) in slots.iter() {
+        println!("No words fit {} at index {}", direction, index);
+    }
+    Err(AppError::Unfillable(slots.len()))
+}
+
+fn check_complete(ctx: &Context) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    if !puzzle.is_complete() {
+        return Err(AppError::Incomplete(puzzle.empty_cells()));
+    }
+    println!("Puzzle is complete");
+    Ok(())
+}
+
+fn display(ctx: &Context) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    puzzle.pretty_print();
+    Ok(())
+}
+
+fn list_words(ctx: &Context) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    let words = puzzle.words_with_positions();
+    for (heading, direction) in [("Across", Direction::Across), ("Down", Direction::Down)] {
+        println!("{}", heading);
+        let suffix = &heading[..1];
+        for (number, word) in words.iter().filter(|(_, word)| word.direction == direction) {
+            let (x, y) = word.start;
+            println!("  {}{} {} ({},{})", number, suffix, word.as_string(), x, y);
         }
-        Commands::RandomFill => match Puzzle::open_from_file(name) {
-            Ok(mut puzzle) => {
-                puzzle.random_letters();
-                println!("{}", puzzle.cells());
-                match puzzle.save_to_file() {
-                    Ok(_) => (),
-                    Err(e) => println!("Error saving puzzle to file: {}", e),
+    }
+    Ok(())
+}
+
+fn preview_black(ctx: &Context, preview: &Preview) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    let (x, y) = puzzle.index_coords(preview.index)?;
+    let (cells, valid) = puzzle.preview_symmetric((x, y));
+    for (x, y) in cells {
+        println!("({}, {}) index {}", x, y, y * puzzle.size() + x);
+    }
+    if valid {
+        println!("The placement is valid");
+    } else {
+        println!("The placement would leave a word shorter than the minimum length");
+    }
+    Ok(())
+}
+
+fn describe_cell(ctx: &Context, describe: &Describe) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    let (x, y) = puzzle.index_coords(describe.index)?;
+    println!(
+        "Index {} is row {}, column {}: {}",
+        describe.index,
+        y,
+        x,
+        puzzle.token(puzzle.get(x, y))
+    );
+    let numbers = puzzle.clue_numbers();
+    for (heading, direction) in [("Across", Direction::Across), ("Down", Direction::Down)] {
+        let Some(word) = puzzle.word_through(x, y, direction) else {
+            println!("{}: none, this is a black square", heading);
+            continue;
+        };
+        let (start_x, start_y) = word.start;
+        let start = start_y * puzzle.size() + start_x;
+        let clue = match numbers.get(&word.start) {
+            Some(number) if word.len >= 2 => format!("{}{}", number, &heading[..1]),
+            _ => "unnumbered".to_string(),
+        };
+        let position = if start == describe.index {
+            "starts here".to_string()
+        } else {
+            format!("starts at index {}", start)
+        };
+        println!(
+            "{}: {} letters, {}, {}, reads {}",
+            heading,
+            word.len,
+            clue,
+            position,
+            word.as_string()
+        );
+    }
+    Ok(())
+}
+
+fn stats(ctx: &Context) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    println!("{}", puzzle.stats());
+    Ok(())
+}
+
+fn edit(ctx: &Context) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    Editor::new(puzzle).run()?;
+    Ok(())
+}
+
+fn export_puzzle(ctx: &Context, export: &Export) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    let (contents, extension) = match export.format {
+        Format::Svg => (puzzle.to_svg().into_bytes(), "svg"),
+        Format::Png => (puzzle.to_png(export.cell_size), "png"),
+        Format::Json => (puzzle.to_json().into_bytes(), "json"),
+        Format::Puz => (puzzle.to_puz()?, "puz"),
+    };
+    let path = match &export.output {
+        Some(output) => output.clone(),
+        None => format!("{}/{}.{}", PUZZLE_DIR, ctx.name, extension),
+    };
+    if path == "-" {
+        io::stdout().write_all(&contents)?;
+        return Ok(());
+    }
+    if ctx.dry_run {
+        eprintln!("Dry run, so {} was not written", path);
+        return Ok(());
+    }
+    fs::write(&path, contents)?;
+    println!("Exported to {}", path);
+    Ok(())
+}
+
+fn import_puzzle(ctx: &Context, import: &Import) -> Result<(), AppError> {
+    let format = match import.template {
+        true => Some(ImportFormat::Template),
+        false => import.format,
+    };
+    let imported = Puzzle::import_file(ctx.name.clone(), &import.file, format)?;
+    if !imported.checksums_match {
+        eprintln!(
+            "Warning: the .puz file's checksums don't match its contents, so it may be damaged"
+        );
+    }
+    if let Some(title) = imported.title.filter(|title| *title != ctx.name) {
+        eprintln!(
+            "The file is titled \"{}\", but is saved as {}",
+            title, ctx.name
+        );
+    }
+    let puzzle = imported
+        .puzzle
+        .with_rules(ctx.rules)
+        .with_ascii(ctx.ascii)
+        .with_compact(ctx.compact);
+    match &import.output {
+        Some(output) if !ctx.dry_run => puzzle.save_to_path(output)?,
+        _ => ctx.save(&puzzle)?,
+    }
+    Ok(())
+}
+
+fn resize_puzzle(ctx: &Context, resize: &Resize) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    let before = puzzle.clone();
+    let anchor = if resize.center {
+        Anchor::Center
+    } else {
+        Anchor::TopLeft
+    };
+    let lost = puzzle.resize(resize.size, anchor, resize.force)?;
+    if lost > 0 {
+        eprintln!("Warning: removed {} letter(s) or black square(s)", lost);
+    }
+    ctx.save_renumbered(&before, &puzzle)
+}
+
+fn trim_puzzle(ctx: &Context) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    let before = puzzle.clone();
+    let trimmed = puzzle.trim_borders()?;
+    eprintln!("Trimmed {} row(s) and column(s)", trimmed);
+    ctx.save_renumbered(&before, &puzzle)
+}
+
+fn copy_puzzle(ctx: &Context, copy: &CopyTo) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    if !copy.force && Puzzle::exists(&copy.new_name) {
+        return Err(AppError::AlreadyExists(copy.new_name.clone()));
+    }
+    if ctx.dry_run {
+        eprintln!(
+            "Dry run, so {} was not copied to {}",
+            ctx.name, copy.new_name
+        );
+        return Ok(());
+    }
+    puzzle.with_name(copy.new_name.clone()).save_to_file()?;
+    if copy.new_name != "-" {
+        println!("Copied {} to {}", ctx.name, copy.new_name);
+    }
+    Ok(())
+}
+
+fn diff_puzzles(ctx: &Context, diff: &Diff) -> Result<(), AppError> {
+    let old = ctx.open()?;
+    let new = Puzzle::open_from_file(diff.other.clone())?;
+    if old.size() != new.size() {
+        return Err(AppError::DifferentSizes(old.size(), new.size()));
+    }
+    let changes = old.cells().diff(new.cells());
+    if changes.is_empty() {
+        println!("The puzzles are the same");
+    }
+    for ((x, y), before, after) in changes {
+        println!(
+            "({},{}): {} -> {}",
+            x,
+            y,
+            old.token(&before),
+            old.token(&after)
+        );
+    }
+    Ok(())
+}
+
+fn renumber_clues(ctx: &Context, renumber: &Renumber) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    let before = Puzzle::open_from_file(renumber.before.clone())?;
+    if before.size() != puzzle.size() {
+        return Err(AppError::DifferentSizes(before.size(), puzzle.size()));
+    }
+    for line in renumbering_lines(&puzzle.renumbering(&before)) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// How clue numbers changed, a line per moved or removed slot
+fn renumbering_lines(renumbering: &Renumbering) -> Vec<String> {
+    if renumbering.is_empty() {
+        return vec!["The clue numbers are unchanged".to_string()];
+    }
+    let moved = renumbering
+        .moved
+        .iter()
+        .map(|(direction, old, new)| format!("{} {} is now {} {}", old, direction, new, direction));
+    let removed = renumbering
+        .removed
+        .iter()
+        .map(|(direction, old)| format!("{} {} no longer exists", old, direction));
+    moved.chain(removed).collect()
+}
+
+fn transform_puzzle(ctx: &Context, transform: &Transform) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    let before = puzzle.clone();
+    if let Some(quarter_turns) = transform.rotate {
+        puzzle.rotate(quarter_turns)?;
+    }
+    if let Some(axis) = transform.flip {
+        puzzle.flip(axis)?;
+    }
+    ctx.save_renumbered(&before, &puzzle)
+}
+
+/// A count of 0 on the command line asks for every result
+fn limit(count: usize) -> usize {
+    match count {
+        0 => usize::MAX,
+        count => count,
+    }
+}
+
+fn suggest_words(ctx: &Context, suggest: &Suggest) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    let count = limit(suggest.count);
+    let (index, direction) = (suggest.index, suggest.direction);
+    let suggestions = match suggest.min_quality {
+        None if suggest.constrained => puzzle.suggest_constrained(index, direction, count)?,
+        None if suggest.by_frequency => puzzle.suggest_by_frequency(index, direction, count)?,
+        None => puzzle.suggest(index, direction, count)?,
+        Some(min_quality) => {
+            // Filtering can drop words, so look through every candidate, checking the quick quality score before
+            // the crossings
+            let candidates = if suggest.by_frequency {
+                puzzle.suggest_by_frequency(index, direction, usize::MAX)?
+            } else {
+                puzzle.suggest(index, direction, usize::MAX)?
+            };
+            let mut kept = Vec::new();
+            for word in candidates {
+                if kept.len() >= count {
+                    break;
                 }
-            }
-            Err(e) => println!("{}", e),
-        },
-        Commands::CheckBase => match Puzzle::open_from_file(name) {
-            Ok(puzzle) => match puzzle.validate_base() {
-                Ok(_) => println!("Puzzle base is valid"),
-                Err(e) => println!("Puzzle base is invalid: {}", e),
-            },
-            Err(e) => println!("{}", e),
-        },
-        Commands::CheckWords => match Puzzle::open_from_file(name) {
-            Ok(puzzle) => match puzzle.validate_words() {
-                Ok(_) => println!("Puzzle words are valid"),
-                Err(e) => println!("Puzzle words are invalid: {}", e),
-            },
-            Err(e) => println!("{}", e),
-        },
-        Commands::Display => match Puzzle::open_from_file(name) {
-            Ok(puzzle) => puzzle.pretty_print(),
-            Err(e) => println!("{}", e),
-        },
-        Commands::Suggest(suggest) => match Puzzle::open_from_file(name) {
-            Ok(puzzle) => {
-                let partial_word = match suggest.direction.as_str() {
-                    "across" => puzzle.get_across_word(suggest.index),
-                    "down" => puzzle.get_down_word(suggest.index),
-                    x => {
-                        println!("Expected across or down, got {}", x);
-                        return;
-                    }
-                };
-                match partial_word {
-                    Some(word) => {
-                        let suggestions = DICTIONARY.suggest_words(word, suggest.count);
-                        println!("{:?}", suggestions)
-                    }
-                    None => println!(
-                        "There is no {} word at index {}",
-                        suggest.direction, suggest.index
-                    ),
+                if puzzle.fill_quality(index, direction, &word)? >= min_quality
+                    && (!suggest.constrained
+                        || puzzle.keeps_crossings_fillable(index, direction, &word))
+                {
+                    kept.push(word);
                 }
             }
-            Err(e) => println!("{}", e),
-        },
+            kept
+        }
+    };
+    if suggestions.is_empty() {
+        let near = puzzle.suggest_near(suggest.index, suggest.direction, count, 1)?;
+        println!(
+            "No words fit exactly. Words off by one letter: {:?}",
+            displayed(&near)
+        );
+        return Ok(());
+    }
+    println!("{:?}", displayed(&suggestions));
+    Ok(())
+}
+
+fn cross_words(ctx: &Context, cross: &Cross) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    puzzle.cells().try_get(cross.x, cross.y)?;
+    let (across, down) = puzzle.slots_through(cross.x, cross.y);
+    let mut columns = Vec::new();
+    for (heading, slot) in [("Across", across), ("Down", down)] {
+        let Some(slot) = slot else {
+            columns.push(vec![format!("{}: no slot", heading)]);
+            continue;
+        };
+        let (x, y) = slot.start;
+        let index = y * puzzle.size() + x;
+        let mut column = vec![format!("{} at {}: {}", heading, index, slot.as_string())];
+        column.extend(displayed(&puzzle.suggest(
+            index,
+            slot.direction,
+            limit(cross.count),
+        )?));
+        columns.push(column);
+    }
+    let width = columns[0].iter().map(|line| line.chars().count()).max();
+    for row in 0..columns.iter().map(Vec::len).max().unwrap_or(0) {
+        let cell = |column: &Vec<String>| column.get(row).cloned().unwrap_or_default();
+        println!(
+            "{:width$}   {}",
+            cell(&columns[0]),
+            cell(&columns[1]),
+            width = width.unwrap_or(0)
+        );
+    }
+    Ok(())
+}
+
+/// Suggested words as they're best read, with phrases spaced out
+fn displayed(words: &[String]) -> Vec<String> {
+    words.iter().map(|word| DICTIONARY.display(word)).collect()
+}
+
+fn fill_word(ctx: &Context, fill: &Fill) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    if let Some(path) = &fill.from_file {
+        return fill_from_file(ctx, puzzle, path, fill.stop_on_error);
+    }
+    // clap requires all three whenever --from-file is missing
+    let (Some(index), Some(direction), Some(word)) = (fill.index, fill.direction, &fill.word)
+    else {
+        unreachable!();
+    };
+    match direction {
+        Direction::Across => puzzle.fill_across(index, word)?,
+        Direction::Down => puzzle.fill_down(index, word)?,
+    }
+    ctx.save(&puzzle)?;
+    Ok(())
+}
+
+/// Apply each `index direction word` line of a file, skipping blank lines. Lines that don't parse or fit are reported
+/// with their line number, and the rest are still saved unless stop_on_error is set.
+fn fill_from_file(
+    ctx: &Context,
+    mut puzzle: Puzzle,
+    path: &Path,
+    stop_on_error: bool,
+) -> Result<(), AppError> {
+    let contents = fs::read_to_string(path)?;
+    let mut line_numbers = Vec::new();
+    let mut fills = Vec::new();
+    let mut failures = Vec::new();
+    for (number, line) in contents.lines().enumerate().map(|(i, line)| (i + 1, line)) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_fill(line) {
+            Ok(fill) => {
+                line_numbers.push(number);
+                fills.push(fill);
+            }
+            Err(e) => failures.push((number, e)),
+        }
+    }
+    for (number, result) in line_numbers.into_iter().zip(puzzle.apply_fills(&fills)) {
+        if let Err(e) = result {
+            failures.push((number, e.to_string()));
+        }
+    }
+    failures.sort();
+    if stop_on_error {
+        if let Some((number, e)) = failures.first() {
+            return Err(AppError::FillLine(*number, e.clone()));
+        }
+    }
+    for (number, e) in &failures {
+        eprintln!("Line {}: {}", number, e);
+    }
+    ctx.save(&puzzle)?;
+    match failures.len() {
+        0 => Ok(()),
+        failed => Err(AppError::FailedFills(failed)),
     }
 }
+
+/// Parse a line of `index direction word`, like `5 across cat`
+fn parse_fill(line: &str) -> Result<(usize, Direction, String), String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let [index, direction, word] = parts[..] else {
+        return Err(format!(
+            "expected \"index direction word\", got \"{}\"",
+            line.trim()
+        ));
+    };
+    let index = index
+        .parse()
+        .map_err(|_| format!("\"{}\" isn't an index", index))?;
+    let direction = direction.parse().map_err(|e: PuzzleError| e.to_string())?;
+    Ok((index, direction, word.to_string()))
+}
+
+fn autofill_slot(ctx: &Context, autofill: &Autofill) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    let word = puzzle.autofill_slot(autofill.index, autofill.direction, autofill.pangram)?;
+    eprintln!(
+        "Filled {} {} with {}",
+        autofill.index, autofill.direction, word
+    );
+    ctx.save(&puzzle)?;
+    if autofill.pangram {
+        report_pangram(&puzzle);
+    }
+    Ok(())
+}
+
+fn solve_puzzle(ctx: &Context, solve: &Solve) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    DICTIONARY.check_loaded()?;
+    let outcome = match solve.timeout {
+        Some(seconds) => {
+            let budget = Duration::try_from_secs_f64(seconds)
+                .map_err(|_e| AppError::InvalidTimeout(seconds))?;
+            puzzle.solve_with_budget(Instant::now() + budget)
+        }
+        None => puzzle.solve(),
+    };
+    match outcome {
+        SolveOutcome::Complete => eprintln!("Filled the grid"),
+        SolveOutcome::Partial(empty) => {
+            eprintln!("Ran out of time with {} empty cell(s) left", empty)
+        }
+        SolveOutcome::Unsatisfiable => return Err(AppError::Unsolvable),
+    }
+    ctx.save(&puzzle)?;
+    Ok(())
+}
+
+fn circle_cell(ctx: &Context, circle: &Circle) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    if circle.remove {
+        puzzle.uncircle_cell(circle.x, circle.y)?;
+    } else {
+        puzzle.circle_cell(circle.x, circle.y)?;
+    }
+    ctx.save(&puzzle)?;
+    Ok(())
+}
+
+fn lock_cell(ctx: &Context, lock: &Lock) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    if lock.remove {
+        puzzle.unlock_cell(lock.x, lock.y)?;
+    } else {
+        puzzle.lock_cell(lock.x, lock.y)?;
+    }
+    ctx.save(&puzzle)?;
+    Ok(())
+}
+
+fn place_bar(ctx: &Context, bar: &Bar) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    if bar.remove {
+        puzzle.remove_bar(bar.x, bar.y, bar.direction)?;
+    } else {
+        puzzle.add_bar(bar.x, bar.y, bar.direction)?;
+    }
+    ctx.save(&puzzle)?;
+    Ok(())
+}
+
+fn place_theme(ctx: &Context, theme: &Entry) -> Result<(), AppError> {
+    let mut puzzle = ctx.open()?;
+    let before = puzzle.clone();
+    puzzle.place_theme(&theme.word, theme.index, theme.direction)?;
+    ctx.save_renumbered(&before, &puzzle)
+}
+
+fn show_heatmap(ctx: &Context, heatmap: &Heatmap) -> Result<(), AppError> {
+    let puzzle = ctx.open()?;
+    DICTIONARY.check_loaded()?;
+    for (y, row) in puzzle.density_map().into_iter().enumerate() {
+        let tokens: Vec<String> = row
+            .into_iter()
+            .enumerate()
+            .map(|(x, count)| match (count, puzzle.get(x, y)) {
+                (Some(count), _) if heatmap.numbers => format!("{:>5}", count),
+                (Some(0), _) => "!".to_string(),
+                (Some(count), _) => {
+                    let shades = if ctx.ascii {
+                        &HEATMAP_ASCII_SHADES
+                    } else {
+                        &HEATMAP_SHADES
+                    };
+                    let shade = (count.ilog10() as usize).min(shades.len() - 1);
+                    shades[shade].to_string()
+                }
+                (None, cell) if heatmap.numbers => format!("{:>5}", puzzle.token(cell)),
+                (None, cell) => puzzle.token(cell),
+            })
+            .collect();
+        println!("{}", tokens.join(" "));
+    }
+    Ok(())
+}
+
+fn dict_info() -> Result<(), AppError> {
+    DICTIONARY.check_loaded()?;
+    let counts = DICTIONARY.counts_by_length();
+    let most = counts.iter().map(|&(_, count)| count).max().unwrap_or(0);
+    println!("Words: {}", DICTIONARY.word_count());
+    println!("Longest: {}", DICTIONARY.longest());
+    for (len, count) in counts {
+        let bar = "#".repeat((count * HISTOGRAM_WIDTH).div_ceil(most));
+        println!("{:>3} {:>7} {}", len, count, bar);
+    }
+    Ok(())
+}
+
+fn search_words(search: &Search) -> Result<(), AppError> {
+    DICTIONARY.check_loaded()?;
+    if search.count_per_length {
+        let counts = DICTIONARY.pattern_counts_by_length(&search.pattern);
+        if counts.is_empty() {
+            println!("No words match {}", search.pattern);
+            return Ok(());
+        }
+        let counts: Vec<String> = counts
+            .iter()
+            .map(|(len, count)| format!("len {}: {}", len, count))
+            .collect();
+        println!("{}", counts.join(", "));
+        return Ok(());
+    }
+    let suggestions = DICTIONARY.suggest_by_pattern(&search.pattern, limit(search.count));
+    println!("{:?}", suggestions);
+    Ok(())
+}
+
+fn print_completion(completion: &Completion) -> Result<(), AppError> {
+    // Generate into a buffer first, since writing straight to stdout panics if the pipe closes
+    let mut script = Vec::new();
+    clap_complete::generate(
+        completion.shell,
+        &mut Cli::command(),
+        env!("CARGO_PKG_NAME"),
+        &mut script,
+    );
+    io::stdout().write_all(&script)?;
+    Ok(())
+}
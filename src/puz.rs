@@ -0,0 +1,333 @@
+//! Reading and writing Across Lite `.puz` files, the binary format most published puzzles are shared in. Only the
+//! parts this crate can represent are kept: the solution grid, the title and circled cells. Clues are written empty.
+
+use crate::{
+    grid::{Cell, Grid},
+    puzzle::{Puzzle, PuzzleError},
+};
+
+/// The magic string that follows the file checksum at the start of the header
+const MAGIC: &[u8] = b"ACROSS&DOWN\0";
+/// The length of the fixed header before the grids
+const HEADER_LEN: usize = 0x34;
+/// Where the magic string sits in the header
+const MAGIC_OFFSET: usize = 0x02;
+/// The part of the header covered by the CIB checksum: width, height, clue count, puzzle type and scrambled tag
+const CIB: std::ops::Range<usize> = 0x2C..0x34;
+/// The extension section flagging circled cells, among other markup
+const GEXT: &[u8] = b"GEXT";
+const GEXT_CIRCLED: u8 = 0x80;
+
+/// How cells are written in the solution and player grids
+const BLACK: u8 = b'.';
+const EMPTY: u8 = b'-';
+
+/// Fold bytes into a running checksum the way Across Lite does: rotate right by one bit, then add the byte
+fn checksum(bytes: &[u8], mut sum: u16) -> u16 {
+    for &byte in bytes {
+        sum = sum.rotate_right(1).wrapping_add(u16::from(byte));
+    }
+    sum
+}
+
+/// Strings in a `.puz` file are ISO-8859-1, so each byte is a char with the same code point
+fn decode(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| char::from(byte)).collect()
+}
+
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .collect()
+}
+
+/// The checksum of the title, author, copyright, clues and notes, in that order. Empty strings other than clues are
+/// left out, and clues are summed without their terminating null.
+fn text_checksum(strings: &[Vec<u8>], clue_count: usize, mut sum: u16) -> u16 {
+    for (i, string) in strings.iter().enumerate() {
+        let is_clue = (3..3 + clue_count).contains(&i);
+        if is_clue {
+            sum = checksum(string, sum);
+        } else if !string.is_empty() {
+            sum = checksum(string, sum);
+            sum = checksum(&[0], sum);
+        }
+    }
+    sum
+}
+
+/// The header checksums worked out from the rest of the file: the overall checksum, the CIB checksum, and the eight
+/// masked checksum bytes
+fn checksums(
+    header: &[u8],
+    solution: &[u8],
+    player: &[u8],
+    strings: &[Vec<u8>],
+    clue_count: usize,
+) -> (u16, u16, [u8; 8]) {
+    let cib = checksum(&header[CIB], 0);
+    let overall = text_checksum(
+        strings,
+        clue_count,
+        checksum(player, checksum(solution, cib)),
+    );
+    let parts = [
+        cib,
+        checksum(solution, 0),
+        checksum(player, 0),
+        text_checksum(strings, clue_count, 0),
+    ];
+    let mut masked = [0; 8];
+    for (i, part) in parts.into_iter().enumerate() {
+        masked[i] = b"ICHE"[i] ^ part.to_le_bytes()[0];
+        masked[i + 4] = b"ATED"[i] ^ part.to_le_bytes()[1];
+    }
+    (overall, cib, masked)
+}
+
+/// A puzzle read from a `.puz` file, along with whether the file's checksums matched its contents
+#[derive(Debug, Clone, PartialEq)]
+pub struct PuzFile {
+    /// Named with the file's title, or "untitled" if it has none
+    pub puzzle: Puzzle,
+    /// A mismatch doesn't stop the grid being read, but suggests the file may be damaged
+    pub checksums_match: bool,
+}
+
+/// A reader over the bytes of a `.puz` file that reports running out of bytes as an error
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], PuzzleError> {
+        let taken = self
+            .bytes
+            .get(self.position..self.position + len)
+            .ok_or_else(|| PuzzleError::InvalidPuz("the file ends early".to_string()))?;
+        self.position += len;
+        Ok(taken)
+    }
+
+    /// A null-terminated string, without its null
+    fn string(&mut self) -> Result<&'a [u8], PuzzleError> {
+        let rest = &self.bytes[self.position..];
+        let len = rest
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| PuzzleError::InvalidPuz("the file ends early".to_string()))?;
+        self.position += len + 1;
+        Ok(&rest[..len])
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+}
+
+impl Puzzle {
+    /// Write the puzzle as an Across Lite `.puz` file titled with the puzzle's name. Letters go in the solution grid,
+    /// empty cells are written as `-`, a rebus keeps only its first letter, and every clue is left blank. Circled cells
+    /// are kept in a GEXT section. The format can't hold a grid more than 255 cells across.
+    pub fn to_puz(&self) -> Result<Vec<u8>, PuzzleError> {
+        let size =
+            u8::try_from(self.size()).map_err(|_e| PuzzleError::TooLargeForPuz(self.size()))?;
+        let mut solution = Vec::new();
+        let mut player = Vec::new();
+        let mut circles = Vec::new();
+        for y in 0..self.size() {
+            for x in 0..self.size() {
+                let cell = self.get(x, y);
+                let letter = match cell {
+                    Cell::Black => BLACK,
+                    Cell::Empty => EMPTY,
+                    Cell::Letter(letter) => encode(&letter.to_string())[0],
+                    Cell::Rebus(letters) => encode(letters).first().copied().unwrap_or(EMPTY),
+                };
+                solution.push(letter);
+                player.push(if cell.is_black() { BLACK } else { EMPTY });
+                circles.push(if self.is_circled(x, y) && cell.is_white() {
+                    GEXT_CIRCLED
+                } else {
+                    0
+                });
+            }
+        }
+
+        let clue_count = self.words_with_positions().len();
+
+        let mut strings = vec![encode(self.name()), Vec::new(), Vec::new()];
+        strings.extend(std::iter::repeat_n(Vec::new(), clue_count));
+        strings.push(Vec::new());
+
+        let mut header = vec![0; HEADER_LEN];
+        header[MAGIC_OFFSET..MAGIC_OFFSET + MAGIC.len()].copy_from_slice(MAGIC);
+        header[0x18..0x1C].copy_from_slice(b"1.3\0");
+        header[0x2C] = size;
+        header[0x2D] = size;
+        header[0x2E..0x30].copy_from_slice(&(clue_count as u16).to_le_bytes());
+        header[0x30..0x32].copy_from_slice(&1u16.to_le_bytes());
+        let (overall, cib, masked) = checksums(&header, &solution, &player, &strings, clue_count);
+        header[0x00..0x02].copy_from_slice(&overall.to_le_bytes());
+        header[0x0E..0x10].copy_from_slice(&cib.to_le_bytes());
+        header[0x10..0x18].copy_from_slice(&masked);
+
+        let mut puz = header;
+        puz.extend(&solution);
+        puz.extend(&player);
+        for string in &strings {
+            puz.extend(string);
+            puz.push(0);
+        }
+        if circles.iter().any(|&flags| flags != 0) {
+            puz.extend(GEXT);
+            puz.extend((circles.len() as u16).to_le_bytes());
+            puz.extend(checksum(&circles, 0).to_le_bytes());
+            puz.extend(&circles);
+            puz.push(0);
+        }
+        Ok(puz)
+    }
+
+    /// Read an Across Lite `.puz` file, naming the puzzle with its title. The solution grid gives the cells: `.` is a
+    /// black square, letters are themselves and anything else is empty. Circles are read from a GEXT section if
+    /// there is one. Checksums that don't match are reported rather than refused, since the grid may well be fine, but
+    /// a scrambled solution or a grid that isn't square can't be loaded.
+    pub fn from_puz(contents: &[u8]) -> Result<PuzFile, PuzzleError> {
+        // Some files have junk before the header, so find it by its magic string
+        let start = contents
+            .windows(MAGIC.len())
+            .position(|window| window == MAGIC)
+            .and_then(|position| position.checked_sub(MAGIC_OFFSET))
+            .ok_or_else(|| PuzzleError::InvalidPuz("no Across Lite header".to_string()))?;
+        let mut reader = Reader {
+            bytes: &contents[start..],
+            position: 0,
+        };
+        let header = reader.take(HEADER_LEN)?;
+        let (width, height) = (usize::from(header[0x2C]), usize::from(header[0x2D]));
+        let clue_count = usize::from(u16::from_le_bytes([header[0x2E], header[0x2F]]));
+        if u16::from_le_bytes([header[0x32], header[0x33]]) != 0 {
+            return Err(PuzzleError::InvalidPuz(
+                "the solution is scrambled".to_string(),
+            ));
+        }
+        if width != height {
+            return Err(PuzzleError::InvalidPuz(format!(
+                "the grid is {}x{}, but only square grids are supported",
+                width, height
+            )));
+        }
+
+        let solution = reader.take(width * height)?;
+        let player = reader.take(width * height)?;
+        let mut strings = Vec::new();
+        for _ in 0..3 + clue_count {
+            strings.push(reader.string()?.to_vec());
+        }
+        // Older files may stop before the notes
+        strings.push(match reader.remaining() {
+            0 => Vec::new(),
+            _ => reader.string()?.to_vec(),
+        });
+        let expected = checksums(header, solution, player, &strings, clue_count);
+        let found = (
+            u16::from_le_bytes([header[0x00], header[0x01]]),
+            u16::from_le_bytes([header[0x0E], header[0x0F]]),
+            header[0x10..0x18].try_into().unwrap(),
+        );
+
+        let cells = Grid(
+            solution
+                .chunks(width.max(1))
+                .take(height)
+                .map(|row| {
+                    row.iter()
+                        .map(|&byte| match byte {
+                            BLACK => Cell::Black,
+                            byte if byte.is_ascii_alphabetic() => {
+                                Cell::Letter(char::from(byte.to_ascii_uppercase()))
+                            }
+                            _ => Cell::Empty,
+                        })
+                        .collect()
+                })
+                .collect(),
+        );
+        let title = decode(&strings[0]).trim().to_string();
+        let name = if title.is_empty() {
+            "untitled".to_string()
+        } else {
+            title
+        };
+        let mut puzzle = Puzzle::from_grid(name, cells);
+
+        while reader.remaining() >= 8 {
+            let section = reader.take(4)?;
+            let len = usize::from(u16::from_le_bytes(reader.take(2)?.try_into().unwrap()));
+            reader.take(2)?;
+            let data = reader.take(len)?;
+            reader.take(1)?;
+            if section == GEXT && len == width * height {
+                for (index, &flags) in data.iter().enumerate() {
+                    if flags & GEXT_CIRCLED != 0 {
+                        puzzle.circle_cell(index % width, index / width)?;
+                    }
+                }
+            }
+        }
+        Ok(PuzFile {
+            puzzle,
+            checksums_match: found == expected,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        grid::Cell,
+        puzzle::{Puzzle, PuzzleError},
+    };
+
+    #[test]
+    fn puz_round_trip() {
+        let mut puzzle =
+            Puzzle::from_template("Mini".to_string(), "CAT#\nO..A\nW..P\n#TOE").unwrap();
+        puzzle.circle_cell(1, 1).unwrap();
+        let puz = puzzle.to_puz().unwrap();
+        assert_eq!(&puz[0x02..0x0E], b"ACROSS&DOWN\0");
+        // 1 starts words both ways, 2, 3 and 5 only down, and 4, 6 and 7 only across
+        assert_eq!(puz[0x2E], 8);
+
+        let read = Puzzle::from_puz(&puz).unwrap();
+        assert!(read.checksums_match);
+        let read = read.puzzle;
+        assert_eq!(read.cells(), puzzle.cells());
+        assert_eq!(read.name(), "Mini");
+        assert!(read.is_circled(1, 1));
+        assert_eq!(read.get(1, 1), &Cell::Empty);
+
+        // A damaged checksum is only reported, but a file that isn't a .puz at all can't be read
+        let mut damaged = puz.clone();
+        damaged[0] ^= 0xFF;
+        let read = Puzzle::from_puz(&damaged).unwrap();
+        assert!(!read.checksums_match);
+        assert_eq!(read.puzzle.cells(), puzzle.cells());
+        let mut junk = b"junk".to_vec();
+        junk.extend(&puz);
+        assert_eq!(
+            Puzzle::from_puz(&junk).unwrap().puzzle.cells(),
+            puzzle.cells()
+        );
+        assert_eq!(
+            Puzzle::from_puz(&puz[..0x40]),
+            Err(PuzzleError::InvalidPuz("the file ends early".to_string()))
+        );
+        assert!(matches!(
+            Puzzle::from_puz(b"CAT"),
+            Err(PuzzleError::InvalidPuz(_))
+        ));
+    }
+}
@@ -1,18 +1,24 @@
 use dictionary::DICTIONARY;
-use rand::Rng;
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
 use std::{
     cmp::max,
-    collections::HashMap,
-    fmt::Debug,
-    fs::File,
-    io::{Read, Write},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fmt::{self, Debug},
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Instant,
 };
 use thiserror::Error;
 
 use crate::{
-    dictionary::{self, SparseWord},
-    grid::{Cell, Grid, GridError},
-    PERCENT_BLACK, PUZZLE_DIR,
+    dictionary::{self, Dictionary, DictionaryError, SparseWord},
+    grid::{canonical_letter, Axis, Cell, Grid, GridError, Symmetry},
+    import::format_list,
+    template::Template,
+    MIN_WORD_LEN, PERCENT_BLACK, PUZZLE_DIR,
 };
 
 /// The rules for American crosswords are as follows:
@@ -38,30 +44,469 @@ use crate::{
 /// 9. (Modern rule) The vocabulary in a crossword must be lively and have very little obscurity.
 #[derive(Error, Debug, PartialEq)]
 pub enum PuzzleError {
-    #[error("The black squares are not placed symmetrically")]
-    NotSymmetric,
+    #[error("{}", not_symmetric_message(.0))]
+    NotSymmetric(Vec<(usize, usize)>),
     #[error("More than {0} percent of the puzzle squares are black")]
     TooManyBlackSquares(usize),
-    #[error("The word \"{0}\" is shorter than 3 letters")]
-    WordTooShort(String),
-    #[error("The word \"{0}\" is repeated")]
-    RepeatWord(String),
+    #[error("The letter at {0:?} is not part of both an across and a down word")]
+    UnkeyedLetter((usize, usize)),
+    #[error("The white square at {0:?} is boxed in by black squares and isn't part of any word")]
+    IsolatedCell((usize, usize)),
+    #[error("The slot symmetric to the theme entry can't hold a word of {0} letters")]
+    NoSymmetricSlot(usize),
+    #[error("The white squares are split into separate areas")]
+    NotInterlocked,
+    #[error("Unable to generate a valid base in {0} attempts")]
+    NoValidBase(usize),
+    #[error("Unable to place exactly {0} black squares in {1} attempts")]
+    NoBlackPlacement(usize, usize),
+    #[error("{}", short_words_message(.0, *.1))]
+    ShortWords(Vec<ShortWord>, usize),
+    #[error("{}", repeated_words_message(.0))]
+    RepeatedWords(Vec<RepeatedWord>),
     #[error("\"{0}\" are not in the dictionary")]
     MadeUpWord(String),
     #[error("Unable create the file \'{0}\'")]
     FileCreationError(String),
     #[error("Unable open the file \'{0}\'")]
     FileOpenError(String),
-    #[error("Unable to parse this puzzle due to: \"{0}\"")]
-    ParseError(GridError),
+    #[error("Unable to write to {0}: {1}")]
+    WriteError(String, String),
+    #[error("{0}: {1}")]
+    InFile(String, Box<PuzzleError>),
+    #[error(transparent)]
+    Grid(#[from] GridError),
+    #[error(transparent)]
+    Dictionary(#[from] DictionaryError),
+    #[error("Resizing would remove {0} letter(s) or black square(s)")]
+    WouldLoseCells(usize),
+    #[error("Unable to parse this puzzle as JSON: {0}")]
+    InvalidJson(String),
+    #[error("Expected across or down, got {0}")]
+    InvalidDirection(String),
+    #[error("Unknown rule \"{0}\", expected one of {}", Rule::ALL.map(|rule| format!("{} ({})", rule.number(), rule)).join(", "))]
+    UnknownRule(String),
+    #[error("Unknown puzzle format \"{0}\", expected one of {}", format_list())]
+    UnknownFormat(String),
+    #[error(
+        "{0} has no extension to tell its format by, so give one with --format: {}",
+        format_list()
+    )]
+    NoExtension(String),
+    #[error("Unable to read this as an Across Lite .puz file: {0}")]
+    InvalidPuz(String),
+    #[error("Turning or flipping this grid would move its diagonal pattern onto the other diagonal, which isn't a supported symmetry")]
+    OffDiagonal,
+    #[error("A .puz file can't hold a grid {0} cells across, the most is 255")]
+    TooLargeForPuz(usize),
+    #[error("There is no {0} word at index {1}")]
+    NoWord(Direction, usize),
+    #[error("No dictionary word fits the {0} slot at index {1}")]
+    NoCandidates(Direction, usize),
+    #[error("\"{0}\" doesn't fit in a slot of {1} letters")]
+    WrongLength(String, usize),
+    #[error("{0:?} and {1:?} can't be swapped symmetrically, since they have different numbers of partners or are partners of each other")]
+    UnswappableCells((usize, usize), (usize, usize)),
+    #[error("The letter {2} conflicts with the {1} already at {0:?}")]
+    ConflictingLetter((usize, usize), char, char),
+    #[error("The cell at {0:?} is locked")]
+    LockedCell((usize, usize)),
 }
 
+/// A word that's too short, along with the coordinates of its first cell and its direction
+pub type ShortWord = (String, (usize, usize), Direction);
+
+/// A word that fills more than one slot, with the clue number, direction and first cell of each of those slots
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepeatedWord {
+    pub word: String,
+    pub slots: Vec<(usize, Direction, (usize, usize))>,
+}
+
+impl fmt::Display for RepeatedWord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let slots: Vec<String> = self
+            .slots
+            .iter()
+            .map(|(number, direction, coords)| format!("{} {} {:?}", number, direction, coords))
+            .collect();
+        write!(f, "\"{}\" at {}", self.word, slots.join(" and "))
+    }
+}
+
+fn repeated_words_message(words: &[RepeatedWord]) -> String {
+    let words: Vec<String> = words.iter().map(ToString::to_string).collect();
+    format!("These words are repeated: {}", words.join("; "))
+}
+
+fn not_symmetric_message(cells: &[(usize, usize)]) -> String {
+    let cells: Vec<String> = cells.iter().map(|coords| format!("{:?}", coords)).collect();
+    if cells.is_empty() {
+        return "The black squares are not placed symmetrically".to_string();
+    }
+    format!(
+        "The black squares are not placed symmetrically at {}",
+        cells.join(", ")
+    )
+}
+
+fn short_words_message(words: &[ShortWord], min_word_len: usize) -> String {
+    let words: Vec<String> = words
+        .iter()
+        .map(|(word, coords, direction)| format!("\"{}\" ({} at {:?})", word, direction, coords))
+        .collect();
+    format!(
+        "These words are shorter than {} letters: {}",
+        min_word_len,
+        words.join(", ")
+    )
+}
+
+/// A run of white cells in a row or column, along with where it is in the grid
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word<'a> {
+    /// The coordinates of the first cell
+    pub start: (usize, usize),
+    pub direction: Direction,
+    pub len: usize,
+    pub cells: &'a [Cell],
+}
+
+impl Word<'_> {
+    /// The word's letters, with `_` for each empty cell
+    pub fn as_string(&self) -> String {
+        Cell::as_string(self.cells)
+    }
+
+    /// Whether every cell in the word has a letter
+    pub fn is_complete(&self) -> bool {
+        !self.cells.contains(&Cell::Empty)
+    }
+
+    /// The coordinates of each cell in the word, in order
+    pub fn coords(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let (x, y) = self.start;
+        (0..self.len).map(move |i| match self.direction {
+            Direction::Across => (x + i, y),
+            Direction::Down => (x, y + i),
+        })
+    }
+
+    /// How clean the word's fill is, from 0 to 1: the average `letter_commonality` of its letters, so words of
+    /// different lengths compare fairly and a single rare letter drags a short word down further than a long one.
+    /// Empty cells aren't counted, and a word without letters scores 1.
+    pub fn score(&self) -> f64 {
+        weighted_commonality(self.cells.iter().map(|cell| (cell, 1.0)))
+    }
+}
+
+/// How often each letter from A to Z turns up in English text, as a percentage
+const LETTER_FREQUENCIES: [f64; 26] = [
+    8.2, 1.5, 2.8, 4.3, 12.7, 2.2, 2.0, 6.1, 7.0, 0.15, 0.77, 4.0, 2.4, 6.7, 7.5, 1.9, 0.095, 6.0,
+    6.3, 9.1, 2.8, 0.98, 2.4, 0.15, 2.0, 0.074,
+];
+
+/// How common a letter is in English relative to E, the most common, so E scores 1 and Z close to 0. Anything that
+/// isn't a letter scores 0.
+pub fn letter_commonality(letter: char) -> f64 {
+    let letter = letter.to_ascii_uppercase();
+    if !letter.is_ascii_uppercase() {
+        return 0.0;
+    }
+    LETTER_FREQUENCIES[(letter as u8 - b'A') as usize] / LETTER_FREQUENCIES[(b'E' - b'A') as usize]
+}
+
+/// The weighted average commonality of the letters in cells, with a rebus scoring the average of its letters. Cells
+/// without letters are skipped, and 1 is returned if there are none.
+fn weighted_commonality<'a>(cells: impl Iterator<Item = (&'a Cell, f64)>) -> f64 {
+    let (mut total, mut weights) = (0.0, 0.0);
+    for (cell, weight) in cells {
+        let letters = match cell {
+            Cell::Letter(letter) => vec![*letter],
+            Cell::Rebus(letters) => letters.chars().collect(),
+            _ => continue,
+        };
+        let commonality =
+            letters.iter().copied().map(letter_commonality).sum::<f64>() / letters.len() as f64;
+        total += commonality * weight;
+        weights += weight;
+    }
+    if weights == 0.0 {
+        1.0
+    } else {
+        total / weights
+    }
+}
+
+/// How clue numbers changed between two versions of a grid, matching slots by their starting cell and direction
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Renumbering {
+    /// Slots whose number changed, as (direction, old number, new number)
+    pub moved: Vec<(Direction, usize, usize)>,
+    /// Slots in the old grid that no longer start in the same cell, as (direction, old number)
+    pub removed: Vec<(Direction, usize)>,
+}
+
+impl Renumbering {
+    pub fn is_empty(&self) -> bool {
+        self.moved.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// How a search for a fill of the whole grid ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolveOutcome {
+    /// Every slot holds a dictionary word
+    Complete,
+    /// The time ran out first. The grid holds the fullest fill found, with this many cells still empty.
+    Partial(usize),
+    /// Every option was tried and no fill of dictionary words fits the letters already in the grid
+    Unsatisfiable,
+}
+
+/// Summary metrics describing how a puzzle is shaping up
+#[derive(Debug, Clone, PartialEq)]
+pub struct PuzzleStats {
+    pub total_words: usize,
+    pub words_by_length: BTreeMap<usize, usize>,
+    pub average_word_length: f64,
+    pub black_percent: f64,
+    /// Black squares that don't change the number of words in the puzzle, and so only exist to make it easier to fill
+    pub cheater_squares: usize,
+    pub empty_cells: usize,
+}
+
+impl fmt::Display for PuzzleStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Words: {}", self.total_words)?;
+        for (len, count) in self.words_by_length.iter() {
+            writeln!(f, "  {:2} letters: {}", len, count)?;
+        }
+        writeln!(f, "Average word length: {:.2}", self.average_word_length)?;
+        writeln!(f, "Black squares: {:.1}%", self.black_percent)?;
+        writeln!(f, "Cheater squares: {}", self.cheater_squares)?;
+        write!(f, "Empty cells: {}", self.empty_cells)
+    }
+}
+
+impl fmt::Display for Puzzle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} ({}x{}, {} symmetry)",
+            self.name, self.size, self.size, self.symmetry
+        )?;
+        for y in 0..self.size {
+            for x in 0..self.size {
+                write!(f, "{} ", self.display_token(x, y))?;
+            }
+            writeln!(f)?;
+        }
+        match self.empty_cells() {
+            0 => write!(f, "Complete"),
+            empty => write!(f, "Incomplete: {} empty cell(s)", empty),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Across,
+    Down,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Across => write!(f, "across"),
+            Direction::Down => write!(f, "down"),
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = PuzzleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "across" => Ok(Direction::Across),
+            "down" => Ok(Direction::Down),
+            x => Err(PuzzleError::InvalidDirection(x.to_string())),
+        }
+    }
+}
+
+/// Where the existing grid ends up when a puzzle is resized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Anchor {
+    /// Rows and columns are added or removed at the bottom and right
+    #[default]
+    TopLeft,
+    /// Rows and columns are added or removed evenly on every side, with any odd one at the bottom and right
+    Center,
+}
+
+/// The rules from the list on `PuzzleError` that can be checked on their own, numbered as they are there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    Symmetry,
+    BlackCount,
+    /// Covers both unkeyed letters and white squares that aren't part of any word
+    Unkeyed,
+    MinLength,
+    Interlock,
+    Repeats,
+    Dictionary,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 7] = [
+        Rule::Symmetry,
+        Rule::BlackCount,
+        Rule::Unkeyed,
+        Rule::MinLength,
+        Rule::Interlock,
+        Rule::Repeats,
+        Rule::Dictionary,
+    ];
+
+    /// The rule's number in the list on `PuzzleError`
+    pub fn number(self) -> usize {
+        match self {
+            Rule::Symmetry => 1,
+            Rule::BlackCount => 2,
+            Rule::Unkeyed => 3,
+            Rule::MinLength => 4,
+            Rule::Interlock => 5,
+            Rule::Repeats => 7,
+            Rule::Dictionary => 8,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Rule::Symmetry => "symmetry",
+            Rule::BlackCount => "black-count",
+            Rule::Unkeyed => "unkeyed",
+            Rule::MinLength => "min-length",
+            Rule::Interlock => "interlock",
+            Rule::Repeats => "repeats",
+            Rule::Dictionary => "dictionary",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A rule can be given by its number or its name
+impl FromStr for Rule {
+    type Err = PuzzleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        Rule::ALL
+            .into_iter()
+            .find(|rule| rule.number().to_string() == s || rule.to_string() == s)
+            .ok_or(PuzzleError::UnknownRule(s))
+    }
+}
+
+/// The limits a puzzle is validated and generated against, where they can vary between puzzle styles
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rules {
+    pub max_black_percent: usize,
+    /// The fewest letters an across or down word may have
+    pub min_word_len: usize,
+    /// How many black squares may break the grid's symmetry, for themed grids with a few cheater squares
+    pub symmetry_tolerance: usize,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            max_black_percent: PERCENT_BLACK,
+            min_word_len: MIN_WORD_LEN,
+            symmetry_tolerance: 0,
+        }
+    }
+}
+
+/// The puzzle name that stands for stdin and stdout
+const STDIO_NAME: &str = "-";
+
+/// How many grids `random_valid_base` generates before giving up
+const MAX_BASE_ATTEMPTS: usize = 100;
+
+/// The most edits that can be undone
+const MAX_HISTORY: usize = 50;
+
+/// How many dictionary words `autofill_slot` weighs against each other
+const AUTOFILL_CANDIDATES: usize = 50;
+
+/// How many words fitting a crossing slot are enough to call it wide open when scoring an autofill candidate
+const CROSSING_OPTIONS: usize = 50;
+
+/// How many words fitting a slot are enough for the solver to stop counting when choosing which slot to fill next
+const SOLVE_LOOKAHEAD: usize = 50;
+
+/// Earlier and undone versions of a puzzle's grid. History is only kept in memory, not saved with the puzzle.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct History {
+    undo: VecDeque<Snapshot>,
+    redo: Vec<Snapshot>,
+}
+
+/// A version of the grid in the history. Edits that move the whole grid also move its marks, so they keep those too;
+/// undoing any other edit leaves the marks as they are.
+#[derive(Debug, Clone, PartialEq)]
+struct Snapshot {
+    cells: Grid,
+    marks: Option<Marks>,
+}
+
+/// The circles, locks and bars laid over the grid, along with its symmetry, which a quarter turn can change
 #[derive(Debug, Clone, PartialEq)]
+struct Marks {
+    symmetry: Symmetry,
+    circled: BTreeSet<(usize, usize)>,
+    locked: BTreeSet<(usize, usize)>,
+    bars: BTreeSet<((usize, usize), Direction)>,
+}
+
+/// The name, size, symmetry, cells, circles, locks and bars are part of a puzzle's JSON; the rest is rebuilt or
+/// supplied on load
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Puzzle {
     name: String,
     size: usize,
+    symmetry: Symmetry,
+    #[serde(skip)]
+    rules: Rules,
     cells: Grid,
+    /// Circled cells, which mark things like the letters of a hidden theme word without changing how the grid is
+    /// validated
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    circled: BTreeSet<(usize, usize)>,
+    /// Cells whose contents are given, such as the letters of a theme entry, which filling and clearing leave alone
+    /// even while they're empty
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    locked: BTreeSet<(usize, usize)>,
+    /// Bars that end words the way black squares do, for barred puzzles. A bar at (x, y) across sits on the cell's
+    /// right edge and ends an across word there; one down sits on its bottom edge and ends a down word.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    bars: BTreeSet<((usize, usize), Direction)>,
+    #[serde(skip)]
     transpose: Grid,
+    #[serde(skip)]
+    history: History,
+    /// Show and save the grid with `#` and `.` instead of the usual glyphs
+    #[serde(skip)]
+    ascii: bool,
+    /// Save the grid with a character per cell and no spaces between them
+    #[serde(skip)]
+    compact: bool,
 }
 
 impl Puzzle {
@@ -71,358 +516,3739 @@ impl Puzzle {
         Puzzle {
             name,
             size,
+            symmetry: Symmetry::default(),
+            rules: Rules::default(),
             cells,
+            circled: BTreeSet::new(),
+            locked: BTreeSet::new(),
+            bars: BTreeSet::new(),
             transpose,
+            history: History::default(),
+            ascii: false,
+            compact: false,
         }
     }
 
-    pub fn save_to_file(&self) -> Result<(), PuzzleError> {
-        let path = format!("{}/{}.txt", PUZZLE_DIR, self.name);
-        let mut f =
-            File::create(path.clone()).map_err(|_e| PuzzleError::FileCreationError(path))?;
-        let puzzle = format!("{}", self.cells());
-        f.write_all(puzzle.as_bytes()).unwrap();
-        Ok(())
+    /// A puzzle from a grid written a character per cell, as described in `Grid::from_template`, e.g. "#..\n...\n..#"
+    pub fn from_template(name: String, template: &str) -> Result<Self, GridError> {
+        Ok(Puzzle::from_grid(name, Grid::from_template(template)?))
     }
 
-    pub fn open_from_file(name: String) -> Result<Self, PuzzleError> {
-        let path = format!("{}/{}.txt", PUZZLE_DIR, name);
-        let mut f = File::open(path.clone()).map_err(|_e| PuzzleError::FileOpenError(path))?;
-        let mut buffer = Vec::new();
-        f.read_to_end(&mut buffer).unwrap();
+    /// A blank puzzle with one of the standard sizes and black square patterns. The pattern is checked against the
+    /// default rules, so a template that's been edited into an invalid base is an error rather than a bad grid.
+    pub fn from_preset(name: String, template: Template) -> Result<Self, PuzzleError> {
+        let mut puzzle = Puzzle::new(name, template.size()).with_symmetry(Symmetry::Rotational);
+        for coords in template.black_squares() {
+            puzzle.put_symmetric(coords, Cell::Black);
+        }
+        puzzle.validate_base()?;
+        Ok(puzzle)
+    }
 
-        let cells = Grid::from_bytes(&buffer).map_err(|e| PuzzleError::ParseError(e))?;
-        Ok(Puzzle::from_grid(name, cells))
+    pub fn with_symmetry(mut self, symmetry: Symmetry) -> Self {
+        self.symmetry = symmetry;
+        self
     }
 
-    pub fn pretty_print(&self) {
-        self.cells.pretty_print();
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
     }
 
-    fn from_grid(name: String, cells: Grid) -> Self {
-        let size = cells.len();
-        let transpose = cells.transpose();
-        Puzzle {
-            name,
-            size,
-            cells,
-            transpose,
-        }
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self
     }
 
-    pub fn cells(&self) -> &Grid {
-        &self.cells
+    pub fn with_ascii(mut self, ascii: bool) -> Self {
+        self.ascii = ascii;
+        self
     }
 
-    /// Get the down word that starts at index, where cells are numbered left to right, 0 to (size*size - 1), starting in the top left
-    pub fn get_down_word(&self, index: usize) -> Option<SparseWord> {
-        let row_num = index / self.size;
-        let col_num = index % self.size;
-        let col = self.transpose.get_row(col_num);
-        Puzzle::take_word(col, row_num)
+    pub fn with_compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
     }
 
-    /// Get the across word that starts at index, where cells are numbered left to right, 0 to (size*size - 1), starting in the top left
-    pub fn get_across_word(&self, index: usize) -> Option<SparseWord> {
-        let row_num = index / self.size;
-        let col_num = index % self.size;
-        let row = self.cells.get_row(row_num);
-        Puzzle::take_word(row, col_num)
+    /// Puzzle files start with a header of `key: value` lines, followed by the grid itself. A puzzle named `-` is
+    /// written to stdout instead, so it can be piped to another command.
+    pub fn save_to_file(&self) -> Result<(), PuzzleError> {
+        if self.is_stdio() {
+            return self.save_to_path(STDIO_NAME);
+        }
+        self.save_to_path(&format!("{}/{}.txt", PUZZLE_DIR, self.name))
     }
 
-    fn take_word(cells: &Vec<Cell>, start: usize) -> Option<SparseWord> {
-        let mut idx = start;
-        let mut chars: Vec<Option<char>> = Vec::new();
-        loop {
-            match cells.get(idx) {
-                Some(cell) => match cell {
-                    Cell::Black => break,
-                    Cell::Empty => chars.push(None),
-                    Cell::Letter(l) => chars.push(Some(*l)),
-                },
-                None => break,
+    /// Save the puzzle somewhere other than the puzzle directory, with `-` meaning stdout
+    pub fn save_to_path(&self, path: &str) -> Result<(), PuzzleError> {
+        let puzzle = self.save_text();
+        if path == STDIO_NAME {
+            return io::stdout()
+                .write_all(puzzle.as_bytes())
+                .map_err(|e| PuzzleError::WriteError("stdout".to_string(), e.to_string()));
+        }
+        let mut f =
+            File::create(path).map_err(|_e| PuzzleError::FileCreationError(path.to_string()))?;
+        f.write_all(puzzle.as_bytes())
+            .map_err(|_e| PuzzleError::FileCreationError(path.to_string()))
+    }
+
+    /// The puzzle as it's written to a file: the header, then the grid
+    pub(crate) fn save_text(&self) -> String {
+        let mut header = format!("symmetry: {}\n", self.symmetry);
+        for (key, cells) in [("circled", &self.circled), ("locked", &self.locked)] {
+            if !cells.is_empty() {
+                let cells: Vec<String> =
+                    cells.iter().map(|(x, y)| format!("{},{}", x, y)).collect();
+                header.push_str(&format!("{}: {}\n", key, cells.join(" ")));
             }
-            idx += 1;
         }
-        if chars.len() > 0 {
-            Some(SparseWord::new(chars))
+        for (key, direction) in [
+            ("bars-across", Direction::Across),
+            ("bars-down", Direction::Down),
+        ] {
+            let bars: Vec<String> = self
+                .bars
+                .iter()
+                .filter(|&&(_, bar)| bar == direction)
+                .map(|((x, y), _)| format!("{},{}", x, y))
+                .collect();
+            if !bars.is_empty() {
+                header.push_str(&format!("{}: {}\n", key, bars.join(" ")));
+            }
+        }
+        format!("{}{}", header, self.grid_text())
+    }
+
+    /// Whether a puzzle is already saved under name. The stdio name is never saved.
+    pub fn exists(name: &str) -> bool {
+        name != STDIO_NAME && Path::new(&format!("{}/{}.txt", PUZZLE_DIR, name)).exists()
+    }
+
+    /// Open a saved puzzle by name, or read one from stdin if the name is `-`
+    pub fn open_from_file(name: String) -> Result<Self, PuzzleError> {
+        let mut buffer = Vec::new();
+        if name == STDIO_NAME {
+            io::stdin()
+                .read_to_end(&mut buffer)
+                .map_err(|_e| PuzzleError::FileOpenError("stdin".to_string()))?;
         } else {
-            None
+            let path = format!("{}/{}.txt", PUZZLE_DIR, name);
+            let mut f = File::open(&path).map_err(|_e| PuzzleError::FileOpenError(path.clone()))?;
+            f.read_to_end(&mut buffer)
+                .map_err(|_e| PuzzleError::FileOpenError(path))?;
         }
+        Puzzle::from_bytes(name, &buffer)
     }
 
-    /// iterate through each row, separating by black cells
-    fn words_across_iter(&self) -> impl Iterator<Item = &[Cell]> {
-        self.cells.rows_iter().flat_map(|row| {
-            row.split(|cell| matches!(cell, Cell::Black))
-                .filter(|x| !x.is_empty())
-        })
+    /// Open every puzzle file in dir, in order of file name, each named after its file. A file that can't be read or
+    /// parsed gives an error naming it, without stopping the rest from loading. A directory that can't be read at all
+    /// gives a single error.
+    pub fn load_all(dir: &Path) -> Vec<Result<Puzzle, PuzzleError>> {
+        let entries = match dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => return vec![Err(PuzzleError::FileOpenError(dir.display().to_string()))],
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "txt"))
+            .collect();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| {
+                let name = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                fs::read(&path)
+                    .map_err(|_e| PuzzleError::FileOpenError(path.display().to_string()))
+                    .and_then(|buffer| Puzzle::from_bytes(name, &buffer))
+                    .map_err(|e| PuzzleError::InFile(path.display().to_string(), Box::new(e)))
+            })
+            .collect()
     }
 
-    /// iterate through each col, separating by black cells
-    fn words_down_iter(&self) -> impl Iterator<Item = &[Cell]> {
-        self.transpose.rows_iter().flat_map(|row| {
-            row.split(|cell| matches!(cell, Cell::Black))
-                .filter(|x| !x.is_empty())
-        })
+    /// Parse a puzzle file's contents: an optional header followed by the grid
+    pub(crate) fn from_bytes(name: String, buffer: &[u8]) -> Result<Self, PuzzleError> {
+        let (header, body) = Puzzle::split_header(buffer);
+        // Count lines from the top of the file rather than the start of the grid
+        let header_lines = buffer[..buffer.len() - body.len()]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count();
+        let cells = Grid::from_bytes(body).map_err(|e| match e {
+            GridError::InvalidPuzzleFormat { line, token } => GridError::InvalidPuzzleFormat {
+                line: line + header_lines,
+                token,
+            },
+            e => e,
+        })?;
+        let mut puzzle = Puzzle::from_grid(name, cells);
+        if let Some(symmetry) = header.get("symmetry") {
+            puzzle.symmetry = symmetry.parse()?;
+        }
+        if let Some(circled) = header.get("circled") {
+            for coords in circled.split_whitespace() {
+                let (x, y) = Puzzle::parse_coords(coords)
+                    .ok_or_else(|| GridError::InvalidCoords(coords.to_string()))?;
+                puzzle.circle_cell(x, y)?;
+            }
+        }
+        if let Some(locked) = header.get("locked") {
+            for coords in locked.split_whitespace() {
+                let (x, y) = Puzzle::parse_coords(coords)
+                    .ok_or_else(|| GridError::InvalidCoords(coords.to_string()))?;
+                puzzle.lock_cell(x, y)?;
+            }
+        }
+        for (key, direction) in [
+            ("bars-across", Direction::Across),
+            ("bars-down", Direction::Down),
+        ] {
+            let Some(bars) = header.get(key) else {
+                continue;
+            };
+            for coords in bars.split_whitespace() {
+                let (x, y) = Puzzle::parse_coords(coords)
+                    .ok_or_else(|| GridError::InvalidCoords(coords.to_string()))?;
+                puzzle.add_bar(x, y, direction)?;
+            }
+        }
+        Ok(puzzle)
     }
 
-    fn all_words_iter(&self) -> impl Iterator<Item = &[Cell]> {
-        self.words_across_iter().chain(self.words_down_iter())
+    /// Parse coordinates written as `x,y`
+    fn parse_coords(coords: &str) -> Option<(usize, usize)> {
+        let (x, y) = coords.split_once(',')?;
+        Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
     }
 
-    /// Validate that the puzzle "base" (the grid, with black cells but without letters) is valid according to the spec:
-    /// 1. The grid is square
-    /// 2. The positions of the blacks squares are rotationally symmetric
-    /// 3. That the black squares don't represent too high a proportion of the total grid.
-    /// 4. All words are 3 characters or longer
-    pub fn validate_base(&self) -> Result<(), PuzzleError> {
-        self.cells.is_square()?;
-        self.cells.is_symmetric()?;
-        self.cells.acceptable_black_square_count()?;
-        self.no_too_short_words()?;
+    /// Circle the cell at (x, y). Circles are only drawn on white cells, but one on a black square is kept in case
+    /// the square is made white again.
+    pub fn circle_cell(&mut self, x: usize, y: usize) -> Result<(), GridError> {
+        self.cells.try_get(x, y)?;
+        self.circled.insert((x, y));
         Ok(())
     }
 
-    /// Validate that the words in the puzzle meet the spec:
-    /// 1. Not repeat workds
-    /// 2. All words are 3 characters or longer
-    /// 3. All words appear in the dictionary we're using
-    pub fn validate_words(&self) -> Result<(), PuzzleError> {
-        self.no_repeat_words()?;
-        self.no_too_short_words()?;
-        self.valid_words()?;
+    pub fn uncircle_cell(&mut self, x: usize, y: usize) -> Result<(), GridError> {
+        self.cells.try_get(x, y)?;
+        self.circled.remove(&(x, y));
         Ok(())
     }
 
-    fn no_repeat_words(&self) -> Result<(), PuzzleError> {
-        let mut words = HashMap::new();
-        for word in self.all_words_iter().map(|x| Cell::as_string(x)) {
-            if word.len() > 0 {
-                match words.insert(word.clone(), 1) {
-                    Some(_) => return Err(PuzzleError::RepeatWord(word)),
-                    None => (),
-                }
-            }
-        }
+    pub fn is_circled(&self, x: usize, y: usize) -> bool {
+        self.circled.contains(&(x, y))
+    }
+
+    /// Lock the cell at (x, y), so that filling words or letters and clearing the grid won't change it. Placing black
+    /// squares and editing cells directly still can.
+    pub fn lock_cell(&mut self, x: usize, y: usize) -> Result<(), GridError> {
+        self.cells.try_get(x, y)?;
+        self.locked.insert((x, y));
         Ok(())
     }
 
-    fn no_too_short_words(&self) -> Result<(), PuzzleError> {
-        for word in self.all_words_iter().map(|x| Cell::as_string(x)) {
-            if word.len() < 3 {
-                return Err(PuzzleError::WordTooShort(word));
-            }
-        }
+    pub fn unlock_cell(&mut self, x: usize, y: usize) -> Result<(), GridError> {
+        self.cells.try_get(x, y)?;
+        self.locked.remove(&(x, y));
         Ok(())
     }
 
-    fn valid_words(&self) -> Result<(), PuzzleError> {
-        let mut invalid_words = Vec::new();
-        for word in self.all_words_iter().map(|x| Cell::as_string(x)) {
-            if !DICTIONARY.is_valid(&word.to_ascii_lowercase()) {
-                invalid_words.push(word);
-            }
+    pub fn is_locked(&self, x: usize, y: usize) -> bool {
+        self.locked.contains(&(x, y))
+    }
+
+    /// Put a bar after the cell at (x, y) that ends words in direction there, as a black square would: on the cell's
+    /// right edge for across words and its bottom edge for down words. There has to be a cell on the other side.
+    pub fn add_bar(&mut self, x: usize, y: usize, direction: Direction) -> Result<(), GridError> {
+        let (next_x, next_y) = Puzzle::bar_neighbor((x, y), direction);
+        self.cells.try_get(x, y)?;
+        self.cells.try_get(next_x, next_y)?;
+        self.bars.insert(((x, y), direction));
+        Ok(())
+    }
+
+    pub fn remove_bar(
+        &mut self,
+        x: usize,
+        y: usize,
+        direction: Direction,
+    ) -> Result<(), GridError> {
+        self.cells.try_get(x, y)?;
+        self.bars.remove(&((x, y), direction));
+        Ok(())
+    }
+
+    pub fn has_bar(&self, x: usize, y: usize, direction: Direction) -> bool {
+        self.bars.contains(&((x, y), direction))
+    }
+
+    /// Whether any words are ended by bars rather than only by black squares and the edge of the grid
+    pub fn is_barred(&self) -> bool {
+        !self.bars.is_empty()
+    }
+
+    /// The cell on the other side of a bar
+    fn bar_neighbor((x, y): (usize, usize), direction: Direction) -> (usize, usize) {
+        match direction {
+            Direction::Across => (x + 1, y),
+            Direction::Down => (x, y + 1),
         }
-        if invalid_words.is_empty() {
-            Ok(())
-        } else {
-            return Err(PuzzleError::MadeUpWord(invalid_words.join(", ")));
+    }
+
+    /// How the cell at (x, y) is shown on screen, with circled cells marked
+    fn display_token(&self, x: usize, y: usize) -> String {
+        let cell = self.get(x, y);
+        if !self.is_circled(x, y) {
+            return self.token(cell);
+        }
+        match (self.ascii, cell) {
+            (true, Cell::Black) => cell.ascii_token(),
+            (true, cell) => format!("({})", cell.ascii_token()),
+            (false, cell) => cell.circled_token(),
         }
     }
 
-    fn valid_black_placement(&self, (x, y): (usize, usize)) -> bool {
-        // Capture the slices of the puzzle right, left, above and below the suggested black-placement and validate that it would leave
-        // enough space in each direction
-        let mut row: Vec<Cell> = self.cells.get_row(y).clone();
-        let mut col: Vec<Cell> = self.transpose.get_row(x).clone();
-        let (left, mut right) = row.split_at_mut(x);
-        let (up, mut down) = col.split_at_mut(y);
+    /// How a cell is written, in the glyphs this puzzle is shown with
+    pub fn token(&self, cell: &Cell) -> String {
+        if self.ascii {
+            cell.ascii_token()
+        } else {
+            cell.token()
+        }
+    }
 
-        // Truncate right and down since `split_at_mut` is inclusive.
-        if right.len() > 0 {
-            right = &mut right[1..];
+    /// The grid as it's written in a puzzle file, a row per line
+    pub fn grid_text(&self) -> String {
+        match (self.compact, self.ascii) {
+            (true, ascii) => self.cells.render_compact(ascii),
+            (false, true) => self.cells.render_ascii(),
+            (false, false) => self.cells.to_string(),
         }
-        if down.len() > 0 {
-            down = &mut down[1..];
+    }
+
+    /// Move the circles, locks and bars along with the cells when the grid is transformed, dropping any that end up
+    /// off the grid. A bar moves with the pair of cells it separates, so it turns with them under a rotation.
+    fn move_marks(&mut self, to: impl Fn((usize, usize)) -> Option<(usize, usize)>) {
+        for marks in [&mut self.circled, &mut self.locked] {
+            *marks = marks.iter().filter_map(|&coords| to(coords)).collect();
         }
+        self.bars = self
+            .bars
+            .iter()
+            .filter_map(|&(coords, direction)| {
+                let (a, b) = (to(coords)?, to(Puzzle::bar_neighbor(coords, direction))?);
+                let (first, second) = (a.min(b), a.max(b));
+                if first.1 == second.1 && first.0 + 1 == second.0 {
+                    Some((first, Direction::Across))
+                } else if first.0 == second.0 && first.1 + 1 == second.1 {
+                    Some((first, Direction::Down))
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
 
-        // Reverse left and up so we just have to look left to right each slice.
-        left.reverse();
-        up.reverse();
+    /// Whether the puzzle is read from stdin and written to stdout rather than kept in a file
+    pub fn is_stdio(&self) -> bool {
+        self.name == STDIO_NAME
+    }
 
-        Grid::ok_dist_to_black_or_edge(left)
-            && Grid::ok_dist_to_black_or_edge(right)
-            && Grid::ok_dist_to_black_or_edge(up)
-            && Grid::ok_dist_to_black_or_edge(down)
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
     }
 
-    /// Generate a random configuration of black squares to form a symmetric puzzle
-    pub fn random_black(&mut self) {
-        // It's not possible to have valid black squares for puzzles 4 and smaller, since all words must be at least 3 letters
-        // and the puzzle must be symmetric
-        if self.size < 5 {
-            return;
+    pub fn from_json(json: &str) -> Result<Self, PuzzleError> {
+        let mut puzzle: Puzzle =
+            serde_json::from_str(json).map_err(|e| PuzzleError::InvalidJson(e.to_string()))?;
+        if puzzle.cells.is_square().is_err() {
+            return Err(PuzzleError::InvalidJson(
+                "the cells must form a square grid".to_string(),
+            ));
         }
-        let quadrant = max(2, self.size / 2);
-        let mut rng = rand::thread_rng();
-        let upper_threshold_black = (self.size * self.size * PERCENT_BLACK) / 100;
-        let mut black_set = 0;
-
-        loop {
-            for row in 0..quadrant {
-                for col in 0..quadrant {
-                    let cell = self.get(col, row);
-                    if !matches!(cell, Cell::Black) {
-                        if self.valid_black_placement((col, row)) {
-                            // A random chance of setting the cell to black
-                            let x = rng.gen_bool(1.0 / 2.0);
-                            if x {
-                                self.set_symmetric((col, row), Cell::Black);
-                                black_set += 1;
-                                if black_set >= upper_threshold_black / 4 {
-                                    return;
-                                }
-                                break;
-                            }
-                        }
+        if puzzle.size != puzzle.cells.len() {
+            return Err(PuzzleError::InvalidJson(format!(
+                "the size is {} but there are {} rows of cells",
+                puzzle.size,
+                puzzle.cells.len()
+            )));
+        }
+        // JSON written by other tools may not follow the uppercase convention
+        for row in puzzle.cells.0.iter_mut() {
+            for cell in row.iter_mut() {
+                match cell {
+                    Cell::Letter(letter) => *letter = canonical_letter(*letter),
+                    Cell::Rebus(letters) => {
+                        *letters = letters.chars().map(canonical_letter).collect()
                     }
+                    _ => (),
                 }
             }
         }
+        puzzle.restore_transpose();
+        Ok(puzzle)
     }
 
-    fn set_symmetric(&mut self, (x, y): (usize, usize), val: Cell) {
-        self.set(x, y, val.clone());
-        self.set(self.size - (y + 1), x, val.clone());
-        self.set(self.size - (x + 1), self.size - (y + 1), val.clone());
-        self.set(y, self.size - (x + 1), val);
-    }
-
-    /// Trying to generate a random, valid puzzle with this takes too long for anything larger than
-    /// a 3x3 puzzle. Instead, can I organize the words in such a way that I can pick words by length
-    /// and verify that a substring could fit with existing letters?
-    pub fn random_letters(&mut self) {
-        let mut rng = rand::thread_rng();
-        for row in 0..self.size {
-            for col in 0..self.size {
-                let cell = self.get_mut(col, row);
-                if let Cell::Empty = cell {
-                    let x: char = rng.gen_range(b'A'..b'Z' + 1) as char;
-                    self.set(col, row, Cell::Letter(x));
+    /// Separate the leading `key: value` lines of a puzzle file from the grid that follows them. Files written
+    /// before the header existed have no such lines, so the whole buffer is the grid.
+    fn split_header(buf: &[u8]) -> (HashMap<String, String>, &[u8]) {
+        let mut header = HashMap::new();
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let end = rest.iter().position(|b| *b == b'\n').unwrap_or(rest.len());
+            let line = String::from_utf8_lossy(&rest[..end]);
+            match line.split_once(':') {
+                Some((key, value)) => {
+                    header.insert(key.trim().to_string(), value.trim().to_string());
                 }
+                None => break,
             }
+            rest = &rest[(end + 1).min(rest.len())..];
         }
+        (header, rest)
     }
 
-    fn set(&mut self, x: usize, y: usize, value: Cell) {
-        self.cells.set(x, y, value.clone());
-        self.transpose.set(y, x, value);
+    /// The number of white squares that don't have a letter yet
+    pub fn empty_cells(&self) -> usize {
+        self.cells.count_cells(|cell| cell.is_empty())
     }
 
-    #[allow(dead_code)]
-    fn get(&self, x: usize, y: usize) -> &Cell {
-        self.cells.get(x, y)
+    /// Whether every white square has a letter
+    pub fn is_complete(&self) -> bool {
+        self.empty_cells() == 0
     }
 
-    fn get_mut(&mut self, x: usize, y: usize) -> &mut Cell {
-        self.cells.get_mut(x, y)
+    pub fn pretty_print(&self) {
+        for y in 0..self.size {
+            print!(" {:3}: ", (y * self.size));
+            for x in 0..self.size {
+                print!("{} ", self.display_token(x, y));
+            }
+            println!();
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::{
-        dictionary::SparseWord,
-        puzzle::{Cell, Grid, PuzzleError},
-        Puzzle,
-    };
+    pub(crate) fn from_grid(name: String, cells: Grid) -> Self {
+        let size = cells.len();
+        let transpose = cells.transpose();
+        Puzzle {
+            name,
+            size,
+            symmetry: Symmetry::default(),
+            rules: Rules::default(),
+            cells,
+            circled: BTreeSet::new(),
+            locked: BTreeSet::new(),
+            bars: BTreeSet::new(),
+            transpose,
+            history: History::default(),
+            ascii: false,
+            compact: false,
+        }
+    }
 
-    #[test]
-    fn valid_empty_grid() {
-        let empty = Puzzle::new("x".to_string(), 10);
+    pub fn cells(&self) -> &Grid {
+        &self.cells
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn symmetry(&self) -> Symmetry {
+        self.symmetry
+    }
+
+    /// Get the down word that starts at index, where cells are numbered left to right, 0 to (size*size - 1), starting in the top left
+    pub fn get_down_word(&self, index: usize) -> Option<SparseWord> {
+        let (col_num, row_num) = self.index_coords(index).ok()?;
+        let col = self.transpose.get_row(col_num);
+        let len = self.slot_coords(index, Direction::Down).len();
+        Puzzle::take_word(&col[..row_num + len], row_num)
+    }
+
+    /// Get the across word that starts at index, where cells are numbered left to right, 0 to (size*size - 1), starting in the top left
+    pub fn get_across_word(&self, index: usize) -> Option<SparseWord> {
+        let (col_num, row_num) = self.index_coords(index).ok()?;
+        let row = self.cells.get_row(row_num);
+        let len = self.slot_coords(index, Direction::Across).len();
+        Puzzle::take_word(&row[..col_num + len], col_num)
+    }
+
+    /// Suggest up to count dictionary words for the slot starting at index, leaving out words that are already
+    /// complete elsewhere in the grid since a puzzle can't repeat a word
+    pub fn suggest(
+        &self,
+        index: usize,
+        direction: Direction,
+        count: usize,
+    ) -> Result<Vec<String>, PuzzleError> {
+        DICTIONARY.check_loaded()?;
+        let word = self.slot_word(index, direction)?;
+        Ok(DICTIONARY.suggest_words_excluding(word, count, &self.used_words()))
+    }
+
+    /// Like `suggest`, but ordered by how common each word is according to the dictionary's frequency list
+    pub fn suggest_by_frequency(
+        &self,
+        index: usize,
+        direction: Direction,
+        count: usize,
+    ) -> Result<Vec<String>, PuzzleError> {
+        DICTIONARY.check_loaded()?;
+        let word = self.slot_word(index, direction)?;
+        Ok(DICTIONARY.suggest_words_by_frequency(word, count, &self.used_words()))
+    }
+
+    /// Like `suggest`, but only suggesting words that leave at least one dictionary word for every slot crossing this
+    /// one, so that taking a suggestion never paints a crossing into a corner
+    pub fn suggest_constrained(
+        &self,
+        index: usize,
+        direction: Direction,
+        count: usize,
+    ) -> Result<Vec<String>, PuzzleError> {
+        DICTIONARY.check_loaded()?;
+        let word = self.slot_word(index, direction)?;
+        let used = self.used_words();
+        let mut suggestions = Vec::new();
+        for candidate in DICTIONARY.suggest_words_excluding(word, usize::MAX, &used) {
+            if suggestions.len() >= count {
+                break;
+            }
+            if self.crossings_stay_fillable(index, direction, &candidate, &used) {
+                suggestions.push(candidate);
+            }
+        }
+        Ok(suggestions)
+    }
+
+    /// Whether putting fill in the slot starting at index would leave at least one dictionary word for every slot
+    /// crossing it, the check behind `suggest_constrained`
+    pub fn keeps_crossings_fillable(&self, index: usize, direction: Direction, fill: &str) -> bool {
+        self.crossings_stay_fillable(index, direction, fill, &self.used_words())
+    }
+
+    fn crossings_stay_fillable(
+        &self,
+        index: usize,
+        direction: Direction,
+        fill: &str,
+        used: &HashSet<String>,
+    ) -> bool {
+        let mut trial = self.scratch_copy();
+        if trial.fill(index, direction, fill).is_err() {
+            return false;
+        }
+        let mut used = used.clone();
+        used.insert(fill.to_string());
+        let fits = trial.crossing_fits(&DICTIONARY, index, direction, &used, 1);
+        fits.iter().all(|&fits| fits > 0)
+    }
+
+    /// Like `suggest`, but for words that disagree with up to max_mismatches of the letters already in the slot
+    pub fn suggest_near(
+        &self,
+        index: usize,
+        direction: Direction,
+        count: usize,
+        max_mismatches: usize,
+    ) -> Result<Vec<String>, PuzzleError> {
+        DICTIONARY.check_loaded()?;
+        let word = self.slot_word(index, direction)?;
+        let used = self.used_words();
+        let mut suggestions =
+            DICTIONARY.suggest_near(word, count.saturating_add(used.len()), max_mismatches);
+        suggestions.retain(|word| !used.contains(word));
+        suggestions.truncate(count);
+        Ok(suggestions)
+    }
+
+    /// Fill the slot starting at index with the dictionary word that leaves the crossing slots the most room, returning
+    /// the word chosen. Each candidate is scored first by how many crossings it leaves with no possible word, then by
+    /// how many words still fit across all of its crossings, so a slightly less common word that keeps the rest of the
+    /// grid open wins over one that paints a crossing into a corner. Aiming for a pangram, words that add letters
+    /// missing from the grid come next, ahead of how open the crossings are.
+    pub fn autofill_slot(
+        &mut self,
+        index: usize,
+        direction: Direction,
+        pangram: bool,
+    ) -> Result<String, PuzzleError> {
+        self.autofill_slot_from(&DICTIONARY, index, direction, pangram)
+    }
+
+    /// `autofill_slot` with the words of any dictionary
+    fn autofill_slot_from(
+        &mut self,
+        dictionary: &Dictionary,
+        index: usize,
+        direction: Direction,
+        pangram: bool,
+    ) -> Result<String, PuzzleError> {
+        dictionary.check_loaded()?;
+        let word = self.slot_word(index, direction)?;
+        let used = self.used_words();
+        let missing = if pangram {
+            self.missing_letters()
+        } else {
+            Vec::new()
+        };
+        // Every match comes back in the same order, so the same candidates are weighed each time
+        let mut candidates = dictionary.suggest_words_excluding(word, usize::MAX, &used);
+        candidates.truncate(AUTOFILL_CANDIDATES);
+        let mut best: Option<((usize, isize, isize), String)> = None;
+        for candidate in candidates {
+            let mut trial = self.scratch_copy();
+            if trial.fill(index, direction, &candidate).is_err() {
+                continue;
+            }
+            let mut used = used.clone();
+            used.insert(candidate.clone());
+            let (unfillable, options) = trial.crossing_score(dictionary, index, direction, &used);
+            let covered = missing
+                .iter()
+                .filter(|&&letter| candidate.chars().any(|c| canonical_letter(c) == letter))
+                .count();
+            let score = (unfillable, -(covered as isize), options);
+            if best.as_ref().is_none_or(|(best, _)| score < *best) {
+                best = Some((score, candidate));
+            }
+        }
+        let (_, word) = best.ok_or(PuzzleError::NoCandidates(direction, index))?;
+        self.fill(index, direction, &word)?;
+        Ok(word)
+    }
+
+    /// Fill every empty cell with letters spelling dictionary words, without repeating a word, searching until a fill
+    /// is found or every option has been tried. On success the fill can be undone in one step; otherwise the grid is
+    /// left as it was. The dictionary should be checked with `Dictionary::check_loaded` first, since without one no
+    /// slot has any options.
+    pub fn solve(&mut self) -> SolveOutcome {
+        self.solve_until(None)
+    }
+
+    /// Like `solve`, but giving up at deadline, checked before each step of the search. If the time runs out the grid
+    /// is left with the fullest fill found, its remaining cells empty.
+    pub fn solve_with_budget(&mut self, deadline: Instant) -> SolveOutcome {
+        self.solve_until(Some(deadline))
+    }
+
+    fn solve_until(&mut self, deadline: Option<Instant>) -> SolveOutcome {
+        let mut best = (self.empty_cells(), self.cells.clone());
+        let outcome = match self.scratch_copy().solve_from(deadline, &mut best) {
+            Some(true) => SolveOutcome::Complete,
+            Some(false) => return SolveOutcome::Unsatisfiable,
+            None => SolveOutcome::Partial(best.0),
+        };
+        if best.1 != self.cells {
+            self.checkpoint();
+            self.cells = best.1;
+            self.restore_transpose();
+        }
+        outcome
+    }
+
+    /// Backtrack through fills of the slot with the fewest options, keeping the fill with the fewest empty cells in
+    /// best. Returns whether the grid could be completed, or `None` if the deadline passed.
+    fn solve_from(&self, deadline: Option<Instant>, best: &mut (usize, Grid)) -> Option<bool> {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return None;
+        }
+        let empty = self.empty_cells();
+        if empty < best.0 {
+            *best = (empty, self.cells.clone());
+        }
+        let used = self.used_words();
+        let mut next: Option<(usize, usize, Direction)> = None;
+        for (index, direction) in self.slot_starts() {
+            let Ok(word) = self.slot_word(index, direction) else {
+                continue;
+            };
+            if word.len() < 2 {
+                continue;
+            }
+            // Grid words never hold spaces, so lowercasing is all they need to be looked up as they're stored
+            let options = match word.as_complete() {
+                Some(complete) if DICTIONARY.is_valid_canonical(&complete.to_lowercase()) => {
+                    continue
+                }
+                Some(_) => 0,
+                None => DICTIONARY
+                    .suggest_words_excluding(word, SOLVE_LOOKAHEAD, &used)
+                    .len(),
+            };
+            if options == 0 {
+                return Some(false);
+            }
+            if next.is_none_or(|(fewest, _, _)| options < fewest) {
+                next = Some((options, index, direction));
+            }
+        }
+        let Some((_, index, direction)) = next else {
+            *best = (empty, self.cells.clone());
+            return Some(true);
+        };
+        let Ok(word) = self.slot_word(index, direction) else {
+            return Some(false);
+        };
+        for candidate in DICTIONARY.suggest_words_excluding(word, usize::MAX, &used) {
+            let mut trial = self.scratch_copy();
+            if trial.fill(index, direction, &candidate).is_err() {
+                continue;
+            }
+            // Skip words that leave a crossing without options before searching any further
+            let mut used = used.clone();
+            used.insert(candidate.clone());
+            if trial
+                .crossing_fits(&DICTIONARY, index, direction, &used, 1)
+                .contains(&0)
+            {
+                continue;
+            }
+            match trial.solve_from(deadline, best) {
+                Some(false) => continue,
+                done => return done,
+            }
+        }
+        Some(false)
+    }
+
+    /// The number of slots crossing the slot starting at index that no word fits, and the (negated) number of words
+    /// that fit the rest, so that lower scores are better
+    fn crossing_score(
+        &self,
+        dictionary: &Dictionary,
+        index: usize,
+        direction: Direction,
+        used: &HashSet<String>,
+    ) -> (usize, isize) {
+        let fits = self.crossing_fits(dictionary, index, direction, used, CROSSING_OPTIONS);
+        let unfillable = fits.iter().filter(|&&fits| fits == 0).count();
+        let options: usize = fits.iter().sum();
+        (unfillable, -(options as isize))
+    }
+
+    /// How many words, up to limit, fit each slot crossing the slot starting at index. Complete crossings count as one
+    /// option if they're real words, and crossings of a single cell are left out.
+    fn crossing_fits(
+        &self,
+        dictionary: &Dictionary,
+        index: usize,
+        direction: Direction,
+        used: &HashSet<String>,
+        limit: usize,
+    ) -> Vec<usize> {
+        let crossing = match direction {
+            Direction::Across => Direction::Down,
+            Direction::Down => Direction::Across,
+        };
+        let mut all_fits = Vec::new();
+        for (x, y) in self.slot_coords(index, direction) {
+            let (mut start_x, mut start_y) = (x, y);
+            loop {
+                let before = match crossing {
+                    Direction::Across => start_x.checked_sub(1).map(|x| (x, start_y)),
+                    Direction::Down => start_y.checked_sub(1).map(|y| (start_x, y)),
+                };
+                match before {
+                    Some((bx, by))
+                        if self.get(bx, by).is_white() && !self.has_bar(bx, by, crossing) =>
+                    {
+                        (start_x, start_y) = (bx, by)
+                    }
+                    _ => break,
+                }
+            }
+            let Ok(word) = self.slot_word(start_y * self.size + start_x, crossing) else {
+                continue;
+            };
+            if word.len() < 2 {
+                continue;
+            }
+            let fits = match word.as_complete() {
+                Some(word) => usize::from(dictionary.is_valid_canonical(&word.to_lowercase())),
+                None => dictionary.suggest_words_excluding(word, limit, used).len(),
+            };
+            all_fits.push(fits);
+        }
+        all_fits
+    }
+
+    /// The partial word in the slot starting at index
+    fn slot_word(&self, index: usize, direction: Direction) -> Result<SparseWord, PuzzleError> {
+        self.index_coords(index)?;
+        let partial_word = match direction {
+            Direction::Across => self.get_across_word(index),
+            Direction::Down => self.get_down_word(index),
+        };
+        partial_word.ok_or(PuzzleError::NoWord(direction, index))
+    }
+
+    /// Every complete word in the grid, lowercase
+    fn used_words(&self) -> HashSet<String> {
+        self.all_words_iter()
+            .filter(|word| word.is_complete())
+            .map(|word| word.as_string().to_lowercase())
+            .collect()
+    }
+
+    /// The (x, y) coordinates of the cell at index, or an error if the index is past the last cell
+    pub fn index_coords(&self, index: usize) -> Result<(usize, usize), GridError> {
+        let (x, y) = match self.size {
+            0 => (index, 0),
+            size => (index % size, index / size),
+        };
+        self.cells.try_get(x, y)?;
+        Ok((x, y))
+    }
+
+    fn take_word(cells: &[Cell], start: usize) -> Option<SparseWord> {
+        let mut idx = start;
+        let mut chars: Vec<Option<char>> = Vec::new();
+        while let Some(cell) = cells.get(idx) {
+            match cell {
+                Cell::Black => break,
+                Cell::Empty => chars.push(None),
+                Cell::Letter(l) => chars.push(Some(*l)),
+                Cell::Rebus(letters) => chars.extend(letters.chars().map(Some)),
+            }
+            idx += 1;
+        }
+        if !chars.is_empty() {
+            Some(SparseWord::new(chars))
+        } else {
+            None
+        }
+    }
+
+    /// Write a word into the across slot that starts at index, numbered as in `get_across_word`
+    pub fn fill_across(&mut self, index: usize, word: &str) -> Result<(), PuzzleError> {
+        self.fill(index, Direction::Across, word)
+    }
+
+    /// Write a word into the down slot that starts at index, numbered as in `get_down_word`
+    pub fn fill_down(&mut self, index: usize, word: &str) -> Result<(), PuzzleError> {
+        self.fill(index, Direction::Down, word)
+    }
+
+    /// Write each word into its slot in order, as `fill_across` and `fill_down` would, returning what happened to each.
+    /// A word that doesn't fit is skipped and the rest are still written, so later words see earlier ones.
+    pub fn apply_fills(
+        &mut self,
+        fills: &[(usize, Direction, String)],
+    ) -> Vec<Result<(), PuzzleError>> {
+        fills
+            .iter()
+            .map(|(index, direction, word)| self.fill(*index, *direction, word))
+            .collect()
+    }
+
+    /// Write a word into a slot, as long as it's exactly the length of the slot and agrees with every letter already
+    /// in it. Rebus cells are left in place and must match the letters of the word that fall in them, and a locked
+    /// empty cell can't be written at all. Nothing is written if the word doesn't fit.
+    fn fill(&mut self, index: usize, direction: Direction, word: &str) -> Result<(), PuzzleError> {
+        self.index_coords(index)?;
+        let slot = self.slot_coords(index, direction);
+        if slot.is_empty() {
+            return Err(PuzzleError::NoWord(direction, index));
+        }
+        let letters: Vec<char> = word.chars().map(canonical_letter).collect();
+        let widths: Vec<usize> = slot
+            .iter()
+            .map(|&(x, y)| match self.get(x, y) {
+                Cell::Rebus(rebus) => rebus.chars().count(),
+                _ => 1,
+            })
+            .collect();
+        let slot_len = widths.iter().sum();
+        if letters.len() != slot_len {
+            return Err(PuzzleError::WrongLength(word.to_string(), slot_len));
+        }
+
+        let mut placed = Vec::new();
+        let mut rest = letters.as_slice();
+        for (&(x, y), width) in slot.iter().zip(widths) {
+            let (part, remaining) = rest.split_at(width);
+            rest = remaining;
+            match self.get(x, y) {
+                Cell::Letter(existing) if *existing != part[0] => {
+                    return Err(PuzzleError::ConflictingLetter((x, y), *existing, part[0]));
+                }
+                Cell::Rebus(rebus) => {
+                    let mismatch = rebus
+                        .chars()
+                        .zip(part)
+                        .find(|(existing, letter)| existing != *letter);
+                    if let Some((existing, letter)) = mismatch {
+                        return Err(PuzzleError::ConflictingLetter((x, y), existing, *letter));
+                    }
+                }
+                Cell::Empty if self.is_locked(x, y) => return Err(PuzzleError::LockedCell((x, y))),
+                _ => placed.push(((x, y), part[0])),
+            }
+        }
+        self.checkpoint();
+        for ((x, y), letter) in placed {
+            self.put(x, y, Cell::Letter(letter));
+        }
+        Ok(())
+    }
+
+    /// Write a theme entry into the slot starting at index, bounding it with black squares if the slot is longer than
+    /// the word. The black squares are placed symmetrically, which reserves a slot of the same length on the other
+    /// side of the grid for the entry's partner. Fails without changing anything if that slot doesn't come out the
+    /// same length, such as when the grid isn't symmetric.
+    pub fn place_theme(
+        &mut self,
+        word: &str,
+        index: usize,
+        direction: Direction,
+    ) -> Result<(), PuzzleError> {
+        let (x, y) = self.index_coords(index)?;
+        let slot = self.slot_coords(index, direction);
+        if slot.is_empty() {
+            return Err(PuzzleError::NoWord(direction, index));
+        }
+        let len = word.chars().count();
+        if len > slot.len() {
+            return Err(PuzzleError::WrongLength(word.to_string(), slot.len()));
+        }
+
+        let mut trial = self.scratch_copy();
+        let before = match direction {
+            Direction::Across => x.checked_sub(1).map(|x| (x, y)),
+            Direction::Down => y.checked_sub(1).map(|y| (x, y)),
+        };
+        if let Some((x, y)) = before {
+            if trial.get(x, y).is_white() {
+                trial.put_symmetric((x, y), Cell::Black);
+            }
+        }
+        if let Some(&after) = slot.get(len) {
+            trial.put_symmetric(after, Cell::Black);
+        }
+        trial.fill(index, direction, word)?;
+
+        let mut partner: Vec<(usize, usize)> = slot[..len]
+            .iter()
+            .map(|&cell| self.symmetry.counterpart(cell, self.size))
+            .collect();
+        partner.sort_by_key(|&(x, y)| (y, x));
+        let (start_x, start_y) = partner[0];
+        let partner_direction = if partner.iter().all(|&(_, y)| y == start_y) {
+            Direction::Across
+        } else {
+            Direction::Down
+        };
+        let starts_slot = trial
+            .slot_starts()
+            .contains(&(start_y * self.size + start_x, partner_direction));
+        let partner_slot = trial.slot_coords(start_y * self.size + start_x, partner_direction);
+        if len > 1 && (!starts_slot || partner_slot != partner) {
+            return Err(PuzzleError::NoSymmetricSlot(len));
+        }
+
+        self.checkpoint();
+        self.cells = trial.cells;
+        self.restore_transpose();
+        Ok(())
+    }
+
+    /// The coordinates of the cells from index up to the next black square, bar or edge in the given direction
+    fn slot_coords(&self, index: usize, direction: Direction) -> Vec<(usize, usize)> {
+        let (mut x, mut y) = (index % self.size, index / self.size);
+        let mut coords = Vec::new();
+        while x < self.size && y < self.size && self.get(x, y).is_white() {
+            coords.push((x, y));
+            if self.has_bar(x, y, direction) {
+                break;
+            }
+            match direction {
+                Direction::Across => x += 1,
+                Direction::Down => y += 1,
+            }
+        }
+        coords
+    }
+
+    /// The index and direction of every slot, across slots first, where a slot starts at a white cell with a black
+    /// square or the edge of the grid before it
+    fn slot_starts(&self) -> Vec<(usize, Direction)> {
+        let mut starts: Vec<(usize, Direction)> = self
+            .segments_across()
+            .chain(self.segments_down())
+            .map(|word| (word.start.1 * self.size + word.start.0, word.direction))
+            .collect();
+        starts.sort_by_key(|&(index, direction)| (direction == Direction::Down, index));
+        starts
+    }
+
+    /// Number the cells that start an across or down word of at least two letters, left to right and top to bottom,
+    /// the way clues are numbered in a published puzzle
+    pub fn clue_numbers(&self) -> HashMap<(usize, usize), usize> {
+        let mut starts: Vec<(usize, usize)> = self
+            .slot_starts()
+            .into_iter()
+            .filter(|&(index, direction)| self.slot_coords(index, direction).len() >= 2)
+            .map(|(index, _)| (index % self.size, index / self.size))
+            .collect();
+        starts.sort_by_key(|&(x, y)| (y, x));
+        starts.dedup();
+        starts
+            .into_iter()
+            .enumerate()
+            .map(|(n, coords)| (coords, n + 1))
+            .collect()
+    }
+
+    /// Every word of at least two letters with its clue number, across words first and then down words, each in clue
+    /// order
+    pub fn words_with_positions(&self) -> Vec<(usize, Word<'_>)> {
+        let numbers = self.clue_numbers();
+        let mut words: Vec<(usize, Word)> = self
+            .all_words_iter()
+            .filter(|word| word.len >= 2)
+            .filter_map(|word| Some((*numbers.get(&word.start)?, word)))
+            .collect();
+        words.sort_by_key(|(number, word)| (word.direction == Direction::Down, *number));
+        words
+    }
+
+    /// Compare the clue numbers of this grid's slots against an earlier version of it. Clue numbers are always worked
+    /// out from the grid, so nothing needs updating, but anything keyed by number, like clues written elsewhere, needs
+    /// to follow the moved slots and drop the removed ones.
+    pub fn renumbering(&self, before: &Puzzle) -> Renumbering {
+        let now: HashMap<((usize, usize), Direction), usize> = self
+            .words_with_positions()
+            .into_iter()
+            .map(|(number, word)| ((word.start, word.direction), number))
+            .collect();
+        let mut renumbering = Renumbering::default();
+        for (old, word) in before.words_with_positions() {
+            match now.get(&(word.start, word.direction)) {
+                Some(&new) if new != old => renumbering.moved.push((word.direction, old, new)),
+                Some(_) => (),
+                None => renumbering.removed.push((word.direction, old)),
+            }
+        }
+        renumbering
+    }
+
+    /// For each empty cell, how many dictionary words fit the more constrained of the across and down slots through
+    /// it. Black squares and letters are `None`, and the cells with the lowest counts are the hardest to fill. Words
+    /// already used elsewhere in the grid are still counted, which only matters for slots with very few options.
+    pub fn density_map(&self) -> Vec<Vec<Option<usize>>> {
+        let mut map = vec![vec![None; self.size]; self.size];
+        for (index, direction) in self.slot_starts() {
+            let empty: Vec<(usize, usize)> = self
+                .slot_coords(index, direction)
+                .into_iter()
+                .filter(|&(x, y)| self.get(x, y).is_empty())
+                .collect();
+            if empty.is_empty() {
+                continue;
+            }
+            let Ok(word) = self.slot_word(index, direction) else {
+                continue;
+            };
+            if word.len() < 2 {
+                continue;
+            }
+            let count = DICTIONARY.count_matches(&word);
+            for (x, y) in empty {
+                let cell: &mut Option<usize> = &mut map[y][x];
+                *cell = Some(cell.map_or(count, |other| other.min(count)));
+            }
+        }
+        map
+    }
+
+    /// Find the partially filled slots that no word in the dictionary fits
+    pub fn unfillable_slots(&self) -> Vec<(usize, Direction)> {
+        self.slot_starts()
+            .into_iter()
+            .filter(|&(index, direction)| {
+                let has_empty = self
+                    .slot_coords(index, direction)
+                    .iter()
+                    .any(|&(x, y)| self.get(x, y).is_empty());
+                let word = match direction {
+                    Direction::Across => self.get_across_word(index),
+                    Direction::Down => self.get_down_word(index),
+                };
+                has_empty && word.is_some_and(|word| DICTIONARY.suggest_words(word, 1).is_empty())
+            })
+            .collect()
+    }
+
+    /// The run of white cells in the given direction that includes (x, y), wherever in the run the cell falls, or
+    /// `None` for a black square. `get_across_word` and `get_down_word` instead read from the cell to the end of the
+    /// run.
+    pub fn word_through(&self, x: usize, y: usize, direction: Direction) -> Option<Word<'_>> {
+        let (grid, line, offset) = match direction {
+            Direction::Across => (&self.cells, y, x),
+            Direction::Down => (&self.transpose, x, y),
+        };
+        Puzzle::segments_in(grid, direction, &self.bars).find(|word| {
+            let (start_line, start_offset) = match direction {
+                Direction::Across => (word.start.1, word.start.0),
+                Direction::Down => (word.start.0, word.start.1),
+            };
+            start_line == line && (start_offset..start_offset + word.len).contains(&offset)
+        })
+    }
+
+    /// The across and down slots that (x, y) is part of. A run of a single cell isn't a slot, so a cell boxed in on
+    /// one axis only has a slot in the other direction.
+    pub fn slots_through(&self, x: usize, y: usize) -> (Option<Word<'_>>, Option<Word<'_>>) {
+        let slot = |direction| {
+            self.word_through(x, y, direction)
+                .filter(|word| word.len >= 2)
+        };
+        (slot(Direction::Across), slot(Direction::Down))
+    }
+
+    /// Like `Word::score`, but letters in cells shared with a crossing slot count twice, since a rare letter there
+    /// makes two entries harder to fill cleanly
+    pub fn slot_quality(&self, word: &Word) -> f64 {
+        let other = match word.direction {
+            Direction::Across => Direction::Down,
+            Direction::Down => Direction::Across,
+        };
+        weighted_commonality(word.cells.iter().zip(word.coords()).map(|(cell, (x, y))| {
+            let crossed = self
+                .word_through(x, y, other)
+                .is_some_and(|crossing| crossing.len >= 2);
+            (cell, if crossed { 2.0 } else { 1.0 })
+        }))
+    }
+
+    /// The `slot_quality` the slot starting at index would have if fill were put in it, for ranking suggestions
+    pub fn fill_quality(
+        &self,
+        index: usize,
+        direction: Direction,
+        fill: &str,
+    ) -> Result<f64, PuzzleError> {
+        let mut trial = self.scratch_copy();
+        trial.fill(index, direction, fill)?;
+        let (x, y) = trial.index_coords(index)?;
+        Ok(trial
+            .word_through(x, y, direction)
+            .map_or(1.0, |word| trial.slot_quality(&word)))
+    }
+
+    /// Every maximal run of white cells in each row, top to bottom, including runs of a single cell
+    pub fn segments_across(&self) -> impl Iterator<Item = Word<'_>> {
+        Puzzle::segments_in(&self.cells, Direction::Across, &self.bars)
+    }
+
+    /// Every maximal run of white cells in each column, left to right, including runs of a single cell
+    pub fn segments_down(&self) -> impl Iterator<Item = Word<'_>> {
+        Puzzle::segments_in(&self.transpose, Direction::Down, &self.bars)
+    }
+
+    /// iterate through each row, separating by black cells and bars
+    fn words_across_iter(&self) -> impl Iterator<Item = Word<'_>> {
+        self.segments_across()
+    }
+
+    /// iterate through each col, separating by black cells and bars
+    fn words_down_iter(&self) -> impl Iterator<Item = Word<'_>> {
+        self.segments_down()
+    }
+
+    fn all_words_iter(&self) -> impl Iterator<Item = Word<'_>> {
+        self.words_across_iter().chain(self.words_down_iter())
+    }
+
+    /// The runs of white cells in each row of a grid, where the rows of the transpose are the puzzle's columns. A run
+    /// ends at a black square, or just after a cell with a bar in the direction of the run.
+    fn segments_in<'a>(
+        grid: &'a Grid,
+        direction: Direction,
+        bars: &'a BTreeSet<((usize, usize), Direction)>,
+    ) -> impl Iterator<Item = Word<'a>> {
+        grid.rows_iter().enumerate().flat_map(move |(line, row)| {
+            let coords = move |offset| match direction {
+                Direction::Across => (offset, line),
+                Direction::Down => (line, offset),
+            };
+            let mut runs = Vec::new();
+            let mut start = 0;
+            for (offset, cell) in row.iter().enumerate() {
+                if cell.is_black() {
+                    runs.push(start..offset);
+                    start = offset + 1;
+                } else if bars.contains(&(coords(offset), direction)) {
+                    runs.push(start..offset + 1);
+                    start = offset + 1;
+                }
+            }
+            runs.push(start..row.len());
+            runs.into_iter()
+                .filter(|run| !run.is_empty())
+                .map(move |run| Word {
+                    start: coords(run.start),
+                    direction,
+                    len: run.len(),
+                    cells: &row[run],
+                })
+        })
+    }
+
+    /// Gather word counts, black square density and fill progress for the puzzle
+    pub fn stats(&self) -> PuzzleStats {
+        let mut words_by_length = BTreeMap::new();
+        for word in self.all_words_iter() {
+            *words_by_length.entry(word.len).or_insert(0) += 1;
+        }
+        let total_words: usize = words_by_length.values().sum();
+        let total_letters: usize = words_by_length.iter().map(|(len, n)| len * n).sum();
+        let average_word_length = if total_words > 0 {
+            total_letters as f64 / total_words as f64
+        } else {
+            0.0
+        };
+
+        PuzzleStats {
+            total_words,
+            words_by_length,
+            average_word_length,
+            black_percent: self.cells.black_square_fraction() * 100.0,
+            cheater_squares: self.cheater_squares(total_words),
+            empty_cells: self.empty_cells(),
+        }
+    }
+
+    /// Count the black squares that could be turned white without changing the number of words
+    fn cheater_squares(&self, total_words: usize) -> usize {
+        let mut cheaters = 0;
+        for y in 0..self.size {
+            for x in 0..self.size {
+                if self.get(x, y).is_black() {
+                    let mut without = self.scratch_copy();
+                    without.put(x, y, Cell::Empty);
+                    if without.all_words_iter().count() == total_words {
+                        cheaters += 1;
+                    }
+                }
+            }
+        }
+        cheaters
+    }
+
+    /// Validate that the puzzle "base" (the grid, with black cells but without letters) is valid according to the spec:
+    /// 1. The grid is square
+    /// 2. The positions of the blacks squares follow the puzzle's symmetry
+    /// 3. That the black squares don't represent too high a proportion of the total grid, per the puzzle's rules.
+    /// 4. Every white square is part of both an across and a down word
+    /// 5. All words are at least `Rules::min_word_len` letters long, 3 by default
+    pub fn validate_base(&self) -> Result<(), PuzzleError> {
+        self.cells.is_square()?;
+        self.symmetric()?;
+        self.cells
+            .acceptable_black_square_count(self.rules.max_black_percent)?;
+        self.no_isolated_cells()?;
+        self.no_unkeyed_letters()?;
+        self.interlocked()?;
+        self.no_too_short_words()?;
+        Ok(())
+    }
+
+    /// Validate that the words in the puzzle meet the spec:
+    /// 1. Not repeat workds
+    /// 2. All words are at least `Rules::min_word_len` letters long, 3 by default
+    /// 3. All words appear in the dictionary we're using
+    pub fn validate_words(&self) -> Result<(), PuzzleError> {
+        self.no_repeat_words()?;
+        self.no_too_short_words()?;
+        self.valid_words()?;
+        Ok(())
+    }
+
+    /// Run every check from `validate_base` without stopping at the first failure. The other checks assume a square
+    /// grid, so only that problem is reported if the grid isn't square.
+    pub fn base_violations(&self) -> Vec<PuzzleError> {
+        if let Err(e) = self.cells.is_square() {
+            return vec![e];
+        }
+        [
+            self.symmetric(),
+            self.cells
+                .acceptable_black_square_count(self.rules.max_black_percent),
+            self.no_isolated_cells(),
+            self.no_unkeyed_letters(),
+            self.interlocked(),
+            self.no_too_short_words(),
+        ]
+        .into_iter()
+        .filter_map(Result::err)
+        .collect()
+    }
+
+    /// The word problems caused by an edit to (x, y), checking only the across and down words through it: whether
+    /// they're long enough and, once complete, whether they're in the dictionary or repeat a word elsewhere in the
+    /// grid. Much cheaper than `word_violations` after every keystroke, at the cost of not noticing problems
+    /// elsewhere.
+    pub fn validate_cell_edit(&self, x: usize, y: usize) -> Vec<PuzzleError> {
+        let words: Vec<Word> = [Direction::Across, Direction::Down]
+            .into_iter()
+            .filter_map(|direction| self.word_through(x, y, direction))
+            .collect();
+        let mut problems = Vec::new();
+
+        let short: Vec<ShortWord> = words
+            .iter()
+            .filter(|word| word.len < self.rules.min_word_len)
+            .map(|word| (word.as_string(), word.start, word.direction))
+            .collect();
+        if !short.is_empty() {
+            problems.push(PuzzleError::ShortWords(short, self.rules.min_word_len));
+        }
+
+        let complete: Vec<&Word> = words
+            .iter()
+            .filter(|word| word.len >= 2 && word.is_complete())
+            .collect();
+        // Reported the same way as the full check, with every slot each word fills. The across and down words can
+        // repeat each other, which still only makes one group.
+        let repeated: Vec<RepeatedWord> = self
+            .repeated_words()
+            .into_iter()
+            .filter(|group| {
+                group.slots.iter().any(|&(_, direction, start)| {
+                    complete
+                        .iter()
+                        .any(|word| (word.start, word.direction) == (start, direction))
+                })
+            })
+            .collect();
+        if !repeated.is_empty() {
+            problems.push(PuzzleError::RepeatedWords(repeated));
+        }
+
+        let complete: Vec<String> = complete.iter().map(|word| word.as_string()).collect();
+        if !complete.is_empty() {
+            match DICTIONARY.check_loaded() {
+                Err(e) => problems.push(e.into()),
+                Ok(()) => {
+                    let mut invalid = DICTIONARY.which_invalid(&complete);
+                    invalid.dedup();
+                    if !invalid.is_empty() {
+                        problems.push(PuzzleError::MadeUpWord(invalid.join(", ")));
+                    }
+                }
+            }
+        }
+        problems
+    }
+
+    /// Run the word checks that `base_violations` doesn't cover, without stopping at the first failure
+    pub fn word_violations(&self) -> Vec<PuzzleError> {
+        [self.no_repeat_words(), self.valid_words()]
+            .into_iter()
+            .filter_map(Result::err)
+            .collect()
+    }
+
+    /// Run the check for a single rule. Every rule but the dictionary one first checks that the grid is square, since
+    /// the others assume it is.
+    pub fn check_rule(&self, rule: Rule) -> Result<(), PuzzleError> {
+        if rule != Rule::Dictionary {
+            self.cells.is_square()?;
+        }
+        match rule {
+            Rule::Symmetry => self.symmetric(),
+            Rule::BlackCount => self
+                .cells
+                .acceptable_black_square_count(self.rules.max_black_percent),
+            Rule::Unkeyed => self
+                .no_isolated_cells()
+                .and_then(|_| self.no_unkeyed_letters()),
+            Rule::MinLength => self.no_too_short_words(),
+            Rule::Interlock => self.interlocked(),
+            Rule::Repeats => self.no_repeat_words(),
+            Rule::Dictionary => self.valid_words(),
+        }
+    }
+
+    /// Check the black squares follow the puzzle's symmetry, within the tolerance the rules allow
+    pub fn symmetric(&self) -> Result<(), PuzzleError> {
+        self.cells
+            .is_nearly_symmetric(self.symmetry, self.rules.symmetry_tolerance)
+    }
+
+    /// Check that no word appears twice in the grid, reporting every repeated word at once
+    pub fn no_repeat_words(&self) -> Result<(), PuzzleError> {
+        let repeated = self.repeated_words();
+        if repeated.is_empty() {
+            Ok(())
+        } else {
+            Err(PuzzleError::RepeatedWords(repeated))
+        }
+    }
+
+    /// Every complete word that fills more than one slot, in either direction, with where each copy is. Words are
+    /// listed in the order they first appear, across slots before down ones.
+    pub fn repeated_words(&self) -> Vec<RepeatedWord> {
+        let numbers = self.clue_numbers();
+        let mut groups: Vec<RepeatedWord> = Vec::new();
+        for word in self.all_words_iter() {
+            if word.len < 2 || !word.is_complete() {
+                continue;
+            }
+            let text = word.as_string();
+            let slot = (numbers[&word.start], word.direction, word.start);
+            match groups.iter_mut().find(|group| group.word == text) {
+                Some(group) => group.slots.push(slot),
+                None => groups.push(RepeatedWord {
+                    word: text,
+                    slots: vec![slot],
+                }),
+            }
+        }
+        groups.retain(|group| group.slots.len() > 1);
+        groups
+    }
+
+    /// The white cells with a black square or the edge of the grid on all four sides, left to right and top to bottom
+    pub fn isolated_cells(&self) -> Vec<(usize, usize)> {
+        let single_across: HashSet<(usize, usize)> = self
+            .segments_across()
+            .filter(|segment| segment.len == 1)
+            .map(|segment| segment.start)
+            .collect();
+        let mut isolated: Vec<(usize, usize)> = self
+            .segments_down()
+            .filter(|segment| segment.len == 1 && single_across.contains(&segment.start))
+            .map(|segment| segment.start)
+            .collect();
+        isolated.sort_by_key(|&(x, y)| (y, x));
+        isolated
+    }
+
+    /// Check that every white cell is part of at least one word
+    pub fn no_isolated_cells(&self) -> Result<(), PuzzleError> {
+        match self.isolated_cells().first() {
+            Some(&coords) => Err(PuzzleError::IsolatedCell(coords)),
+            None => Ok(()),
+        }
+    }
+
+    /// Check that every white cell belongs to an across word and a down word of at least two letters, so that no
+    /// letter is "unkeyed"
+    pub fn no_unkeyed_letters(&self) -> Result<(), PuzzleError> {
+        match self.unkeyed_cells().first() {
+            Some(&(coords, _)) => Err(PuzzleError::UnkeyedLetter(coords)),
+            None => Ok(()),
+        }
+    }
+
+    /// The white cells that aren't part of a word of at least two letters in some direction, along with that
+    /// direction, left to right and top to bottom. A cell boxed in on all sides is listed once for each direction.
+    fn unkeyed_cells(&self) -> Vec<((usize, usize), Direction)> {
+        let mut unkeyed: Vec<((usize, usize), Direction)> = self
+            .segments_across()
+            .chain(self.segments_down())
+            .filter(|segment| segment.len == 1)
+            .map(|segment| (segment.start, segment.direction))
+            .collect();
+        unkeyed.sort_by_key(|&((x, y), direction)| (y, x, direction == Direction::Down));
+        unkeyed
+    }
+
+    /// Black squares next to an unkeyed cell whose removal, along with their symmetric partners, would key that cell
+    /// and leave fewer unkeyed cells overall, left to right and top to bottom. Each is a repair for a failing
+    /// `no_unkeyed_letters`, though the other rules should be checked again after making it.
+    pub fn suggest_keying_fixes(&self) -> Vec<(usize, usize)> {
+        let unkeyed = self.unkeyed_cells();
+        let mut fixes = BTreeSet::new();
+        for &((x, y), direction) in &unkeyed {
+            let neighbors = match direction {
+                Direction::Across => [x.checked_sub(1).map(|x| (x, y)), Some((x + 1, y))],
+                Direction::Down => [y.checked_sub(1).map(|y| (x, y)), Some((x, y + 1))],
+            };
+            for (bx, by) in neighbors.into_iter().flatten() {
+                if !self.cells.try_get(bx, by).is_ok_and(Cell::is_black) {
+                    continue;
+                }
+                let mut trial = self.scratch_copy();
+                trial.put_symmetric((bx, by), Cell::Empty);
+                let still_unkeyed = trial.unkeyed_cells();
+                if still_unkeyed.len() < unkeyed.len()
+                    && !still_unkeyed.iter().any(|&(coords, _)| coords == (x, y))
+                {
+                    fixes.insert((by, bx));
+                }
+            }
+        }
+        fixes.into_iter().map(|(y, x)| (x, y)).collect()
+    }
+
+    /// Check that every white cell can be reached from every other one without crossing a black square, so the puzzle
+    /// doesn't fall apart into separate mini puzzles
+    pub fn interlocked(&self) -> Result<(), PuzzleError> {
+        let white = |(x, y): (usize, usize)| self.get(x, y).is_white();
+        let whites = self.cells.count_cells(|cell| cell.is_white());
+        let start = self
+            .cells
+            .iter_coords()
+            .find(|(_, _, cell)| cell.is_white())
+            .map(|(x, y, _)| (x, y));
+        let Some(start) = start else {
+            return Ok(());
+        };
+
+        let mut seen = HashSet::from([start]);
+        let mut stack = vec![start];
+        while let Some((x, y)) = stack.pop() {
+            let neighbors = [
+                x.checked_sub(1).map(|x| (x, y)),
+                y.checked_sub(1).map(|y| (x, y)),
+                (x + 1 < self.size).then_some((x + 1, y)),
+                (y + 1 < self.size).then_some((x, y + 1)),
+            ];
+            for neighbor in neighbors.into_iter().flatten() {
+                if white(neighbor) && seen.insert(neighbor) {
+                    stack.push(neighbor);
+                }
+            }
+        }
+        if seen.len() == whites {
+            Ok(())
+        } else {
+            Err(PuzzleError::NotInterlocked)
+        }
+    }
+
+    /// Check that every word is at least as long as the rules allow
+    pub fn no_too_short_words(&self) -> Result<(), PuzzleError> {
+        let short_words = self.short_words();
+        if short_words.is_empty() {
+            Ok(())
+        } else {
+            Err(PuzzleError::ShortWords(
+                short_words,
+                self.rules.min_word_len,
+            ))
+        }
+    }
+
+    /// Every word shorter than the rules allow, across words first, so each one can be found and fixed
+    pub fn short_words(&self) -> Vec<ShortWord> {
+        self.all_words_iter()
+            .filter(|word| word.len < self.rules.min_word_len)
+            .map(|word| (word.as_string(), word.start, word.direction))
+            .collect()
+    }
+
+    /// Check that every complete word is in the dictionary
+    pub fn valid_words(&self) -> Result<(), PuzzleError> {
+        DICTIONARY.check_loaded()?;
+        let words: Vec<String> = self.all_words_iter().map(|word| word.as_string()).collect();
+        if DICTIONARY.all_valid(&words) {
+            return Ok(());
+        }
+        // A made-up word can show up more than once, such as both across and down
+        let mut invalid = DICTIONARY.which_invalid(&words);
+        invalid.sort();
+        invalid.dedup();
+        Err(PuzzleError::MadeUpWord(invalid.join(", ")))
+    }
+
+    fn valid_black_placement(&self, (x, y): (usize, usize)) -> bool {
+        // Capture the slices of the puzzle right, left, above and below the suggested black-placement and validate that it would leave
+        // enough space in each direction
+        let mut row: Vec<Cell> = self.cells.get_row(y).clone();
+        let Ok(mut col) = self.cells.get_column(x) else {
+            return false;
+        };
+        let (left, mut right) = row.split_at_mut(x);
+        let (up, mut down) = col.split_at_mut(y);
+
+        // Truncate right and down since `split_at_mut` is inclusive.
+        if !right.is_empty() {
+            right = &mut right[1..];
+        }
+        if !down.is_empty() {
+            down = &mut down[1..];
+        }
+
+        // Reverse left and up so we just have to look left to right each slice.
+        left.reverse();
+        up.reverse();
+
+        let min = self.rules.min_word_len;
+        Grid::ok_dist_to_black_or_edge(left, min)
+            && Grid::ok_dist_to_black_or_edge(right, min)
+            && Grid::ok_dist_to_black_or_edge(up, min)
+            && Grid::ok_dist_to_black_or_edge(down, min)
+    }
+
+    /// Check that a black square could be placed at (x, y) along with all of its symmetric partners, without cutting
+    /// the grid into separate areas. The partners are placed first since they can be close enough to each other to
+    /// create short words.
+    fn valid_symmetric_placement(&self, (x, y): (usize, usize)) -> bool {
+        let mut trial = self.scratch_copy();
+        trial.put_symmetric((x, y), Cell::Black);
+        self.symmetry
+            .partners((x, y), self.size)
+            .into_iter()
+            .all(|partner| trial.valid_black_placement(partner))
+            && trial.interlocked().is_ok()
+    }
+
+    /// The cells a black square at (x, y) would fill once placed symmetrically, each listed once even where the
+    /// symmetry maps a cell to itself, and whether every one of them would still leave room for words around it.
+    /// Nothing is changed, so this shows what `set_symmetric` would do before doing it.
+    pub fn preview_symmetric(&self, (x, y): (usize, usize)) -> (Vec<(usize, usize)>, bool) {
+        let partners = self.symmetry.partners((x, y), self.size);
+        let mut trial = self.scratch_copy();
+        trial.put_symmetric((x, y), Cell::Black);
+        let valid = partners
+            .iter()
+            .all(|&partner| trial.valid_black_placement(partner));
+        (partners, valid)
+    }
+
+    /// Generate a random configuration of black squares to form a symmetric puzzle, drawing from rng so that a seeded
+    /// generator reproduces the same grid
+    pub fn random_black(&mut self, rng: &mut impl Rng) {
+        // It's not possible to have valid black squares for puzzles that can't fit two minimum length words and a black
+        // square between them in a row, since the puzzle must be symmetric
+        if self.size < 2 * self.rules.min_word_len + 1 {
+            return;
+        }
+        self.checkpoint();
+        let upper_threshold_black = (self.size * self.size * self.rules.max_black_percent) / 100;
+        // The number of squares a typical placement adds, used to stop before going over the threshold
+        let orbit = match self.symmetry {
+            Symmetry::Rotational => 4,
+            _ => 2,
+        };
+        let mut black_set = 0;
+
+        loop {
+            // Stop early if there's nowhere left to put a black square, rather than looping forever
+            let mut any_valid = false;
+            for row in self.placement_region() {
+                for (col, row) in row {
+                    let cell = self.get(col, row);
+                    if cell.is_white() && self.valid_symmetric_placement((col, row)) {
+                        any_valid = true;
+                        // A random chance of setting the cell to black
+                        let x = rng.gen_bool(1.0 / 2.0);
+                        if x {
+                            black_set += self.put_symmetric((col, row), Cell::Black);
+                            if black_set + orbit > upper_threshold_black {
+                                return;
+                            }
+                            break;
+                        }
+                    }
+                }
+            }
+            if !any_valid {
+                return;
+            }
+        }
+    }
+
+    /// Replace the grid with exactly target black squares, placed symmetrically at random. Each placement is checked
+    /// like those in `random_black`, and a placement whose symmetric partners would overshoot the target is skipped,
+    /// so a target that no combination of placements adds up to fails after `MAX_BASE_ATTEMPTS` tries.
+    pub fn random_black_count(
+        &mut self,
+        rng: &mut impl Rng,
+        target: usize,
+    ) -> Result<(), PuzzleError> {
+        if target * 100 > self.size * self.size * self.rules.max_black_percent {
+            return Err(PuzzleError::TooManyBlackSquares(
+                self.rules.max_black_percent,
+            ));
+        }
+        let mut coords: Vec<(usize, usize)> = (0..self.size * self.size)
+            .map(|index| (index % self.size, index / self.size))
+            .collect();
+        for _ in 0..MAX_BASE_ATTEMPTS {
+            let mut attempt = self.scratch_copy();
+            attempt.cells = Grid::new(self.size);
+            attempt.restore_transpose();
+            let mut placed = 0;
+            coords.shuffle(rng);
+            for &(x, y) in &coords {
+                if placed == target {
+                    break;
+                }
+                let orbit = self.symmetry.partners((x, y), self.size).len();
+                if attempt.get(x, y).is_black()
+                    || placed + orbit > target
+                    || !attempt.valid_symmetric_placement((x, y))
+                {
+                    continue;
+                }
+                placed += attempt.put_symmetric((x, y), Cell::Black);
+            }
+            if placed == target {
+                self.checkpoint();
+                self.cells = attempt.cells;
+                self.restore_transpose();
+                return Ok(());
+            }
+        }
+        Err(PuzzleError::NoBlackPlacement(target, MAX_BASE_ATTEMPTS))
+    }
+
+    /// Clear the grid, letters included, and place a fresh random set of black squares with `random_black`, keeping the
+    /// puzzle's name, symmetry, rules and marks. The new base has to pass `validate_base`; if it doesn't, the error is
+    /// returned and the puzzle is left as it was.
+    pub fn shuffle_black(&mut self, rng: &mut impl Rng) -> Result<(), PuzzleError> {
+        let mut attempt = self.scratch_copy();
+        attempt.cells = Grid::new(self.size);
+        attempt.restore_transpose();
+        attempt.random_black(rng);
+        attempt.validate_base()?;
+        self.checkpoint();
+        self.cells = attempt.cells;
+        self.restore_transpose();
+        Ok(())
+    }
+
+    /// Replace the grid with random black squares, retrying from an empty grid until the result passes
+    /// `validate_base`. Unlike `random_black` alone, this guarantees a valid base, or fails after `MAX_BASE_ATTEMPTS`.
+    /// progress is called with the number of each attempt as it starts, and the total allowed.
+    pub fn random_valid_base(
+        &mut self,
+        rng: &mut impl Rng,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<(), PuzzleError> {
+        for attempt in 1..=MAX_BASE_ATTEMPTS {
+            progress(attempt, MAX_BASE_ATTEMPTS);
+            let mut attempt = self.scratch_copy();
+            attempt.cells = Grid::new(self.size);
+            attempt.restore_transpose();
+            attempt.random_black(rng);
+            if attempt.validate_base().is_ok() {
+                self.checkpoint();
+                self.cells = attempt.cells;
+                self.restore_transpose();
+                return Ok(());
+            }
+        }
+        Err(PuzzleError::NoValidBase(MAX_BASE_ATTEMPTS))
+    }
+
+    /// The cells, row by row, that `random_black` picks from. Setting these symmetrically covers the whole grid: a
+    /// quadrant for rotational symmetry, half the grid for mirror symmetry and a triangle for diagonal symmetry.
+    fn placement_region(&self) -> Vec<Vec<(usize, usize)>> {
+        let half = self.size.div_ceil(2);
+        let quadrant = max(2, self.size / 2);
+        let (rows, cols) = match self.symmetry {
+            Symmetry::Rotational => (quadrant, quadrant),
+            Symmetry::Horizontal => (self.size, half),
+            Symmetry::Vertical => (half, self.size),
+            Symmetry::Diagonal => (self.size, self.size),
+        };
+        (0..rows)
+            .map(|row| {
+                (0..cols)
+                    .filter(|col| self.symmetry != Symmetry::Diagonal || *col >= row)
+                    .map(|col| (col, row))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Set a cell and all of its partners under the puzzle's symmetry, returning the number of distinct cells set
+    pub fn set_symmetric(&mut self, (x, y): (usize, usize), val: Cell) -> usize {
+        self.checkpoint();
+        self.put_symmetric((x, y), val)
+    }
+
+    /// Swap the contents of cells a and b, along with each of their symmetric partners, so that moving a black square
+    /// keeps the grid symmetric. Each partner of a is swapped with the partner of b under the same transformation.
+    pub fn swap_symmetric(
+        &mut self,
+        a: (usize, usize),
+        b: (usize, usize),
+    ) -> Result<(), PuzzleError> {
+        self.cells.try_get(a.0, a.1)?;
+        self.cells.try_get(b.0, b.1)?;
+        if a == b {
+            return Ok(());
+        }
+        let (a_partners, b_partners) = (
+            self.symmetry.partners(a, self.size),
+            self.symmetry.partners(b, self.size),
+        );
+        if a_partners.len() != b_partners.len() || a_partners.contains(&b) {
+            return Err(PuzzleError::UnswappableCells(a, b));
+        }
+        self.checkpoint();
+        let pairs: Vec<((usize, usize), (usize, usize))> = self
+            .symmetry
+            .images(a, self.size)
+            .into_iter()
+            .zip(self.symmetry.images(b, self.size))
+            .collect();
+        let swapped: Vec<((usize, usize), Cell)> = pairs
+            .iter()
+            .flat_map(|&(a, b)| {
+                [
+                    (a, self.get(b.0, b.1).clone()),
+                    (b, self.get(a.0, a.1).clone()),
+                ]
+            })
+            .collect();
+        for ((x, y), cell) in swapped {
+            self.put(x, y, cell);
+        }
+        Ok(())
+    }
+
+    fn put_symmetric(&mut self, (x, y): (usize, usize), val: Cell) -> usize {
+        let partners = self.symmetry.partners((x, y), self.size);
+        for (x, y) in partners.iter() {
+            self.put(*x, *y, val.clone());
+        }
+        partners.len()
+    }
+
+    /// Trying to generate a random, valid puzzle with this takes too long for anything larger than
+    /// a 3x3 puzzle. Instead, can I organize the words in such a way that I can pick words by length
+    /// and verify that a substring could fit with existing letters?
+    ///
+    /// Aiming for a pangram, the letters missing from the grid are put in random empty cells first, so the grid uses
+    /// every letter as long as it has enough empty cells. Locked cells are left as they are, even if empty.
+    pub fn random_letters(&mut self, rng: &mut impl Rng, pangram: bool) {
+        self.checkpoint();
+        let mut empty: Vec<(usize, usize)> = self
+            .cells
+            .iter_coords()
+            .filter(|&(x, y, cell)| cell.is_empty() && !self.is_locked(x, y))
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        let mut missing = Vec::new();
+        if pangram {
+            missing = self.missing_letters();
+            empty.shuffle(rng);
+        }
+        for (i, (x, y)) in empty.into_iter().enumerate() {
+            let letter = match missing.get(i) {
+                Some(&letter) => letter,
+                None => rng.gen_range(b'A'..b'Z' + 1) as char,
+            };
+            self.put(x, y, Cell::Letter(letter));
+        }
+    }
+
+    /// The letters A to Z that appear nowhere in the grid, including in rebus cells
+    pub fn missing_letters(&self) -> Vec<char> {
+        let present: HashSet<char> = self
+            .cells
+            .iter_coords()
+            .flat_map(|(_, _, cell)| match cell {
+                Cell::Letter(letter) => vec![*letter],
+                Cell::Rebus(letters) => letters.chars().collect(),
+                _ => Vec::new(),
+            })
+            .collect();
+        ('A'..='Z')
+            .filter(|letter| !present.contains(letter))
+            .collect()
+    }
+
+    /// Whether every letter from A to Z appears somewhere in the grid
+    pub fn is_pangram(&self) -> bool {
+        self.missing_letters().is_empty()
+    }
+
+    /// Erase every letter and rebus, leaving the black squares and locked cells in place so a different fill can be
+    /// tried on the same base
+    pub fn clear_letters(&mut self) {
+        self.checkpoint();
+        let filled: Vec<(usize, usize)> = self
+            .cells
+            .iter_coords()
+            .filter(|&(x, y, cell)| cell.is_letter() && !self.is_locked(x, y))
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        for (x, y) in filled {
+            self.put(x, y, Cell::Empty);
+        }
+    }
+
+    /// Rotate the whole puzzle clockwise by 90 degrees for each quarter turn. A quarter turn swaps horizontal and
+    /// vertical symmetry. It would move a diagonal pattern onto the other diagonal, which isn't a supported symmetry,
+    /// so that's an error that leaves the puzzle as it was.
+    pub fn rotate(&mut self, quarter_turns: u8) -> Result<(), PuzzleError> {
+        let mut turned = self.cells.clone();
+        turned.rotate(quarter_turns);
+        self.keeps_symmetry(&turned)?;
+        self.checkpoint_with_marks();
+        self.cells.rotate(quarter_turns);
+        let last = self.size.saturating_sub(1);
+        for _ in 0..quarter_turns % 4 {
+            self.move_marks(|(x, y)| Some((last - y, x)));
+        }
+        if quarter_turns % 2 == 1 {
+            self.symmetry = match self.symmetry {
+                Symmetry::Horizontal => Symmetry::Vertical,
+                Symmetry::Vertical => Symmetry::Horizontal,
+                other => other,
+            };
+        }
+        self.restore_transpose();
+        Ok(())
+    }
+
+    /// Mirror the whole puzzle along an axis. As with `rotate`, moving a diagonal pattern onto the other diagonal is an
+    /// error.
+    pub fn flip(&mut self, axis: Axis) -> Result<(), PuzzleError> {
+        let mut flipped = self.cells.clone();
+        flipped.flip(axis);
+        self.keeps_symmetry(&flipped)?;
+        self.checkpoint_with_marks();
+        self.cells.flip(axis);
+        let last = self.size.saturating_sub(1);
+        match axis {
+            Axis::Horizontal => self.move_marks(|(x, y)| Some((last - x, y))),
+            Axis::Vertical => self.move_marks(|(x, y)| Some((x, last - y))),
+        }
+        self.restore_transpose();
+        Ok(())
+    }
+
+    /// Check that transformed, the grid after a rotation or flip, still has the symmetry the puzzle is saved with.
+    /// Only a diagonal pattern can lose it, and only if it was symmetric to begin with; a pattern that's symmetric
+    /// across both diagonals is fine.
+    fn keeps_symmetry(&self, transformed: &Grid) -> Result<(), PuzzleError> {
+        if self.symmetry != Symmetry::Diagonal || self.symmetric().is_err() {
+            return Ok(());
+        }
+        transformed
+            .is_nearly_symmetric(self.symmetry, self.rules.symmetry_tolerance)
+            .map_err(|_e| PuzzleError::OffDiagonal)
+    }
+
+    /// Remove the outermost rows and columns that are entirely black, returning how many were removed. The trimmed grid
+    /// must still be square and symmetric, which means trimming matching edges; otherwise the error is returned and the
+    /// puzzle is left as it was. A grid that's black all over is left alone.
+    pub fn trim_borders(&mut self) -> Result<usize, PuzzleError> {
+        let black_row = |y: usize| (0..self.size).all(|x| self.get(x, y).is_black());
+        let black_column = |x: usize| (0..self.size).all(|y| self.get(x, y).is_black());
+        let top = (0..self.size).take_while(|&y| black_row(y)).count();
+        if top == self.size {
+            return Ok(0);
+        }
+        let bottom = (0..self.size).rev().take_while(|&y| black_row(y)).count();
+        let left = (0..self.size).take_while(|&x| black_column(x)).count();
+        let right = (0..self.size)
+            .rev()
+            .take_while(|&x| black_column(x))
+            .count();
+        let trimmed = top + bottom + left + right;
+        if trimmed == 0 {
+            return Ok(0);
+        }
+
+        let mut attempt = self.scratch_copy();
+        attempt.cells = Grid(
+            self.cells.0[top..self.size - bottom]
+                .iter()
+                .map(|row| row[left..self.size - right].to_vec())
+                .collect(),
+        );
+        attempt.cells.is_square()?;
+        attempt.restore_transpose();
+        attempt.symmetric()?;
+
+        self.checkpoint_with_marks();
+        self.cells = attempt.cells;
+        let size = self.cells.len();
+        self.move_marks(|(x, y)| {
+            let (x, y) = (x.checked_sub(left)?, y.checked_sub(top)?);
+            (x < size && y < size).then_some((x, y))
+        });
+        self.restore_transpose();
+        Ok(trimmed)
+    }
+
+    /// Grow or shrink the grid to new_size, padding with empty cells or cutting off rows and columns according to
+    /// anchor. Shrinking fails if it would remove letters or black squares, unless force is set. Returns the number of
+    /// letters and black squares removed. A new_size of 0 leaves an empty grid, like `Puzzle::new` with no cells.
+    pub fn resize(
+        &mut self,
+        new_size: usize,
+        anchor: Anchor,
+        force: bool,
+    ) -> Result<usize, PuzzleError> {
+        let shift = match anchor {
+            Anchor::TopLeft => 0,
+            Anchor::Center => (self.size as isize - new_size as isize) / 2,
+        };
+        let kept = |n: usize| {
+            let n = n as isize - shift;
+            n >= 0 && n < new_size as isize
+        };
+        let lost = self
+            .cells
+            .iter_coords()
+            .filter(|&(x, y, cell)| !(kept(x) && kept(y)) && cell != &Cell::Empty)
+            .count();
+        if lost > 0 && !force {
+            return Err(PuzzleError::WouldLoseCells(lost));
+        }
+
+        let mut cells = Grid::new(new_size);
+        for y in 0..new_size {
+            for x in 0..new_size {
+                let (old_x, old_y) = (x as isize + shift, y as isize + shift);
+                if let (Ok(old_x), Ok(old_y)) = (usize::try_from(old_x), usize::try_from(old_y)) {
+                    if let Ok(cell) = self.cells.try_get(old_x, old_y) {
+                        cells.set(x, y, cell.clone());
+                    }
+                }
+            }
+        }
+        self.checkpoint_with_marks();
+        self.cells = cells;
+        self.move_marks(|(x, y)| {
+            let (x, y) = (x as isize - shift, y as isize - shift);
+            Some((usize::try_from(x).ok()?, usize::try_from(y).ok()?))
+        });
+        self.circled.retain(|&(x, y)| x < new_size && y < new_size);
+        self.locked.retain(|&(x, y)| x < new_size && y < new_size);
+        self.bars.retain(|&(coords, direction)| {
+            let (x, y) = Puzzle::bar_neighbor(coords, direction);
+            x < new_size && y < new_size
+        });
+        self.restore_transpose();
+        Ok(lost)
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: Cell) {
+        self.checkpoint();
+        self.put(x, y, value);
+    }
+
+    /// Set a cell without recording it in the history, for use within operations that record themselves
+    fn put(&mut self, x: usize, y: usize, value: Cell) {
+        self.cells.set(x, y, value.clone());
+        self.transpose.set(y, x, value);
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> &Cell {
+        self.cells.get(x, y)
+    }
+
+    /// Record the current grid so the edit that's about to happen can be undone
+    fn checkpoint(&mut self) {
+        self.push_undo(Snapshot {
+            cells: self.cells.clone(),
+            marks: None,
+        });
+    }
+
+    /// Record the current grid along with its marks, for an edit that moves them too
+    fn checkpoint_with_marks(&mut self) {
+        let marks = Marks {
+            symmetry: self.symmetry,
+            circled: self.circled.clone(),
+            locked: self.locked.clone(),
+            bars: self.bars.clone(),
+        };
+        self.push_undo(Snapshot {
+            cells: self.cells.clone(),
+            marks: Some(marks),
+        });
+    }
+
+    fn push_undo(&mut self, snapshot: Snapshot) {
+        if self.history.undo.len() == MAX_HISTORY {
+            self.history.undo.pop_front();
+        }
+        self.history.undo.push_back(snapshot);
+        self.history.redo.clear();
+    }
+
+    /// Return to the grid as it was before the last edit, returning false if there's nothing to undo
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo.pop_back() {
+            Some(previous) => {
+                let current = self.restore(previous);
+                self.history.redo.push(current);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reapply the last undone edit, returning false if there's nothing to redo
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo.pop() {
+            Some(next) => {
+                let current = self.restore(next);
+                self.history.undo.push_back(current);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Put a snapshot from the history in place, returning what it replaced in the same form
+    fn restore(&mut self, snapshot: Snapshot) -> Snapshot {
+        let cells = std::mem::replace(&mut self.cells, snapshot.cells);
+        let marks = snapshot.marks.map(|marks| Marks {
+            symmetry: std::mem::replace(&mut self.symmetry, marks.symmetry),
+            circled: std::mem::replace(&mut self.circled, marks.circled),
+            locked: std::mem::replace(&mut self.locked, marks.locked),
+            bars: std::mem::replace(&mut self.bars, marks.bars),
+        });
+        self.restore_transpose();
+        Snapshot { cells, marks }
+    }
+
+    fn restore_transpose(&mut self) {
+        self.size = self.cells.len();
+        self.transpose = self.cells.transpose();
+    }
+
+    /// A copy of the puzzle without its history, for trying out edits
+    fn scratch_copy(&self) -> Self {
+        Puzzle {
+            name: self.name.clone(),
+            size: self.size,
+            symmetry: self.symmetry,
+            rules: self.rules,
+            cells: self.cells.clone(),
+            circled: self.circled.clone(),
+            locked: self.locked.clone(),
+            bars: self.bars.clone(),
+            transpose: self.transpose.clone(),
+            history: History::default(),
+            ascii: false,
+            compact: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeSet,
+        time::{Duration, Instant},
+    };
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{
+        dictionary::{Dictionary, SparseWord, DICTIONARY},
+        grid::{Axis, GridError, Symmetry},
+        puzzle::{
+            letter_commonality, Anchor, Cell, Direction, Grid, Puzzle, PuzzleError, RepeatedWord,
+            Rule, Rules, SolveOutcome, Word, LETTER_FREQUENCIES, MAX_BASE_ATTEMPTS, MAX_HISTORY,
+        },
+        template::Template,
+        PERCENT_BLACK,
+    };
+
+    #[test]
+    fn valid_empty_grid() {
+        let empty = Puzzle::new("x".to_string(), 10);
         println!("{}", empty.cells());
         assert_eq!(empty.validate_base(), Ok(()));
     }
 
     #[test]
-    fn valid_random_grid() {
-        let mut random = Puzzle::new("x".to_string(), 14);
-        random.random_black();
-        println!("{}", random.cells());
-        assert_eq!(random.validate_base(), Ok(()));
+    fn seeded_random_grid() {
+        let generate = |seed| {
+            let mut puzzle = Puzzle::new("x".to_string(), 11);
+            let mut rng = StdRng::seed_from_u64(seed);
+            puzzle.random_valid_base(&mut rng, |_, _| ()).unwrap();
+            puzzle.random_letters(&mut rng, false);
+            puzzle.cells().to_string()
+        };
+        assert_eq!(generate(7), generate(7));
+        assert_ne!(generate(7), generate(8));
+
+        let mut puzzle = Puzzle::new("x".to_string(), 9);
+        puzzle.random_black(&mut StdRng::seed_from_u64(1));
+        let expected = "\
+            ▢ ▢ ▢ ▢ ▢ ▩ ▢ ▢ ▢ \n\
+            ▢ ▢ ▢ ▢ ▢ ▩ ▢ ▢ ▢ \n\
+            ▢ ▢ ▢ ▢ ▢ ▩ ▢ ▢ ▢ \n\
+            ▩ ▩ ▩ ▢ ▢ ▢ ▢ ▢ ▢ \n\
+            ▢ ▢ ▢ ▢ ▢ ▢ ▢ ▢ ▢ \n\
+            ▢ ▢ ▢ ▢ ▢ ▢ ▩ ▩ ▩ \n\
+            ▢ ▢ ▢ ▩ ▢ ▢ ▢ ▢ ▢ \n\
+            ▢ ▢ ▢ ▩ ▢ ▢ ▢ ▢ ▢ \n\
+            ▢ ▢ ▢ ▩ ▢ ▢ ▢ ▢ ▢ \n";
+        assert_eq!(puzzle.cells().to_string(), expected);
+    }
+
+    #[test]
+    fn valid_random_grid() {
+        let mut random = Puzzle::new("x".to_string(), 14);
+        random.random_black(&mut rand::thread_rng());
+        println!("{}", random.cells());
+        assert_eq!(random.validate_base(), Ok(()));
+    }
+
+    #[test]
+    fn valid_random_grid_each_symmetry() {
+        for symmetry in [
+            Symmetry::Rotational,
+            Symmetry::Horizontal,
+            Symmetry::Vertical,
+            Symmetry::Diagonal,
+        ] {
+            let mut random = Puzzle::new("x".to_string(), 14).with_symmetry(symmetry);
+            random.random_black(&mut rand::thread_rng());
+            assert_eq!(random.cells().is_symmetric(symmetry), Ok(()));
+        }
+    }
+
+    #[test]
+    fn shuffle_black() {
+        let mut puzzle = Puzzle::new("x".to_string(), 8).with_symmetry(Symmetry::Horizontal);
+        puzzle.put(1, 0, Cell::Letter('C'));
+        let before = puzzle.cells.clone();
+        let mut seed = 0;
+        while puzzle
+            .shuffle_black(&mut StdRng::seed_from_u64(seed))
+            .is_err()
+        {
+            assert_eq!(puzzle.cells, before);
+            seed += 1;
+        }
+        assert_eq!(puzzle.name, "x");
+        assert_eq!(puzzle.symmetry, Symmetry::Horizontal);
+        assert_eq!(puzzle.validate_base(), Ok(()));
+        assert!(puzzle
+            .cells
+            .iter_coords()
+            .all(|(_, _, cell)| !cell.is_letter()));
+
+        // The same seed gives the same pattern
+        let mut again = Puzzle::new("y".to_string(), 8).with_symmetry(Symmetry::Horizontal);
+        again
+            .shuffle_black(&mut StdRng::seed_from_u64(seed))
+            .unwrap();
+        assert_eq!(again.cells, puzzle.cells);
+
+        assert!(puzzle.undo());
+        assert_eq!(puzzle.cells, before);
+    }
+
+    #[test]
+    fn random_valid_base() {
+        for size in [5, 9, 15] {
+            let mut puzzle = Puzzle::new("x".to_string(), size);
+            let mut attempts = 0;
+            let result = puzzle.random_valid_base(&mut rand::thread_rng(), |attempt, max| {
+                assert_eq!(max, MAX_BASE_ATTEMPTS);
+                attempts = attempt;
+            });
+            assert_eq!(result, Ok(()));
+            assert!(attempts >= 1);
+            assert_eq!(puzzle.validate_base(), Ok(()));
+            assert!(puzzle.undo());
+            assert_eq!(puzzle.cells, Grid::new(size));
+        }
+
+        let mut too_small = Puzzle::new("x".to_string(), 2);
+        let mut attempts = 0;
+        assert_eq!(
+            too_small.random_valid_base(&mut rand::thread_rng(), |attempt, _| attempts = attempt),
+            Err(PuzzleError::NoValidBase(MAX_BASE_ATTEMPTS))
+        );
+        assert_eq!(attempts, MAX_BASE_ATTEMPTS);
+    }
+
+    #[test]
+    fn interlocked() {
+        let mut puzzle = Puzzle::new("x".to_string(), 7);
+        assert_eq!(puzzle.interlocked(), Ok(()));
+        for i in 0..7 {
+            puzzle.set(i, 6 - i, Cell::Black);
+        }
+        assert_eq!(puzzle.interlocked(), Err(PuzzleError::NotInterlocked));
+
+        // A wall across the middle passes every other check
+        let mut puzzle = Puzzle::new("x".to_string(), 7);
+        for i in 0..7 {
+            puzzle.set(i, 3, Cell::Black);
+        }
+        assert_eq!(puzzle.validate_base(), Err(PuzzleError::NotInterlocked));
+    }
+
+    #[test]
+    fn mirror_symmetry() {
+        let mut puzzle = Puzzle::new("x".to_string(), 5).with_symmetry(Symmetry::Horizontal);
+        assert_eq!(puzzle.set_symmetric((0, 1), Cell::Black), 2);
+        assert_eq!(puzzle.get(4, 1), &Cell::Black);
+        assert_eq!(puzzle.cells().is_symmetric(Symmetry::Horizontal), Ok(()));
+        assert_eq!(
+            puzzle.cells().is_symmetric(Symmetry::Vertical),
+            Err(PuzzleError::NotSymmetric(vec![
+                (0, 1),
+                (4, 1),
+                (0, 3),
+                (4, 3)
+            ]))
+        );
+        assert!(puzzle.cells().is_symmetric(Symmetry::Rotational).is_err());
+
+        let mut puzzle = Puzzle::new("x".to_string(), 5).with_symmetry(Symmetry::Diagonal);
+        assert_eq!(puzzle.set_symmetric((3, 0), Cell::Black), 2);
+        assert_eq!(puzzle.get(0, 3), &Cell::Black);
+        assert_eq!(puzzle.set_symmetric((2, 2), Cell::Black), 1);
+        assert_eq!(puzzle.cells().is_symmetric(Symmetry::Diagonal), Ok(()));
+    }
+
+    #[test]
+    fn set_symmetric_odd_sizes() {
+        for size in [5, 7] {
+            let c = size / 2;
+            let mut center = Puzzle::new("x".to_string(), size);
+            assert_eq!(center.set_symmetric((c, c), Cell::Black), 1);
+            assert_eq!(center.cells.black_square_count(), 1);
+
+            // Cells on and beside the middle row and column each have three distinct partners
+            for cell in [(c, 0), (c - 1, c), (c + 1, c - 1), (0, c)] {
+                let mut puzzle = Puzzle::new("x".to_string(), size);
+                assert_eq!(puzzle.set_symmetric(cell, Cell::Black), 4);
+                assert_eq!(puzzle.cells.black_square_count(), 4);
+                let (x, y) = cell;
+                assert_eq!(puzzle.get(size - 1 - x, size - 1 - y), &Cell::Black);
+                assert_eq!(puzzle.cells.is_symmetric(Symmetry::Rotational), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn symmetry_header() {
+        let (header, body) = Puzzle::split_header("symmetry: diagonal\n▢ ▢ ▢ \n".as_bytes());
+        assert_eq!(header.get("symmetry"), Some(&"diagonal".to_string()));
+        assert_eq!(body, "▢ ▢ ▢ \n".as_bytes());
+
+        let (header, body) = Puzzle::split_header("▢ ▢ ▢ \n".as_bytes());
+        assert!(header.is_empty());
+        assert_eq!(body, "▢ ▢ ▢ \n".as_bytes());
+    }
+
+    #[test]
+    fn max_black_percent() {
+        let mut puzzle = Puzzle::new("x".to_string(), 10).with_rules(Rules {
+            max_black_percent: 30,
+            ..Rules::default()
+        });
+        puzzle.random_black(&mut rand::thread_rng());
+        let black = puzzle.cells().count_cells(|cell| cell.is_black());
+        assert!(black <= 30);
+        assert_eq!(puzzle.validate_base(), Ok(()));
+
+        let mut corners = Puzzle::new("x".to_string(), 5);
+        corners.set_symmetric((0, 0), Cell::Black);
+        assert_eq!(corners.validate_base(), Ok(()));
+        let strict = corners.with_rules(Rules {
+            max_black_percent: 10,
+            ..Rules::default()
+        });
+        assert_eq!(
+            strict.validate_base(),
+            Err(PuzzleError::TooManyBlackSquares(10))
+        );
+    }
+
+    #[test]
+    fn valid_black_placement() {
+        let cells = Grid(vec![
+            vec![
+                Cell::Black,
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Empty,
+            ],
+            vec![
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Empty,
+            ],
+            vec![
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Empty,
+            ],
+            vec![
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Empty,
+            ],
+            vec![
+                Cell::Empty,
+                Cell::Empty,
+                Cell::Letter('B'),
+                Cell::Letter('A'),
+                Cell::Empty,
+            ],
+        ]);
+        let puzzle = Puzzle::from_grid("x".to_string(), cells);
+        assert!(puzzle.valid_black_placement((0, 1)));
+        assert!(!puzzle.valid_black_placement((1, 1)));
+        assert!(!puzzle.valid_black_placement((2, 2)));
+        assert!(!puzzle.valid_black_placement((3, 4)));
+        assert!(puzzle.valid_black_placement((4, 4)));
+    }
+
+    #[test]
+    fn preview_symmetric() {
+        let puzzle = Puzzle::new("x".to_string(), 7);
+        assert_eq!(
+            puzzle.preview_symmetric((3, 0)),
+            (vec![(0, 3), (3, 0), (3, 6), (6, 3)], true)
+        );
+        assert_eq!(puzzle.preview_symmetric((3, 3)), (vec![(3, 3)], true));
+        assert_eq!(
+            puzzle.preview_symmetric((1, 0)),
+            (vec![(0, 5), (1, 0), (5, 6), (6, 1)], false)
+        );
+        assert_eq!(puzzle.cells.black_square_count(), 0);
+    }
+
+    #[test]
+    fn parse_error_counts_header_lines() {
+        assert_eq!(
+            Puzzle::from_bytes("x".to_string(), b"symmetry: rotational\nA B \nC 1 \n"),
+            Err(PuzzleError::Grid(GridError::InvalidPuzzleFormat {
+                line: 3,
+                token: "1".to_string()
+            }))
+        );
+        assert_eq!(
+            Puzzle::from_bytes("x".to_string(), b"circled: 0;0\nA B \nC D \n"),
+            Err(PuzzleError::Grid(GridError::InvalidCoords(
+                "0;0".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn locked_cells() {
+        let mut puzzle = Puzzle::from_template("x".to_string(), "A..\n...\n...").unwrap();
+        puzzle.lock_cell(0, 0).unwrap();
+        puzzle.lock_cell(2, 2).unwrap();
+        assert_eq!(
+            puzzle.lock_cell(3, 0),
+            Err(GridError::OutOfBounds { x: 3, y: 0 })
+        );
+
+        puzzle.random_letters(&mut StdRng::seed_from_u64(0), false);
+        assert_eq!(puzzle.get(0, 0), &Cell::Letter('A'));
+        assert_eq!(puzzle.get(2, 2), &Cell::Empty);
+        assert_eq!(puzzle.empty_cells(), 1);
+        puzzle.clear_letters();
+        assert_eq!(puzzle.get(0, 0), &Cell::Letter('A'));
+        assert_eq!(puzzle.empty_cells(), 8);
+        assert_eq!(
+            puzzle.fill_across(6, "CAT"),
+            Err(PuzzleError::LockedCell((2, 2)))
+        );
+        assert_eq!(puzzle.fill_across(0, "ACT"), Ok(()));
+
+        // Locks are saved in the header and move with the cells
+        let mut reopened =
+            Puzzle::from_bytes("x".to_string(), puzzle.save_text().as_bytes()).unwrap();
+        assert!(reopened.is_locked(0, 0) && reopened.is_locked(2, 2));
+        reopened.flip(Axis::Horizontal).unwrap();
+        assert!(reopened.is_locked(2, 0) && reopened.is_locked(0, 2));
+        reopened.unlock_cell(2, 0).unwrap();
+        assert!(!reopened.is_locked(2, 0));
+    }
+
+    #[test]
+    fn load_all() {
+        let dir = std::env::temp_dir().join("crossword-builder-load-all");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "A B \nC D \n").unwrap();
+        std::fs::write(dir.join("b.txt"), "A ! \nC D \n").unwrap();
+        std::fs::write(dir.join("c.json"), "{}").unwrap();
+
+        let results = Puzzle::load_all(&dir);
+        assert_eq!(results.len(), 2);
+        let a = results[0].as_ref().unwrap();
+        assert_eq!((a.name.as_str(), a.size), ("a", 2));
+        match &results[1] {
+            Err(PuzzleError::InFile(path, _)) => assert!(path.ends_with("b.txt")),
+            other => panic!("expected an error for b.txt, got {:?}", other),
+        }
+        assert!(matches!(
+            Puzzle::load_all(&dir.join("missing"))[..],
+            [Err(PuzzleError::FileOpenError(_))]
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn check_rule() {
+        assert_eq!("1".parse(), Ok(Rule::Symmetry));
+        assert_eq!("Black-Count".parse(), Ok(Rule::BlackCount));
+        assert_eq!(
+            "6".parse::<Rule>(),
+            Err(PuzzleError::UnknownRule("6".to_string()))
+        );
+        for rule in Rule::ALL {
+            assert_eq!(rule.number().to_string().parse(), Ok(rule));
+            assert_eq!(rule.to_string().parse(), Ok(rule));
+        }
+
+        let puzzle = Puzzle::from_template("x".to_string(), "#AB\nCDE\nFGH").unwrap();
+        assert_eq!(
+            puzzle.check_rule(Rule::Symmetry),
+            Err(PuzzleError::NotSymmetric(vec![(0, 0), (2, 2)]))
+        );
+        assert_eq!(puzzle.check_rule(Rule::Interlock), Ok(()));
+        assert_eq!(puzzle.check_rule(Rule::Repeats), Ok(()));
+        assert!(matches!(
+            puzzle.check_rule(Rule::MinLength),
+            Err(PuzzleError::ShortWords(..))
+        ));
+    }
+
+    #[test]
+    fn validate_cell_edit() {
+        let mut puzzle = Puzzle::from_template("x".to_string(), "SIT\nATE\nPA.").unwrap();
+        assert_eq!(puzzle.validate_cell_edit(2, 2), vec![]);
+
+        // Only the words through the edited cell are checked, so the made-up words elsewhere aren't reported
+        puzzle.set(0, 0, Cell::Letter('Q'));
+        puzzle.set(2, 2, Cell::Letter('Q'));
+        assert_eq!(
+            puzzle.validate_cell_edit(2, 2),
+            vec![PuzzleError::MadeUpWord("PAQ, TEQ".to_string())]
+        );
+        assert_eq!(puzzle.validate_cell_edit(1, 1), vec![]);
+
+        let repeated = Puzzle::from_template("x".to_string(), "CAT\nA.A\nTAC").unwrap();
+        assert_eq!(
+            repeated.validate_cell_edit(0, 0),
+            vec![PuzzleError::RepeatedWords(vec![RepeatedWord {
+                word: "CAT".to_string(),
+                slots: vec![(1, Direction::Across, (0, 0)), (1, Direction::Down, (0, 0))],
+            }])]
+        );
+        // The repeated TAC doesn't pass through the middle of the top row
+        assert_eq!(
+            repeated.validate_cell_edit(1, 0),
+            repeated.validate_cell_edit(0, 0)
+        );
+    }
+
+    #[test]
+    fn valid_words() {
+        let cells = Grid(vec![
+            vec![Cell::Letter('S'), Cell::Letter('I'), Cell::Letter('T')],
+            vec![Cell::Letter('A'), Cell::Letter('T'), Cell::Letter('E')],
+            vec![Cell::Letter('P'), Cell::Letter('A'), Cell::Letter('N')],
+        ]);
+        let puzzle = Puzzle::from_grid("x".to_string(), cells);
+        assert_eq!(puzzle.validate_words(), Ok(()));
+    }
+
+    #[test]
+    fn made_up_words_reported_once() {
+        let cells = Grid::from_bytes("Z X Q \nX A B \nQ B C \n".as_bytes()).unwrap();
+        let puzzle = Puzzle::from_grid("x".to_string(), cells);
+        assert_eq!(
+            puzzle.valid_words(),
+            Err(PuzzleError::MadeUpWord("QBC, XAB, ZXQ".to_string()))
+        );
+    }
+
+    #[test]
+    fn mixed_case_words() {
+        let upper = Grid::from_bytes("S I T \nA T E \nP A N \n".as_bytes()).unwrap();
+        let mixed = Grid::from_bytes("s I t \nA t E \np a N \n".as_bytes()).unwrap();
+        let upper = Puzzle::from_grid("x".to_string(), upper);
+        let mixed = Puzzle::from_grid("x".to_string(), mixed);
+        assert_eq!(mixed.cells(), upper.cells());
+        assert_eq!(mixed.validate_words(), Ok(()));
+
+        let mut puzzle = Puzzle::new("x".to_string(), 3);
+        puzzle.fill_across(0, "sit").unwrap();
+        assert_eq!(puzzle.fill_down(0, "Sap"), Ok(()));
+        assert_eq!(puzzle.get(0, 2), &Cell::Letter('P'));
+    }
+
+    #[test]
+    fn suggest_skips_used_words() {
+        let mut puzzle = Puzzle::new("x".to_string(), 3);
+        puzzle.fill_across(0, "ACT").unwrap();
+        puzzle.fill_across(3, "CAT").unwrap();
+
+        let across = puzzle.suggest(6, Direction::Across, 10000).unwrap();
+        assert!(!across.is_empty());
+        assert!(!across.contains(&"act".to_string()));
+        assert!(!across.contains(&"cat".to_string()));
+        assert!(DICTIONARY.is_valid("act") && DICTIONARY.is_valid("cat"));
+        assert_eq!(
+            puzzle.suggest(9, Direction::Down, 5),
+            Err(PuzzleError::Grid(GridError::OutOfBounds { x: 0, y: 3 }))
+        );
+    }
+
+    #[test]
+    fn templates() {
+        for template in [Template::Mini, Template::Daily, Template::Sunday] {
+            let puzzle = Puzzle::from_preset("x".to_string(), template).unwrap();
+            assert_eq!(puzzle.size(), template.size());
+            assert_eq!(puzzle.base_violations(), vec![]);
+            assert_eq!(template.to_string().parse(), Ok(template));
+        }
+        assert_eq!(Template::Daily.size(), 15);
+        assert_eq!(Template::Sunday.size(), 21);
+        assert_eq!(
+            "weekly".parse::<Template>(),
+            Err(GridError::UnknownTemplate("weekly".to_string()))
+        );
+    }
+
+    #[test]
+    fn circled_cells() {
+        let mut puzzle = Puzzle::new("x".to_string(), 3);
+        puzzle.fill_across(0, "CAT").unwrap();
+        puzzle.circle_cell(0, 0).unwrap();
+        puzzle.circle_cell(2, 2).unwrap();
+        assert_eq!(
+            puzzle.circle_cell(3, 0),
+            Err(GridError::OutOfBounds { x: 3, y: 0 })
+        );
+        assert!(puzzle.is_circled(0, 0));
+        assert!(puzzle.to_string().contains("Ⓒ A T \n▢ ▢ ▢ \n▢ ▢ ◯ \n"));
+
+        // Circles don't change the grid or how it's validated
+        let plain = Puzzle::from_grid("x".to_string(), puzzle.cells.clone());
+        assert_eq!(puzzle.base_violations(), plain.base_violations());
+        assert_eq!(puzzle.word_violations(), plain.word_violations());
+
+        let json = Puzzle::from_json(&puzzle.to_json()).unwrap();
+        assert_eq!(json.circled, puzzle.circled);
+        assert!(!plain.to_json().contains("circled"));
+
+        puzzle.rotate(1).unwrap();
+        assert_eq!(puzzle.circled, BTreeSet::from([(2, 0), (0, 2)]));
+        assert_eq!(puzzle.get(2, 0), &Cell::Letter('C'));
+        puzzle.flip(Axis::Horizontal).unwrap();
+        assert_eq!(puzzle.circled, BTreeSet::from([(0, 0), (2, 2)]));
+        puzzle.resize(2, Anchor::TopLeft, true).unwrap();
+        assert_eq!(puzzle.circled, BTreeSet::from([(0, 0)]));
+        puzzle.uncircle_cell(0, 0).unwrap();
+        assert!(!puzzle.is_circled(0, 0));
+    }
+
+    #[test]
+    fn compact_save() {
+        let mut puzzle = Puzzle::from_template("x".to_string(), "CA#\n...\n#..").unwrap();
+        puzzle.circle_cell(1, 1).unwrap();
+        let spaced = puzzle.save_text();
+        let compact = puzzle.clone().with_compact(true).save_text();
+        assert!(compact.ends_with("\nCA▩\n▢▢▢\n▩▢▢\n"));
+        assert!(puzzle
+            .clone()
+            .with_compact(true)
+            .with_ascii(true)
+            .save_text()
+            .ends_with("\nCA#\n...\n#..\n"));
+        for text in [spaced, compact] {
+            let reopened = Puzzle::from_bytes("x".to_string(), text.as_bytes()).unwrap();
+            assert_eq!(reopened.cells, puzzle.cells);
+            assert!(reopened.is_circled(1, 1));
+        }
+    }
+
+    #[test]
+    fn barred_grid() {
+        let mut puzzle = Puzzle::new("x".to_string(), 4);
+        puzzle.add_bar(1, 0, Direction::Across).unwrap();
+        puzzle.add_bar(0, 1, Direction::Down).unwrap();
+        assert_eq!(
+            puzzle.add_bar(3, 0, Direction::Across),
+            Err(GridError::OutOfBounds { x: 4, y: 0 })
+        );
+        assert!(puzzle.is_barred());
+
+        // Bars split the first row and column just as black squares would
+        assert_eq!(puzzle.segments_across().count(), 5);
+        assert_eq!(puzzle.segments_down().count(), 5);
+        assert_eq!(puzzle.get_across_word(0).unwrap().len(), 2);
+        assert_eq!(puzzle.get_down_word(0).unwrap().len(), 2);
+        let word = puzzle.word_through(3, 0, Direction::Across).unwrap();
+        assert_eq!((word.start, word.len), ((2, 0), 2));
+        assert_eq!(
+            puzzle.fill_across(0, "CAT"),
+            Err(PuzzleError::WrongLength("CAT".to_string(), 2))
+        );
+        puzzle.fill_across(0, "AT").unwrap();
+        assert_eq!(puzzle.get(2, 0), &Cell::Empty);
+        assert_eq!(puzzle.to_svg().matches("<line").count(), 2);
+
+        // Bars are saved in the header, and turn with the cells on either side of them
+        assert!(puzzle
+            .save_text()
+            .contains("bars-across: 1,0\nbars-down: 0,1\n"));
+        let mut reopened =
+            Puzzle::from_bytes("x".to_string(), puzzle.save_text().as_bytes()).unwrap();
+        assert_eq!(reopened.bars, puzzle.bars);
+        assert_eq!(
+            Puzzle::from_json(&puzzle.to_json()).unwrap().bars,
+            puzzle.bars
+        );
+        reopened.rotate(1).unwrap();
+        assert_eq!(
+            reopened.bars,
+            BTreeSet::from([((1, 0), Direction::Across), ((3, 1), Direction::Down)])
+        );
+        reopened.resize(3, Anchor::TopLeft, true).unwrap();
+        assert_eq!(reopened.bars, BTreeSet::from([((1, 0), Direction::Across)]));
+        reopened.remove_bar(1, 0, Direction::Across).unwrap();
+        assert!(!reopened.is_barred());
+        assert!(!reopened.to_json().contains("bars"));
+    }
+
+    #[test]
+    fn clear_letters() {
+        let cells = Grid::from_bytes("A ▩ [CAT] \n▢ B ▢ \n▩ ▢ C \n".as_bytes()).unwrap();
+        let mut puzzle = Puzzle::from_grid("x".to_string(), cells);
+        puzzle.clear_letters();
+        let expected = Grid::from_bytes("▢ ▩ ▢ \n▢ ▢ ▢ \n▩ ▢ ▢ \n".as_bytes()).unwrap();
+        assert_eq!(puzzle.cells, expected);
+        assert_eq!(puzzle.transpose, expected.transpose());
+        assert!(puzzle.undo());
+        assert_eq!(puzzle.get(2, 0), &Cell::Rebus("CAT".to_string()));
+    }
+
+    #[test]
+    fn zero_size() {
+        let mut puzzle = Puzzle::new("x".to_string(), 0);
+        assert_eq!(puzzle.size(), 0);
+        assert_eq!(puzzle.all_words_iter().count(), 0);
+        assert!(puzzle.is_complete());
+        puzzle.validate_base().unwrap();
+        puzzle.validate_words().unwrap();
+        puzzle.random_letters(&mut StdRng::seed_from_u64(1), false);
+        let _ = puzzle.stats().to_string();
+        let _ = puzzle.to_string();
+        let _ = puzzle.to_svg();
+        assert!(puzzle.index_coords(0).is_err());
+
+        let from_file = Puzzle::from_grid("x".to_string(), Grid::from_bytes(b"").unwrap());
+        assert_eq!(from_file.cells, puzzle.cells);
+        assert_eq!(from_file.transpose, puzzle.transpose);
+        assert_eq!(Puzzle::from_json(&puzzle.to_json()).unwrap().size(), 0);
+    }
+
+    #[test]
+    fn suggest_constrained() {
+        let puzzle = Puzzle::from_template("x".to_string(), "...\nQQQ\nQQQ").unwrap();
+        assert!(!puzzle.suggest(0, Direction::Across, 5).unwrap().is_empty());
+        assert_eq!(
+            puzzle.suggest_constrained(0, Direction::Across, 5),
+            Ok(vec![])
+        );
+
+        let puzzle = Puzzle::from_template("x".to_string(), "...\n...\n...").unwrap();
+        let suggestions = puzzle.suggest_constrained(0, Direction::Down, 5).unwrap();
+        assert_eq!(suggestions.len(), 5);
+        let used = puzzle.used_words();
+        for word in suggestions {
+            let mut trial = puzzle.clone();
+            trial.fill_down(0, &word).unwrap();
+            assert!(trial
+                .crossing_fits(&DICTIONARY, 0, Direction::Down, &used, 1)
+                .iter()
+                .all(|&fits| fits > 0));
+        }
+    }
+
+    #[test]
+    fn density_map() {
+        let puzzle = Puzzle::from_template("x".to_string(), "..Q\n...\n#..").unwrap();
+        let map = puzzle.density_map();
+        assert_eq!(map[0][2], None);
+        assert_eq!(map[2][0], None);
+        let (Some(corner), Some(middle)) = (map[0][0], map[1][1]) else {
+            panic!("empty cells should have counts");
+        };
+        let across = puzzle
+            .suggest(0, Direction::Across, usize::MAX)
+            .unwrap()
+            .len();
+        assert!(corner <= across);
+        assert!(middle > 0);
+    }
+
+    #[test]
+    fn random_black_count() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for target in [4, 8, 9, 12] {
+            let mut puzzle = Puzzle::new("x".to_string(), 9);
+            puzzle.random_black_count(&mut rng, target).unwrap();
+            assert_eq!(puzzle.cells().black_square_count(), target);
+            assert_eq!(puzzle.cells().is_symmetric(Symmetry::Rotational), Ok(()));
+        }
+
+        let mut puzzle = Puzzle::new("x".to_string(), 9);
+        assert_eq!(
+            puzzle.random_black_count(&mut rng, 13),
+            Err(PuzzleError::TooManyBlackSquares(PERCENT_BLACK))
+        );
+        // Rotational partners come in fours, apart from the center square
+        assert_eq!(
+            puzzle.random_black_count(&mut rng, 2),
+            Err(PuzzleError::NoBlackPlacement(2, MAX_BASE_ATTEMPTS))
+        );
+    }
+
+    #[test]
+    fn swap_symmetric() {
+        let mut puzzle =
+            Puzzle::from_template("x".to_string(), ".....\n#...A\n.....\nB...#\n.#...").unwrap();
+        puzzle.set(3, 0, Cell::Black);
+        puzzle.set(1, 4, Cell::Black);
+        puzzle.swap_symmetric((0, 1), (1, 0)).unwrap();
+        for (x, y) in [(0, 1), (3, 0), (4, 3), (1, 4)] {
+            assert_ne!(puzzle.get(x, y), &Cell::Black);
+        }
+        for (x, y) in [(1, 0), (4, 1), (3, 4), (0, 3)] {
+            assert_eq!(puzzle.get(x, y), &Cell::Black);
+        }
+        // The letters moved along with the transformation that relates their cells
+        assert_eq!(puzzle.get(1, 4), &Cell::Letter('B'));
+        assert_eq!(puzzle.get(3, 0), &Cell::Letter('A'));
+        assert_eq!(puzzle.cells().is_symmetric(Symmetry::Rotational), Ok(()));
+        assert_eq!(puzzle.transpose, puzzle.cells.transpose());
+
+        assert_eq!(
+            puzzle.swap_symmetric((2, 2), (0, 1)),
+            Err(PuzzleError::UnswappableCells((2, 2), (0, 1)))
+        );
+        assert_eq!(
+            puzzle.swap_symmetric((0, 1), (3, 0)),
+            Err(PuzzleError::UnswappableCells((0, 1), (3, 0)))
+        );
+        assert!(puzzle.swap_symmetric((0, 0), (5, 0)).is_err());
+    }
+
+    #[test]
+    fn apply_fills() {
+        let mut puzzle = Puzzle::new("x".to_string(), 3);
+        let results = puzzle.apply_fills(&[
+            (0, Direction::Across, "cat".to_string()),
+            (0, Direction::Down, "dog".to_string()),
+            (0, Direction::Down, "cow".to_string()),
+            (1, Direction::Down, "ax".to_string()),
+            (2, Direction::Down, "toe".to_string()),
+        ]);
+        assert_eq!(
+            results,
+            vec![
+                Ok(()),
+                Err(PuzzleError::ConflictingLetter((0, 0), 'C', 'D')),
+                Ok(()),
+                Err(PuzzleError::WrongLength("ax".to_string(), 3)),
+                Ok(()),
+            ]
+        );
+        assert_eq!(Cell::as_string(puzzle.cells.get_row(0)), "CAT");
+        assert_eq!(Cell::as_string(puzzle.transpose.get_row(0)), "COW");
+        assert_eq!(Cell::as_string(puzzle.transpose.get_row(2)), "TOE");
+    }
+
+    #[test]
+    fn slots_through() {
+        let puzzle = Puzzle::from_template("x".to_string(), "#..\n...\n..#").unwrap();
+        let starts = |(across, down): (Option<Word>, Option<Word>)| {
+            (across.map(|word| word.start), down.map(|word| word.start))
+        };
+        assert_eq!(
+            starts(puzzle.slots_through(1, 1)),
+            (Some((0, 1)), Some((1, 0)))
+        );
+        assert_eq!(
+            starts(puzzle.slots_through(2, 0)),
+            (Some((1, 0)), Some((2, 0)))
+        );
+        assert_eq!(starts(puzzle.slots_through(0, 0)), (None, None));
+        assert_eq!(starts(puzzle.slots_through(5, 5)), (None, None));
+
+        let boxed = Puzzle::from_template("x".to_string(), "#.#\n...\n#.#").unwrap();
+        assert_eq!(starts(boxed.slots_through(1, 0)), (None, Some((1, 0))));
+    }
+
+    #[test]
+    fn pangram() {
+        let mut puzzle = Puzzle::new("x".to_string(), 4);
+        assert_eq!(puzzle.missing_letters().len(), 26);
+        puzzle.random_letters(&mut StdRng::seed_from_u64(3), true);
+        assert!(!puzzle.is_pangram());
+
+        let mut puzzle = Puzzle::new("x".to_string(), 6);
+        puzzle.set(0, 0, Cell::Rebus("XYZ".to_string()));
+        puzzle.random_letters(&mut StdRng::seed_from_u64(3), true);
+        assert!(puzzle.is_pangram());
+
+        // With no crossings to weigh, the plain pick is the first candidate and the pangram pick the one adding the
+        // most new letters
+        let distinct = |word: &str| {
+            word.chars()
+                .collect::<std::collections::HashSet<char>>()
+                .len()
+        };
+        let puzzle = Puzzle::from_template("x".to_string(), "....\n####\n####\n####").unwrap();
+        let plain = puzzle
+            .clone()
+            .autofill_slot(0, Direction::Across, false)
+            .unwrap();
+        let pangram = puzzle
+            .clone()
+            .autofill_slot(0, Direction::Across, true)
+            .unwrap();
+        assert_eq!(distinct(&pangram), 4);
+        assert!(distinct(&pangram) >= distinct(&plain));
+    }
+
+    #[test]
+    fn solve() {
+        let mut puzzle = Puzzle::from_template("x".to_string(), "C..\n...\n..#").unwrap();
+        assert_eq!(puzzle.solve(), SolveOutcome::Complete);
+        assert!(puzzle.is_complete());
+        assert_eq!(puzzle.get(0, 0), &Cell::Letter('C'));
+        assert_eq!(puzzle.valid_words(), Ok(()));
+        assert_eq!(puzzle.no_repeat_words(), Ok(()));
+        assert!(puzzle.undo());
+        assert_eq!(puzzle.empty_cells(), 7);
+
+        // Out of time before the first step, so the best fill is the one already there
+        assert_eq!(
+            puzzle.solve_with_budget(Instant::now()),
+            SolveOutcome::Partial(7)
+        );
+        assert_eq!(puzzle.empty_cells(), 7);
+        let far_off = Instant::now() + Duration::from_secs(600);
+        assert_eq!(puzzle.solve_with_budget(far_off), SolveOutcome::Complete);
+
+        let mut puzzle = Puzzle::from_template("x".to_string(), "QXZ\n...\n...").unwrap();
+        assert_eq!(puzzle.solve(), SolveOutcome::Unsatisfiable);
+        assert_eq!(puzzle.empty_cells(), 6);
+    }
+
+    #[test]
+    fn autofill_slot() {
+        let dictionary = Dictionary::from_reader("ant\nbag\nget\nhog\nhow\nore\nwet".as_bytes());
+        let mut puzzle = Puzzle::from_template("x".to_string(), "...\nORE\nWET").unwrap();
+        let used = puzzle.used_words();
+
+        // "ant" is the first word to fit, but leaves no word for any crossing, and "bag" none for two of them
+        let word = puzzle
+            .autofill_slot_from(&dictionary, 0, Direction::Across, false)
+            .unwrap();
+        assert_eq!(word, "hog");
+        assert_eq!(Cell::as_string(puzzle.cells.get_row(0)), "HOG");
+        assert_eq!(
+            puzzle.crossing_score(&dictionary, 0, Direction::Across, &used),
+            (0, -3)
+        );
+
+        let mut trial = puzzle.clone();
+        trial.undo();
+        trial.fill_across(0, "ant").unwrap();
+        assert_eq!(
+            trial.crossing_score(&dictionary, 0, Direction::Across, &used),
+            (3, 0)
+        );
+
+        let cells = Grid::from_bytes("Q Q ▢ \n▢ ▢ ▢ \n▢ ▢ ▢ \n".as_bytes()).unwrap();
+        let mut puzzle = Puzzle::from_grid("x".to_string(), cells);
+        assert_eq!(
+            puzzle.autofill_slot(0, Direction::Across, false),
+            Err(PuzzleError::NoCandidates(Direction::Across, 0))
+        );
+        assert_eq!(Cell::as_string(puzzle.cells.get_row(0)), "QQ_");
+    }
+
+    #[test]
+    fn unkeyed_letters() {
+        let mut puzzle = Puzzle::new("x".to_string(), 5);
+        assert_eq!(puzzle.no_unkeyed_letters(), Ok(()));
+
+        puzzle.set(0, 2, Cell::Black);
+        puzzle.set(1, 2, Cell::Black);
+        puzzle.set(3, 2, Cell::Black);
+        puzzle.set(4, 2, Cell::Black);
+        assert_eq!(
+            puzzle.no_unkeyed_letters(),
+            Err(PuzzleError::UnkeyedLetter((2, 2)))
+        );
+        assert_eq!(
+            puzzle.validate_base(),
+            Err(PuzzleError::UnkeyedLetter((2, 2)))
+        );
+    }
+
+    #[test]
+    fn suggest_keying_fixes() {
+        let puzzle = Puzzle::from_template("x".to_string(), "....\n....\n....\n....").unwrap();
+        assert_eq!(puzzle.suggest_keying_fixes(), vec![]);
+
+        // The center is boxed in across, and opening up the square on either side of it opens its partner too
+        let puzzle =
+            Puzzle::from_template("x".to_string(), ".....\n.....\n##.##\n.....\n.....").unwrap();
+        assert_eq!(
+            puzzle.no_unkeyed_letters(),
+            Err(PuzzleError::UnkeyedLetter((2, 2)))
+        );
+        assert_eq!(puzzle.suggest_keying_fixes(), vec![(1, 2), (3, 2)]);
+        let mut fixed = puzzle.clone();
+        fixed.set_symmetric((1, 2), Cell::Empty);
+        assert_eq!(fixed.get(3, 2), &Cell::Empty);
+        assert_eq!(fixed.no_unkeyed_letters(), Ok(()));
+        assert_eq!(fixed.suggest_keying_fixes(), vec![]);
+    }
+
+    #[test]
+    fn words_too_short() {
+        let cells = Grid(vec![
+            vec![Cell::Letter('S'), Cell::Letter('I'), Cell::Letter('T')],
+            vec![Cell::Letter('A'), Cell::Black, Cell::Letter('E')],
+            vec![Cell::Letter('P'), Cell::Letter('U'), Cell::Letter('N')],
+        ]);
+        let puzzle = Puzzle::from_grid("x".to_string(), cells);
+        let short_words = vec![
+            ("A".to_string(), (0, 1), Direction::Across),
+            ("E".to_string(), (2, 1), Direction::Across),
+            ("I".to_string(), (1, 0), Direction::Down),
+            ("U".to_string(), (1, 2), Direction::Down),
+        ];
+        assert_eq!(puzzle.short_words(), short_words);
+        assert_eq!(
+            puzzle.validate_words(),
+            Err(PuzzleError::ShortWords(short_words, 3))
+        );
+        assert_eq!(
+            puzzle.validate_words().unwrap_err().to_string(),
+            "These words are shorter than 3 letters: \"A\" (across at (0, 1)), \"E\" (across at (2, 1)), \
+             \"I\" (down at (1, 0)), \"U\" (down at (1, 2))"
+        );
     }
 
     #[test]
-    fn valid_black_placement() {
-        let cells = Grid(vec![
+    fn min_word_len() {
+        let rules = |min_word_len| Rules {
+            min_word_len,
+            ..Rules::default()
+        };
+        let cells =
+            Grid::from_bytes("▩ ▢ ▢ ▩ \n▢ ▢ ▢ ▢ \n▢ ▢ ▢ ▢ \n▩ ▢ ▢ ▩ \n".as_bytes()).unwrap();
+        let mut puzzle = Puzzle::from_grid("x".to_string(), cells);
+        assert_eq!(puzzle.short_words().len(), 4);
+        // Length counts cells, not the letters or bytes in them
+        puzzle.set(1, 0, Cell::Letter('É'));
+        puzzle.set(2, 0, Cell::Letter('É'));
+        puzzle.set(1, 3, Cell::Rebus("STAR".to_string()));
+        assert_eq!(puzzle.short_words().len(), 4);
+        let puzzle = puzzle.with_rules(rules(2));
+        assert_eq!(puzzle.no_too_short_words(), Ok(()));
+
+        let mut corners = Puzzle::new("x".to_string(), 5);
+        corners.set_symmetric((0, 0), Cell::Black);
+        assert_eq!(corners.validate_base(), Ok(()));
+        let corners = corners.with_rules(rules(4));
+        assert_eq!(corners.short_words().len(), 4);
+        assert!(matches!(
+            corners.validate_base(),
+            Err(PuzzleError::ShortWords(_, 4))
+        ));
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut two_letter_words = 0;
+        for _ in 0..20 {
+            let mut loose = Puzzle::new("x".to_string(), 8).with_rules(rules(2));
+            loose.random_black(&mut rng);
+            assert_eq!(loose.short_words(), vec![]);
+            two_letter_words += loose.all_words_iter().filter(|word| word.len == 2).count();
+
+            let mut strict = Puzzle::new("x".to_string(), 10).with_rules(rules(4));
+            strict.random_black(&mut rng);
+            assert_eq!(strict.short_words(), vec![]);
+        }
+        assert!(two_letter_words > 0);
+    }
+
+    #[test]
+    fn crossing_conflicts() {
+        let mut puzzle = Puzzle::new("x".to_string(), 3);
+        puzzle.fill_across(0, "CAT").unwrap();
+        let before = puzzle.cells.clone();
+
+        assert_eq!(
+            puzzle.fill_down(0, "DOG"),
+            Err(PuzzleError::ConflictingLetter((0, 0), 'C', 'D'))
+        );
+        assert_eq!(puzzle.cells, before);
+        assert_eq!(puzzle.fill_down(0, "COG"), Ok(()));
+        assert_eq!(puzzle.get(0, 2), &Cell::Letter('G'));
+    }
+
+    #[test]
+    fn fill_words() {
+        let mut puzzle = Puzzle::from_template("x".to_string(), "#...\n....\n....\n...#").unwrap();
+
+        assert_eq!(puzzle.fill_across(1, "cat"), Ok(()));
+        assert_eq!(puzzle.fill_down(2, "Axes"), Ok(()));
+        assert_eq!(puzzle.transpose, puzzle.cells.transpose());
+        assert_eq!(
+            puzzle.get_down_word(2),
+            Some(SparseWord::new(vec![
+                Some('A'),
+                Some('X'),
+                Some('E'),
+                Some('S')
+            ]))
+        );
+
+        assert_eq!(
+            puzzle.fill_across(0, "cat"),
+            Err(PuzzleError::NoWord(Direction::Across, 0))
+        );
+        assert_eq!(
+            puzzle.fill_down(1, "AXE"),
+            Err(PuzzleError::WrongLength("AXE".to_string(), 4))
+        );
+        assert_eq!(
+            puzzle.fill_down(3, "BIRD"),
+            Err(PuzzleError::WrongLength("BIRD".to_string(), 3))
+        );
+        assert_eq!(
+            puzzle.fill_across(9, "RAP"),
+            Err(PuzzleError::ConflictingLetter((2, 2), 'E', 'A'))
+        );
+        assert_eq!(puzzle.get(1, 2), &Cell::Empty);
+    }
+
+    #[test]
+    fn index_out_of_bounds() {
+        let mut puzzle = Puzzle::new("x".to_string(), 4);
+        assert_eq!(puzzle.index_coords(15), Ok((3, 3)));
+        assert_eq!(
+            puzzle.index_coords(16),
+            Err(GridError::OutOfBounds { x: 0, y: 4 })
+        );
+        assert!(puzzle.get_across_word(15).is_some());
+        assert_eq!(puzzle.get_across_word(16), None);
+        assert_eq!(puzzle.get_down_word(16), None);
+        assert_eq!(
+            puzzle.fill_down(16, "CAT"),
+            Err(PuzzleError::Grid(GridError::OutOfBounds { x: 0, y: 4 }))
+        );
+    }
+
+    #[test]
+    fn resize() {
+        let mut puzzle = Puzzle::new("x".to_string(), 3);
+        puzzle.set(0, 0, Cell::Black);
+        puzzle.set(2, 2, Cell::Letter('Z'));
+
+        assert_eq!(puzzle.resize(5, Anchor::TopLeft, false), Ok(0));
+        assert_eq!(puzzle.size(), 5);
+        assert_eq!(puzzle.get(0, 0), &Cell::Black);
+        assert_eq!(puzzle.get(2, 2), &Cell::Letter('Z'));
+        assert_eq!(puzzle.get(4, 4), &Cell::Empty);
+        assert_eq!(puzzle.transpose, puzzle.cells.transpose());
+
+        assert_eq!(
+            puzzle.resize(2, Anchor::TopLeft, false),
+            Err(PuzzleError::WouldLoseCells(1))
+        );
+        assert_eq!(puzzle.size(), 5);
+        assert_eq!(puzzle.resize(3, Anchor::TopLeft, false), Ok(0));
+
+        assert_eq!(puzzle.resize(7, Anchor::Center, false), Ok(0));
+        assert_eq!(puzzle.get(2, 2), &Cell::Black);
+        assert_eq!(puzzle.get(4, 4), &Cell::Letter('Z'));
+        assert_eq!(puzzle.resize(5, Anchor::Center, false), Ok(0));
+        assert_eq!(puzzle.get(1, 1), &Cell::Black);
+        assert_eq!(puzzle.get(3, 3), &Cell::Letter('Z'));
+        assert_eq!(puzzle.resize(2, Anchor::Center, true), Ok(1));
+        assert_eq!(puzzle.get(0, 0), &Cell::Black);
+        assert_eq!(puzzle.get(1, 1), &Cell::Empty);
+
+        assert!(puzzle.undo());
+        assert_eq!(puzzle.size(), 5);
+        assert_eq!(puzzle.transpose, puzzle.cells.transpose());
+
+        // Shrinking to nothing loses every letter and black square, and leaves the same grid as a new empty puzzle
+        assert_eq!(
+            puzzle.resize(0, Anchor::Center, false),
+            Err(PuzzleError::WouldLoseCells(2))
+        );
+        assert_eq!(puzzle.resize(0, Anchor::Center, true), Ok(2));
+        assert_eq!(puzzle.size(), 0);
+        assert_eq!(puzzle.cells, Puzzle::new("x".to_string(), 0).cells);
+        assert_eq!(puzzle.resize(2, Anchor::TopLeft, false), Ok(0));
+        assert_eq!(puzzle.get(1, 1), &Cell::Empty);
+    }
+
+    #[test]
+    fn trim_borders() {
+        let mut puzzle =
+            Puzzle::from_template("x".to_string(), "#####\n#...#\n#.#.#\n#...#\n#####").unwrap();
+        puzzle.circle_cell(1, 1).unwrap();
+        puzzle.circle_cell(0, 0).unwrap();
+        assert_eq!(puzzle.trim_borders(), Ok(4));
+        assert_eq!(puzzle.size(), 3);
+        assert_eq!(puzzle.get(1, 1), &Cell::Black);
+        assert_eq!(puzzle.transpose, puzzle.cells.transpose());
+        assert!(puzzle.is_circled(0, 0));
+        assert_eq!(puzzle.circled.len(), 1);
+        assert_eq!(puzzle.trim_borders(), Ok(0));
+        assert!(puzzle.undo());
+        assert_eq!(puzzle.size(), 5);
+
+        // Black only on the top and bottom would leave a grid that isn't square
+        let mut puzzle =
+            Puzzle::from_template("x".to_string(), "#####\n.....\n.....\n.....\n#####").unwrap();
+        let before = puzzle.cells.clone();
+        assert!(matches!(
+            puzzle.trim_borders(),
+            Err(PuzzleError::Grid(GridError::NotSquare {
+                rows: 3,
+                columns: 5
+            }))
+        ));
+        assert_eq!(puzzle.cells, before);
+
+        // Trimming one side only breaks the symmetry
+        let mut puzzle = Puzzle::from_template("x".to_string(), "####\n#..#\n#...\n#...").unwrap();
+        assert!(matches!(
+            puzzle.trim_borders(),
+            Err(PuzzleError::NotSymmetric(_))
+        ));
+        assert_eq!(puzzle.size(), 4);
+
+        let mut puzzle = Puzzle::from_template("x".to_string(), "##\n##").unwrap();
+        assert_eq!(puzzle.trim_borders(), Ok(0));
+    }
+
+    #[test]
+    fn complete() {
+        let mut puzzle = Puzzle::new("mini".to_string(), 3);
+        assert!(!puzzle.is_complete());
+        assert_eq!(puzzle.empty_cells(), 9);
+        assert!(format!("{}", puzzle).ends_with("Incomplete: 9 empty cell(s)"));
+
+        puzzle.fill_across(0, "CAT").unwrap();
+        puzzle.fill_across(3, "ARE").unwrap();
+        puzzle.set(0, 2, Cell::Black);
+        puzzle.fill_across(7, "RE").unwrap();
+        assert!(puzzle.is_complete());
+        assert_eq!(
+            format!("{}", puzzle),
+            "mini (3x3, rotational symmetry)\nC A T \nA R E \n▩ R E \nComplete"
+        );
+    }
+
+    #[test]
+    fn rebus_words() {
+        let cells = Grid::from_bytes("S [CAT] T E R \n".as_bytes()).unwrap();
+        let mut cells = cells.0;
+        cells.extend((0..4).map(|_| vec![Cell::Empty; 5]));
+        let mut puzzle = Puzzle::from_grid("x".to_string(), Grid(cells));
+        assert_eq!(
+            puzzle.get_across_word(0),
+            Some(SparseWord::new("SCATTER".chars().map(Some).collect()))
+        );
+
+        assert_eq!(puzzle.fill_down(1, "CATHODE"), Ok(()));
+        assert_eq!(puzzle.get(1, 4), &Cell::Letter('E'));
+        assert_eq!(
+            puzzle.fill_down(1, "CATTLE"),
+            Err(PuzzleError::WrongLength("CATTLE".to_string(), 7))
+        );
+        assert_eq!(
+            puzzle.fill_across(0, "SCOTTER"),
+            Err(PuzzleError::ConflictingLetter((1, 0), 'A', 'O'))
+        );
+        assert_eq!(puzzle.fill_across(0, "scatter"), Ok(()));
+        assert_eq!(puzzle.get(1, 0), &Cell::Rebus("CAT".to_string()));
+    }
+
+    #[test]
+    fn transform() {
+        let mut puzzle = Puzzle::new("x".to_string(), 5).with_symmetry(Symmetry::Horizontal);
+        puzzle.set_symmetric((0, 1), Cell::Black);
+        puzzle.fill_across(0, "SCATS").unwrap();
+
+        puzzle.rotate(1).unwrap();
+        assert_eq!(puzzle.symmetry(), Symmetry::Vertical);
+        assert_eq!(puzzle.cells().is_symmetric(Symmetry::Vertical), Ok(()));
+        assert_eq!(
+            puzzle.get_down_word(4),
+            Some(SparseWord::new("SCATS".chars().map(Some).collect()))
+        );
+        assert_eq!(puzzle.get(3, 0), &Cell::Black);
+        assert_eq!(puzzle.transpose, puzzle.cells.transpose());
+
+        puzzle.flip(Axis::Horizontal).unwrap();
+        assert_eq!(puzzle.get(1, 0), &Cell::Black);
+        assert_eq!(puzzle.get(0, 0), &Cell::Letter('S'));
+        assert!(puzzle.undo());
+        assert!(puzzle.undo());
+        assert_eq!(puzzle.get(0, 1), &Cell::Black);
+    }
+
+    #[test]
+    fn transform_diagonal() {
+        let mut puzzle = Puzzle::new("x".to_string(), 5).with_symmetry(Symmetry::Diagonal);
+        puzzle.set_symmetric((1, 0), Cell::Black);
+        let cells = puzzle.cells.clone();
+        assert_eq!(puzzle.rotate(1), Err(PuzzleError::OffDiagonal));
+        assert_eq!(puzzle.flip(Axis::Vertical), Err(PuzzleError::OffDiagonal));
+        assert_eq!(puzzle.cells, cells);
+        assert!(puzzle.undo());
+        assert!(!puzzle.undo());
+        puzzle.set_symmetric((1, 0), Cell::Black);
+
+        // A half turn keeps the pattern on the same diagonal, and one symmetric across both can turn any way
+        puzzle.rotate(2).unwrap();
+        assert_eq!(puzzle.symmetric(), Ok(()));
+        let mut both = Puzzle::new("x".to_string(), 5).with_symmetry(Symmetry::Diagonal);
+        both.set_symmetric((2, 2), Cell::Black);
+        both.rotate(1).unwrap();
+        assert_eq!(both.get(2, 2), &Cell::Black);
+    }
+
+    #[test]
+    fn repeated_words() {
+        // CAT reads across the top and down the left, and TAC across the bottom and down the right
+        let puzzle = Puzzle::from_template("x".to_string(), "CAT\nA.A\nTAC").unwrap();
+        assert_eq!(
+            puzzle.repeated_words(),
             vec![
-                Cell::Black,
-                Cell::Empty,
-                Cell::Empty,
-                Cell::Empty,
-                Cell::Empty,
-            ],
+                RepeatedWord {
+                    word: "CAT".to_string(),
+                    slots: vec![(1, Direction::Across, (0, 0)), (1, Direction::Down, (0, 0))]
+                },
+                RepeatedWord {
+                    word: "TAC".to_string(),
+                    slots: vec![(5, Direction::Across, (0, 2)), (3, Direction::Down, (2, 0))]
+                }
+            ]
+        );
+        assert_eq!(
+            puzzle.no_repeat_words().unwrap_err().to_string(),
+            "These words are repeated: \"CAT\" at 1 across (0, 0) and 1 down (0, 0); \"TAC\" at 5 across (0, 2) \
+             and 3 down (2, 0)"
+        );
+
+        // Slots with empty cells aren't words yet, so they can't repeat
+        let puzzle = Puzzle::new("x".to_string(), 3);
+        assert_eq!(puzzle.no_repeat_words(), Ok(()));
+    }
+
+    #[test]
+    fn all_violations() {
+        let cells = Grid::from_bytes("S I T \nA ▩ E \nS I T \n".as_bytes()).unwrap();
+        let puzzle = Puzzle::from_grid("x".to_string(), cells);
+        let base = puzzle.base_violations();
+        assert_eq!(base.len(), 2);
+        assert_eq!(base[0], PuzzleError::UnkeyedLetter((1, 0)));
+        assert!(matches!(base[1], PuzzleError::ShortWords(..)));
+        assert_eq!(
+            puzzle.word_violations(),
             vec![
-                Cell::Empty,
-                Cell::Empty,
-                Cell::Empty,
-                Cell::Empty,
-                Cell::Empty,
-            ],
+                PuzzleError::RepeatedWords(vec![RepeatedWord {
+                    word: "SIT".to_string(),
+                    slots: vec![
+                        (1, Direction::Across, (0, 0)),
+                        (3, Direction::Across, (0, 2))
+                    ]
+                }]),
+                PuzzleError::MadeUpWord("SAS".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn place_theme() {
+        let mut puzzle = Puzzle::new("x".to_string(), 7);
+        assert_eq!(puzzle.place_theme("CAT", 0, Direction::Across), Ok(()));
+        assert_eq!(puzzle.get(3, 0), &Cell::Black);
+        assert_eq!(puzzle.get(3, 6), &Cell::Black);
+        assert_eq!(puzzle.cells().is_symmetric(Symmetry::Rotational), Ok(()));
+        assert_eq!(puzzle.slot_coords(46, Direction::Across).len(), 3);
+        assert!(puzzle.undo());
+        assert_eq!(puzzle.cells, Grid::new(7));
+
+        // A theme entry in the middle of a row is bounded on both sides
+        assert_eq!(puzzle.place_theme("DOG", 23, Direction::Across), Ok(()));
+        assert_eq!(puzzle.get(1, 3), &Cell::Black);
+        assert_eq!(puzzle.get(5, 3), &Cell::Black);
+        assert_eq!(puzzle.get(3, 3), &Cell::Letter('O'));
+
+        let mut asymmetric = Puzzle::new("x".to_string(), 7);
+        asymmetric.set(5, 6, Cell::Black);
+        assert_eq!(
+            asymmetric.place_theme("CAT", 0, Direction::Across),
+            Err(PuzzleError::NoSymmetricSlot(3))
+        );
+        assert_eq!(asymmetric.get(0, 0), &Cell::Empty);
+        assert_eq!(
+            asymmetric.place_theme("ELEPHANTS", 0, Direction::Down),
+            Err(PuzzleError::WrongLength("ELEPHANTS".to_string(), 7))
+        );
+    }
+
+    #[test]
+    fn unfillable_slots() {
+        let puzzle = Puzzle::from_template("x".to_string(), "XQ.\n...\nIEN").unwrap();
+        assert_eq!(
+            puzzle.unfillable_slots(),
+            vec![(0, Direction::Across), (1, Direction::Down)]
+        );
+
+        let puzzle = Puzzle::new("x".to_string(), 5);
+        assert!(puzzle.unfillable_slots().is_empty());
+    }
+
+    #[test]
+    fn stats() {
+        let puzzle =
+            Puzzle::from_template("x".to_string(), "##AT.\n.....\n.....\n.....\n...##").unwrap();
+        let stats = puzzle.stats();
+        assert_eq!(stats.total_words, 10);
+        assert_eq!(
+            stats.words_by_length.into_iter().collect::<Vec<_>>(),
+            vec![(3, 2), (4, 4), (5, 4)]
+        );
+        assert_eq!(stats.average_word_length, 4.2);
+        assert_eq!(stats.black_percent, 16.0);
+        // Of each pair of corner squares, removing the inner one just lengthens two words
+        assert_eq!(stats.cheater_squares, 2);
+        assert_eq!(stats.empty_cells, 19);
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut puzzle = Puzzle::new("x".to_string(), 5);
+        let blank = puzzle.cells().clone();
+        assert!(!puzzle.undo());
+
+        puzzle.set_symmetric((0, 0), Cell::Black);
+        let corners = puzzle.cells().clone();
+        assert_eq!(puzzle.fill_across(1, "CAT"), Ok(()));
+        let filled = puzzle.cells().clone();
+
+        assert!(puzzle.undo());
+        assert_eq!(puzzle.cells(), &corners);
+        assert_eq!(puzzle.transpose, corners.transpose());
+        assert!(puzzle.undo());
+        assert_eq!(puzzle.cells(), &blank);
+        assert!(!puzzle.undo());
+
+        assert!(puzzle.redo());
+        assert!(puzzle.redo());
+        assert_eq!(puzzle.cells(), &filled);
+        assert_eq!(puzzle.transpose, filled.transpose());
+        assert!(!puzzle.redo());
+
+        // A new edit discards anything that was undone
+        puzzle.undo();
+        puzzle.set(2, 2, Cell::Letter('X'));
+        assert!(!puzzle.redo());
+
+        for _ in 0..MAX_HISTORY + 10 {
+            puzzle.set(2, 2, Cell::Empty);
+        }
+        let mut undone = 0;
+        while puzzle.undo() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_HISTORY);
+    }
+
+    #[test]
+    fn undo_moved_marks() {
+        let mut puzzle = Puzzle::new("x".to_string(), 4).with_symmetry(Symmetry::Horizontal);
+        puzzle.circle_cell(3, 3).unwrap();
+        puzzle.lock_cell(3, 0).unwrap();
+        assert_eq!(puzzle.resize(2, Anchor::TopLeft, false), Ok(0));
+        assert!(!puzzle.is_circled(3, 3));
+        assert!(puzzle.undo());
+        assert_eq!(puzzle.size(), 4);
+        assert!(puzzle.is_circled(3, 3));
+        assert!(puzzle.redo());
+        assert!(!puzzle.is_circled(3, 3));
+        assert!(puzzle.undo());
+
+        puzzle.rotate(1).unwrap();
+        assert!(puzzle.is_circled(0, 3));
+        assert_eq!(puzzle.symmetry, Symmetry::Vertical);
+        assert!(puzzle.undo());
+        assert!(puzzle.is_circled(3, 3));
+        assert!(puzzle.locked.contains(&(3, 0)));
+        assert_eq!(puzzle.symmetry, Symmetry::Horizontal);
+
+        // Undoing an edit to a cell leaves marks made since alone
+        puzzle.set(0, 0, Cell::Letter('A'));
+        puzzle.circle_cell(0, 0).unwrap();
+        assert!(puzzle.undo());
+        assert_eq!(puzzle.get(0, 0), &Cell::Empty);
+        assert!(puzzle.is_circled(0, 0));
+    }
+
+    #[test]
+    fn clue_numbers() {
+        let mut puzzle = Puzzle::new("x".to_string(), 5);
+        puzzle.set_symmetric((0, 0), Cell::Black);
+        let numbers = puzzle.clue_numbers();
+        assert_eq!(numbers.len(), 8);
+        assert_eq!(numbers.get(&(1, 0)), Some(&1));
+        assert_eq!(numbers.get(&(3, 0)), Some(&3));
+        assert_eq!(numbers.get(&(0, 1)), Some(&4));
+        assert_eq!(numbers.get(&(4, 1)), Some(&5));
+        assert_eq!(numbers.get(&(0, 2)), Some(&6));
+        assert_eq!(numbers.get(&(0, 4)), None);
+        assert_eq!(numbers.get(&(1, 4)), Some(&8));
+        assert_eq!(numbers.get(&(2, 2)), None);
+    }
+
+    #[test]
+    fn words_with_positions() {
+        let cells = Grid::from_bytes("▩ A T \nS ▢ E \nO N ▩ \n".as_bytes()).unwrap();
+        let puzzle = Puzzle::from_grid("x".to_string(), cells);
+        let words: Vec<(usize, Direction, String, (usize, usize))> = puzzle
+            .words_with_positions()
+            .into_iter()
+            .map(|(number, word)| (number, word.direction, word.as_string(), word.start))
+            .collect();
+        assert_eq!(
+            words,
             vec![
-                Cell::Empty,
-                Cell::Empty,
-                Cell::Empty,
-                Cell::Empty,
-                Cell::Empty,
-            ],
+                (1, Direction::Across, "AT".to_string(), (1, 0)),
+                (3, Direction::Across, "S_E".to_string(), (0, 1)),
+                (4, Direction::Across, "ON".to_string(), (0, 2)),
+                (1, Direction::Down, "A_N".to_string(), (1, 0)),
+                (2, Direction::Down, "TE".to_string(), (2, 0)),
+                (3, Direction::Down, "SO".to_string(), (0, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn renumbering() {
+        let before =
+            Puzzle::from_template("x".to_string(), ".....\n.....\n.....\n.....\n.....").unwrap();
+        assert!(before.renumbering(&before).is_empty());
+
+        let mut after = before.clone();
+        after.set_symmetric((0, 0), Cell::Black);
+        let renumbering = after.renumbering(&before);
+        // Every corner is blacked out, taking the slots along the edges that started in them
+        assert_eq!(
+            renumbering.removed,
             vec![
-                Cell::Empty,
-                Cell::Empty,
-                Cell::Empty,
-                Cell::Empty,
-                Cell::Empty,
-            ],
+                (Direction::Across, 1),
+                (Direction::Across, 9),
+                (Direction::Down, 1),
+                (Direction::Down, 5)
+            ]
+        );
+        assert_eq!(
+            renumbering.moved,
             vec![
-                Cell::Empty,
-                Cell::Empty,
-                Cell::Letter('B'),
-                Cell::Letter('A'),
-                Cell::Empty,
-            ],
-        ]);
-        let puzzle = Puzzle::from_grid("x".to_string(), cells);
-        assert_eq!(puzzle.valid_black_placement((0, 1)), true);
-        assert_eq!(puzzle.valid_black_placement((1, 1)), false);
-        assert_eq!(puzzle.valid_black_placement((2, 2)), false);
-        assert_eq!(puzzle.valid_black_placement((3, 4)), false);
-        assert_eq!(puzzle.valid_black_placement((4, 4)), true);
+                (Direction::Across, 6, 4),
+                (Direction::Across, 7, 6),
+                (Direction::Across, 8, 7),
+                (Direction::Down, 2, 1),
+                (Direction::Down, 3, 2),
+                (Direction::Down, 4, 3),
+            ]
+        );
     }
 
     #[test]
-    fn valid_words() {
-        let cells = Grid(vec![
-            vec![Cell::Letter('S'), Cell::Letter('I'), Cell::Letter('T')],
-            vec![Cell::Letter('A'), Cell::Letter('T'), Cell::Letter('E')],
-            vec![Cell::Letter('P'), Cell::Letter('A'), Cell::Letter('N')],
-        ]);
-        let puzzle = Puzzle::from_grid("x".to_string(), cells);
-        assert_eq!(puzzle.validate_words(), Ok(()));
+    fn json_round_trip() {
+        let mut puzzle = Puzzle::new("json".to_string(), 5).with_symmetry(Symmetry::Horizontal);
+        puzzle.set_symmetric((0, 0), Cell::Black);
+        puzzle.fill_across(5, "WE'RE").unwrap();
+        let json = puzzle.to_json();
+        assert!(json.contains(r#""type": "black""#));
+        assert!(json.contains(r#""letter": "'""#));
+
+        let loaded = Puzzle::from_json(&json).unwrap();
+        assert_eq!(loaded.name(), "json");
+        assert_eq!(loaded.symmetry(), Symmetry::Horizontal);
+        assert_eq!(loaded.cells(), puzzle.cells());
+        assert_eq!(loaded.transpose, puzzle.transpose);
+
+        let ragged = json.replacen(
+            r#"{
+        "type": "empty"
+      },"#,
+            "",
+            1,
+        );
+        assert!(Puzzle::from_json(&ragged).is_err());
+        assert!(Puzzle::from_json("{}").is_err());
     }
 
     #[test]
-    fn words_too_short() {
-        let cells = Grid(vec![
-            vec![Cell::Letter('S'), Cell::Letter('I'), Cell::Letter('T')],
-            vec![Cell::Letter('A'), Cell::Black, Cell::Letter('E')],
-            vec![Cell::Letter('P'), Cell::Letter('U'), Cell::Letter('N')],
-        ]);
+    fn segments() {
+        let cells = Grid::from_bytes("A ▩ B \n▢ ▢ ▢ \n▩ C ▩ \n".as_bytes()).unwrap();
         let puzzle = Puzzle::from_grid("x".to_string(), cells);
+        let across: Vec<((usize, usize), usize)> = puzzle
+            .segments_across()
+            .map(|segment| (segment.start, segment.len))
+            .collect();
+        let down: Vec<((usize, usize), usize)> = puzzle
+            .segments_down()
+            .map(|segment| (segment.start, segment.len))
+            .collect();
         assert_eq!(
-            puzzle.validate_words(),
-            Err(PuzzleError::WordTooShort("A".to_string()))
+            across,
+            vec![((0, 0), 1), ((2, 0), 1), ((0, 1), 3), ((1, 2), 1)]
+        );
+        assert_eq!(down, vec![((0, 0), 2), ((1, 1), 2), ((2, 0), 2)]);
+        assert_eq!(
+            puzzle.no_unkeyed_letters(),
+            Err(PuzzleError::UnkeyedLetter((0, 0)))
+        );
+    }
+
+    #[test]
+    fn isolated_cells() {
+        let puzzle =
+            Puzzle::from_template("x".to_string(), ".....\n..#..\n.#.#.\n..#..\n.....").unwrap();
+        assert_eq!(puzzle.isolated_cells(), vec![(2, 2)]);
+        assert_eq!(
+            puzzle.validate_base(),
+            Err(PuzzleError::IsolatedCell((2, 2)))
+        );
+        assert!(puzzle
+            .base_violations()
+            .contains(&PuzzleError::IsolatedCell((2, 2))));
+
+        let corner = Puzzle::from_template("x".to_string(), ".#.\n#..\n...").unwrap();
+        assert_eq!(corner.isolated_cells(), vec![(0, 0)]);
+        let open = Puzzle::from_template("x".to_string(), "...\n...\n...").unwrap();
+        assert!(open.isolated_cells().is_empty());
+    }
+
+    #[test]
+    fn letter_commonality_order() {
+        assert_eq!(letter_commonality('E'), 1.0);
+        assert_eq!(letter_commonality('e'), 1.0);
+        assert_eq!(letter_commonality('#'), 0.0);
+        // Every letter scores between 0 and 1, in the same order as the frequency table
+        for a in 'A'..='Z' {
+            assert!(letter_commonality(a) > 0.0 && letter_commonality(a) <= 1.0);
+            for b in 'A'..='Z' {
+                let (fa, fb) = (
+                    LETTER_FREQUENCIES[a as usize - 'A' as usize],
+                    LETTER_FREQUENCIES[b as usize - 'A' as usize],
+                );
+                if fa < fb {
+                    assert!(letter_commonality(a) < letter_commonality(b));
+                }
+            }
+        }
+        assert!(letter_commonality('Z') < letter_commonality('Q'));
+        assert!(letter_commonality('T') > letter_commonality('S'));
+    }
+
+    #[test]
+    fn word_score() {
+        let score = |letters: &str| {
+            let cells: Vec<Cell> = letters
+                .chars()
+                .map(|c| match c {
+                    '_' => Cell::Empty,
+                    c => Cell::Letter(c),
+                })
+                .collect();
+            Word {
+                start: (0, 0),
+                direction: Direction::Across,
+                len: cells.len(),
+                cells: &cells,
+            }
+            .score()
+        };
+        // Swapping a letter for a more common one never lowers the score
+        assert!(score("ETA") > score("ETZ"));
+        assert!(score("ETZ") > score("EQZ"));
+        assert!(score("ZZZ") < score("ZZE"));
+        // A rare letter costs a short word more than a long one
+        assert!(score("ZEE") < score("ZEEEE"));
+        assert_eq!(score("EEE"), 1.0);
+        assert_eq!(score("___"), 1.0);
+        // Empty cells don't count
+        assert_eq!(score("E_E"), 1.0);
+    }
+
+    #[test]
+    fn slot_quality() {
+        let puzzle = Puzzle::from_template("x".to_string(), "ZE.\n#.#\n...").unwrap();
+        let across = puzzle.word_through(0, 0, Direction::Across).unwrap();
+        let down = puzzle.word_through(1, 0, Direction::Down).unwrap();
+        // Only the E is crossed, so it counts twice and lifts the slot above its plain score
+        assert!(puzzle.slot_quality(&across) > across.score());
+        assert_eq!(puzzle.slot_quality(&down), 1.0);
+
+        // A rare crossing letter lowers the slot further than the plain score
+        let puzzle = Puzzle::from_template("x".to_string(), "EZ.\n#.#\n...").unwrap();
+        let across = puzzle.word_through(0, 0, Direction::Across).unwrap();
+        assert!(puzzle.slot_quality(&across) < across.score());
+
+        let puzzle = Puzzle::from_template("x".to_string(), "E..\n#.#\n...").unwrap();
+        assert!(
+            puzzle.fill_quality(0, Direction::Across, "EZE").unwrap()
+                < puzzle.fill_quality(0, Direction::Across, "ETE").unwrap()
+        );
+        assert!(puzzle.fill_quality(0, Direction::Across, "CAT").is_err());
+    }
+
+    #[test]
+    fn word_through() {
+        let puzzle = Puzzle::from_template("x".to_string(), "CAT#\n.#..\nDOGS\n#..#").unwrap();
+        let across = puzzle.word_through(1, 0, Direction::Across).unwrap();
+        assert_eq!(
+            (across.start, across.as_string()),
+            ((0, 0), "CAT".to_string())
         );
+        let down = puzzle.word_through(0, 2, Direction::Down).unwrap();
+        assert_eq!((down.start, down.as_string()), ((0, 0), "C_D".to_string()));
+        let down = puzzle.word_through(3, 2, Direction::Down).unwrap();
+        assert_eq!((down.start, down.len), ((3, 1), 2));
+        assert!(puzzle.word_through(3, 0, Direction::Across).is_none());
     }
 
     #[test]
@@ -436,15 +4262,35 @@ mod tests {
 
         let across_words: Vec<String> = puzzle
             .words_across_iter()
-            .map(|x| Cell::as_string(x))
+            .map(|word| word.as_string())
             .collect();
         let down_words: Vec<String> = puzzle
             .words_down_iter()
-            .map(|x| Cell::as_string(x))
+            .map(|word| word.as_string())
             .collect();
 
         assert_eq!(vec!["SIT", "ACE", "PEN"], across_words);
         assert_eq!(vec!["SAP", "ICE", "TEN"], down_words);
+
+        let cells = Grid::from_bytes("▩ A T \nS ▩ E \nO N ▩ \n".as_bytes()).unwrap();
+        let puzzle = Puzzle::from_grid("x".to_string(), cells);
+        let starts: Vec<((usize, usize), Direction, usize)> = puzzle
+            .all_words_iter()
+            .map(|word| (word.start, word.direction, word.len))
+            .collect();
+        assert_eq!(
+            starts,
+            vec![
+                ((1, 0), Direction::Across, 2),
+                ((0, 1), Direction::Across, 1),
+                ((2, 1), Direction::Across, 1),
+                ((0, 2), Direction::Across, 2),
+                ((0, 1), Direction::Down, 2),
+                ((1, 0), Direction::Down, 1),
+                ((1, 2), Direction::Down, 1),
+                ((2, 0), Direction::Down, 2),
+            ]
+        );
     }
 
     #[test]
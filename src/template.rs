@@ -0,0 +1,86 @@
+use std::{fmt, str::FromStr};
+
+use crate::grid::GridError;
+
+/// The top left quarter of the 5x5 mini, including the middle row and column. `#` is a black square and `.` a white
+/// one; the other three quarters are the same pattern turned by 90, 180 and 270 degrees.
+const MINI: &[&str] = &["#..", "...", "..."];
+
+/// The top left quarter of a 15x15 daily grid
+const DAILY: &[&str] = &[
+    ".......#", ".......#", ".......#", "........", "....#...", "...#....", ".....#..", "###.....",
+];
+
+/// The top left quarter of a 21x21 Sunday grid
+const SUNDAY: &[&str] = &[
+    ".......#...",
+    ".......#...",
+    ".......#...",
+    "......#....",
+    ".....#.....",
+    "...#.....#.",
+    "....#......",
+    "###.......#",
+    "........#..",
+    "......#....",
+    ".......#...",
+];
+
+/// Standard grid sizes, each with a fixed black square pattern that has rotational symmetry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+    /// 5x5
+    Mini,
+    /// 15x15
+    Daily,
+    /// 21x21
+    Sunday,
+}
+
+impl Template {
+    /// The width and height of the grid, which is always odd since the quarters share the middle row and column
+    pub fn size(self) -> usize {
+        self.rows().len() * 2 - 1
+    }
+
+    /// The black squares in the top left quarter of the grid, which placing with rotational symmetry fills in the rest
+    pub fn black_squares(self) -> impl Iterator<Item = (usize, usize)> {
+        self.rows().iter().enumerate().flat_map(|(y, row)| {
+            row.char_indices()
+                .filter(|&(_, c)| c == '#')
+                .map(move |(x, _)| (x, y))
+        })
+    }
+
+    fn rows(self) -> &'static [&'static str] {
+        match self {
+            Template::Mini => MINI,
+            Template::Daily => DAILY,
+            Template::Sunday => SUNDAY,
+        }
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Template::Mini => "mini",
+            Template::Daily => "daily",
+            Template::Sunday => "sunday",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Template {
+    type Err = GridError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "mini" => Ok(Template::Mini),
+            "daily" => Ok(Template::Daily),
+            "sunday" => Ok(Template::Sunday),
+            other => Err(GridError::UnknownTemplate(other.to_string())),
+        }
+    }
+}